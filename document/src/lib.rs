@@ -1,2 +1,5 @@
 pub mod merged_logs;
+pub mod merged_log_file;
+mod stamped_line;
 pub use merged_logs::MergedLogs;
+pub use merged_log_file::MergedLogFile;
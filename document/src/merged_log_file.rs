@@ -0,0 +1,327 @@
+// MergedLogFile presents several logs merged by timestamp as one text file, so the merged view can be indexed,
+// filtered and searched like any other LogFile.
+
+// The merge is produced lazily from the front.  Each run of merged bytes remembers which file and offset it came from
+// so we can read it again later without keeping the text in memory.  Like a stream, the merged file grows as we merge
+// more of it, a little on each poll or read, so we never hold up the caller for long.
+
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+
+use indexed_file::files::{LogBase, LogFile, Stream};
+use indexed_file::indexer::{GetLine, Position};
+use indexed_file::{IndexedLog, Log, TimeStamper};
+
+use crate::merged_logs::merge_key;
+use crate::stamped_line::{BlockContext, StampedLine};
+use crate::MergedLogs;
+
+// Longest we merge for in one call when the caller gives us no deadline
+const MERGE_TIME: Duration = Duration::from_millis(20);
+
+// Lines we merge between checks of the clock
+const MERGE_CHECK_LINES: usize = 64;
+
+// Where the next line comes from in each file
+struct FileCursor {
+    pos: Position,
+    offset: usize,
+    stamper: TimeStamper,
+    ctx: BlockContext,
+
+    // The next line and the bytes it takes in the file
+    peek: Option<(StampedLine, usize)>,
+}
+
+impl FileCursor {
+    fn new() -> Self {
+        Self {
+            pos: Position::from(0),
+            offset: 0,
+            stamper: TimeStamper::default(),
+            ctx: BlockContext::default(),
+            peek: None,
+        }
+    }
+}
+
+// A span of the merged output copied from contiguous bytes in one file
+struct Run {
+    start: usize,       // offset in the merged output
+    index: usize,       // file index
+    offset: usize,      // offset in the file
+    len: usize,         // bytes in the merged output
+    eol: bool,          // last byte is a newline we added because the file did not end with one
+}
+
+impl Run {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+pub struct MergedLogFile {
+    logs: Vec<Log>,
+    cursors: Vec<FileCursor>,
+    runs: Vec<Run>,
+
+    // Bytes of merged output produced so far
+    generated: usize,
+
+    // True when every line from every file is merged
+    done: bool,
+
+    pos: usize,
+    buffer: Vec<u8>,
+    buffer_start: usize,
+}
+
+impl From<MergedLogs> for MergedLogFile {
+    fn from(logs: MergedLogs) -> Self {
+        Self::new(logs.files)
+    }
+}
+
+impl MergedLogFile {
+    pub fn new(logs: Vec<Log>) -> Self {
+        let cursors = logs.iter().map(|_| FileCursor::new()).collect();
+        let mut file = Self {
+            logs,
+            cursors,
+            runs: Vec::new(),
+            generated: 0,
+            done: false,
+            pos: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+        };
+        file.poll(None);
+        file
+    }
+
+    // Read the next line from file `index` into its cursor, if we don't already have one
+    fn peek(&mut self, index: usize) {
+        let cursor = &mut self.cursors[index];
+        if cursor.peek.is_none() {
+            let log = &mut self.logs[index];
+            match log.next(&cursor.pos) {
+                GetLine::Hit(pos, line) => {
+                    // Invalid utf-8 is replaced in the line, so take its length from the file
+                    let len = pos.region().len();
+                    cursor.offset = line.offset + len;
+                    cursor.pos = log.advance(&pos);
+                    let time = cursor.stamper.time(&line.line);
+                    let stamp = cursor.ctx.stamp(time);
+                    cursor.peek = Some((StampedLine { stamp, line }, len));
+                },
+                // Look again from the same offset after the file grows
+                _ => cursor.pos = Position::from(cursor.offset),
+            }
+        }
+    }
+
+    // Merge one more line into the output.  Returns false when there are no more lines.
+    fn generate(&mut self) -> bool {
+        for index in 0..self.cursors.len() {
+            self.peek(index);
+        }
+
+        let next = self.cursors.iter()
+            .enumerate()
+            .filter_map(|(i, cursor)| cursor.peek.as_ref().map(|(line, _)| (i, line)))
+            .min_by_key(|(i, line)| merge_key(*i, line))
+            .map(|(i, _)| i);
+
+        let Some(index) = next else {
+            self.done = !self.logs.iter().any(|log| log.is_open());
+            return false;
+        };
+
+        let (line, len) = self.cursors[index].peek.take().unwrap();
+        let line = line.line;
+        let eol = !line.line.ends_with('\n');
+        let len = len + eol as usize;
+
+        match self.runs.last_mut() {
+            Some(run) if run.index == index && !run.eol && run.offset + run.len == line.offset => {
+                run.len += len;
+                run.eol = eol;
+            },
+            _ => self.runs.push(Run { start: self.generated, index, offset: line.offset, len, eol }),
+        }
+        self.generated += len;
+        true
+    }
+
+    // Merge lines until the output reaches past pos, we run out of lines, or we pass the deadline
+    fn merge(&mut self, pos: usize, deadline: Instant) {
+        let mut lines = 0;
+        while self.generated <= pos && self.generate() {
+            lines += 1;
+            if lines % MERGE_CHECK_LINES == 0 && Instant::now() >= deadline {
+                break;
+            }
+        }
+    }
+
+    // Fill our buffer with merged bytes starting at pos.  It stays empty if we have not merged that far yet.
+    fn load(&mut self, pos: usize) {
+        self.buffer.clear();
+        self.buffer_start = pos;
+
+        self.merge(pos, Instant::now() + MERGE_TIME);
+
+        let ndx = self.runs.partition_point(|run| run.end() <= pos);
+        if let Some(run) = self.runs.get(ndx) {
+            let skip = pos - run.start;
+            let data_len = run.len - run.eol as usize;
+            if skip < data_len
+                && let Some(bytes) = self.logs[run.index].read_bytes(run.offset + skip) {
+                let take = bytes.len().min(data_len - skip);
+                self.buffer.extend_from_slice(&bytes[..take]);
+            }
+            if run.eol && skip + self.buffer.len() == data_len {
+                self.buffer.push(b'\n');
+            }
+        }
+    }
+}
+
+impl Stream for MergedLogFile {
+    fn len(&self) -> usize {
+        self.generated
+    }
+
+    fn is_open(&self) -> bool {
+        !self.done
+    }
+
+    fn truncations(&self) -> usize {
+        self.logs.iter().map(|log| log.truncations()).sum()
+    }
+
+    fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize {
+        let truncations = self.truncations();
+        for log in self.logs.iter_mut() {
+            log.poll(timeout);
+        }
+        if self.truncations() != truncations {
+            // Lines we merged from a truncated file are gone, so merge everything again
+            self.cursors.iter_mut().for_each(|cursor| *cursor = FileCursor::new());
+            self.runs.clear();
+            self.generated = 0;
+            self.done = false;
+            self.buffer.clear();
+        }
+        self.merge(usize::MAX, timeout.unwrap_or_else(|| Instant::now() + MERGE_TIME));
+        self.len()
+    }
+}
+impl BufRead for MergedLogFile {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let buffered = self.buffer_start..self.buffer_start + self.buffer.len();
+        if !buffered.contains(&self.pos) {
+            self.load(self.pos);
+        }
+        let start = self.pos - self.buffer_start;
+        Ok(&self.buffer[start..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+impl Read for MergedLogFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.fill_buf()?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl Seek for MergedLogFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::End(delta) => self.len() as i64 + delta,
+            SeekFrom::Current(delta) => self.pos as i64 + delta,
+        };
+        if pos < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Seek before start of file"));
+        }
+        self.pos = pos as usize;
+        Ok(self.pos as u64)
+    }
+}
+
+impl LogFile for MergedLogFile {}
+impl LogBase for MergedLogFile {}
+
+#[cfg(test)]
+mod merged_log_file_tests {
+    use std::io::Read;
+
+    use indexed_file::{files::{CursorLogFile, CursorUtil, LogBase, Stream}, IndexedLog, Log};
+    use super::MergedLogFile;
+
+    fn log(lines: Vec<&str>) -> Log {
+        Log::from(CursorLogFile::from_vec(lines).unwrap().to_src())
+    }
+
+    #[test]
+    fn test_merged_file_read() {
+        let first = log(vec!["Apr  7 00:00:01.000 one", "  at one", "Apr  7 00:00:03.000 three"]);
+        let second = log(vec!["Apr  7 00:00:02.000 two"]);
+        let mut file = MergedLogFile::new(vec![first, second]);
+
+        let mut text = String::new();
+        file.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "Apr  7 00:00:01.000 one\n  at one\nApr  7 00:00:02.000 two\nApr  7 00:00:03.000 three\n");
+        assert_eq!(file.len(), text.len());
+    }
+
+    #[test]
+    fn test_merged_file_missing_newline() {
+        let first = Log::from(std::io::Cursor::new(b"Apr  7 00:00:03.000 three".to_vec()).to_src());
+        let second = log(vec!["Apr  7 00:00:02.000 two"]);
+        let mut file = MergedLogFile::new(vec![first, second]);
+        let len = file.len();
+
+        let mut text = String::new();
+        file.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "Apr  7 00:00:02.000 two\nApr  7 00:00:03.000 three\n");
+        assert_eq!(len, text.len());
+    }
+
+    #[test]
+    fn test_merged_file_invalid_utf8() {
+        // Invalid utf-8 is replaced when we read lines, but the merged file still holds the bytes from the file
+        let first = Log::from(std::io::Cursor::new(b"Apr  7 00:00:01.000 bad \xff\xfe\nApr  7 00:00:03.000 three\n".to_vec()).to_src());
+        let second = log(vec!["Apr  7 00:00:02.000 two"]);
+        let mut file = MergedLogFile::new(vec![first, second]);
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, b"Apr  7 00:00:01.000 bad \xff\xfe\nApr  7 00:00:02.000 two\nApr  7 00:00:03.000 three\n");
+        assert_eq!(file.len(), bytes.len());
+    }
+
+    #[test]
+    fn test_merged_file_log() {
+        // Index the merged output like any other file, reading from the end first
+        let first = log(vec!["Apr  7 00:00:01.000 one", "Apr  7 00:00:03.000 three"]);
+        let second = log(vec!["Apr  7 00:00:02.000 two", "Apr  7 00:00:04.000 four"]);
+        let mut merged = Log::from(MergedLogFile::new(vec![first, second]));
+
+        let lines = merged.iter_lines().rev().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, [
+            "Apr  7 00:00:04.000 four\n",
+            "Apr  7 00:00:03.000 three\n",
+            "Apr  7 00:00:02.000 two\n",
+            "Apr  7 00:00:01.000 one\n",
+        ]);
+    }
+}
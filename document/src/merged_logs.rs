@@ -1,10 +1,16 @@
 // A collection of log lines from multiple log files, blended together by sorting.
 
-// FIXME: Right now they're sorted by line contents, but they should be sorted by timestamp in the future.
+// Lines are sorted by (timestamp, file index, file offset).  See stamped_line.rs for how lines without timestamps are
+// kept in "blocks" with the timestamped line before them.
+
+use std::collections::VecDeque;
 
 use indexed_file::IndexedLog;
 use indexed_file::Log;
 use indexed_file::LogLine;
+use indexed_file::TimeStamper;
+
+use crate::stamped_line::{BlockContext, Stamp, StampedLine, BLOCK_LINES};
 
 #[cfg(test)]
 use indexed_file::files::LogBase;
@@ -35,15 +41,30 @@ use indexed_file::indexer::sane_indexer::SaneIndexer;
 // A long-lived collection of Logs
 #[derive(Default)]
 pub struct MergedLogs {
-    files: Vec<Log>
+    pub(crate) files: Vec<Log>
 }
 
 type Iter<'a> = Box<dyn DoubleEndedIterator<Item = LogLine> + 'a>;
 
+// Ordering key for a line from file `index`
+pub(crate) fn merge_key(index: usize, line: &StampedLine) -> (Stamp, usize, usize) {
+    (line.stamp, index, line.line.offset)
+}
+
 struct LogIter<'a> {
-    next: Option<LogLine>,
-    prev: Option<LogLine>,
+    next: Option<StampedLine>,
+    prev: Option<StampedLine>,
     iter: Iter<'a>,
+    stamper: TimeStamper,
+
+    // Block state of the lines read from the front
+    fwd: BlockContext,
+
+    // Lines read from the back whose stamps still depend on lines before them, in file order
+    behind: VecDeque<(Stamp, LogLine)>,
+
+    // Lines read from the back which are stamped and ready to go, in file order
+    ready: Vec<(Stamp, StampedLine)>,
 }
 
 impl<'a> LogIter<'a> {
@@ -52,77 +73,111 @@ impl<'a> LogIter<'a> {
             iter: Box::new(log.iter_lines()),
             next: None,
             prev: None,
+            stamper: TimeStamper::default(),
+            fwd: BlockContext::default(),
+            behind: VecDeque::new(),
+            ready: Vec::new(),
         }
     }
 
-    // Return ref to next string unless EOF, else prev string
+    // Return ref to next line unless EOF, else prev line
     // Assumes that prev and next are approaching each other in this DoubleEndedIterator
-    fn peek_next(&mut self) -> &Option<LogLine> {
-        if self.next.is_some() || self.advance() {
-            &self.next
+    fn peek_next(&mut self) -> Option<&StampedLine> {
+        if self.next.is_none() {
+            self.next = self.read_next();
+        }
+        if self.next.is_some() {
+            self.next.as_ref()
         } else {
-            &self.prev
+            self.prev.as_ref()
         }
     }
 
-    // Return ref to prev string unless EOF, else next string
+    // Return ref to prev line unless EOF, else next line
     // Assumes that prev and next are approaching each other in this DoubleEndedIterator
-    fn peek_prev(&mut self) -> &Option<LogLine> {
-        if self.prev.is_some() || self.advance_back() {
-            &self.prev
+    fn peek_prev(&mut self) -> Option<&StampedLine> {
+        if self.prev.is_none() {
+            self.prev = self.read_prev();
+        }
+        if self.prev.is_some() {
+            self.prev.as_ref()
         } else {
-            &self.next
+            self.next.as_ref()
         }
     }
 
-    fn advance(&mut self) -> bool {
-        // FIXME: Return offset to construct a Cursor with
-        // Pre-load the next line for peek
-        self.next = self.iter.next();
-        self.next.is_some()
+    // Return next line unless EOF, else prev line
+    fn take_next(&mut self) -> Option<StampedLine> {
+        if self.next.is_none() {
+            self.next = self.read_next();
+        }
+        self.next.take().or_else(|| self.prev.take())
     }
 
-    fn advance_back(&mut self) -> bool {
-        // FIXME: Return offset to construct a Cursor with
-        // Pre-load the prev line for peek
-        self.prev = self.iter.next_back();
-        self.prev.is_some()
+    // Return prev line unless EOF, else next line
+    fn take_prev(&mut self) -> Option<StampedLine> {
+        if self.prev.is_none() {
+            self.prev = self.read_prev();
+        }
+        self.prev.take().or_else(|| self.next.take())
     }
 
-    // Return next string unless EOF, else prev string
-    // Assumes that prev and next are approaching each other in this DoubleEndedIterator
-    fn take_next(&mut self) -> Option<LogLine> {
-        if self.next.is_none() {
-            // No next line to peek.  Maybe we're not initialized.
-            self.advance();
-        }
-        if let Some(next) = self.next.take() {
-            self.advance();
-            Some(next)
-        } else if self.prev.is_some() {
-            self.take_prev()
+    // Read and stamp the next line from the front.  When the front meets the back, take the lines the back has
+    // already read.
+    fn read_next(&mut self) -> Option<StampedLine> {
+        let (time, line) = if let Some(line) = self.iter.next() {
+            (self.stamper.time(&line.line), line)
+        } else if let Some(behind) = self.behind.pop_front() {
+            behind
+        } else if !self.ready.is_empty() {
+            let (time, ready) = self.ready.remove(0);
+            (time, ready.line)
         } else {
-            None
+            return None;
+        };
+        let stamp = self.fwd.stamp(time);
+        Some(StampedLine { stamp, line })
+    }
+
+    // Read the previous stamped line from the back
+    fn read_prev(&mut self) -> Option<StampedLine> {
+        if self.ready.is_empty() {
+            self.fill_back();
         }
+        self.ready.pop().map(|(_, line)| line)
     }
 
-    // Return prev string unless EOF, else next string
-    // Assumes that prev and next are approaching each other in this DoubleEndedIterator
-    fn take_prev(&mut self) -> Option<LogLine> {
-        if self.prev.is_none() {
-            // No next line to peek.  Maybe we're not initialized.
-            self.advance_back();
+    // Read a batch of lines from the back and stamp the ones we can.  A line's stamp depends on at most
+    // 2 * BLOCK_LINES lines before it, so we keep that many unstamped lines to replay on the next batch.
+    fn fill_back(&mut self) {
+        let keep = 2 * BLOCK_LINES;
+        let mut exhausted = false;
+        while self.behind.len() < 2 * keep {
+            if let Some(line) = self.iter.next_back() {
+                let time = self.stamper.time(&line.line);
+                self.behind.push_front((time, line));
+            } else {
+                exhausted = true;
+                break;
+            }
         }
-        if let Some(prev) = self.prev.take() {
-            self.advance_back();
-            Some(prev)
-        } else if self.next.is_some() {
-            self.take_next()
+
+        // If we reached the lines read from the front, continue from their context.  Otherwise replay enough
+        // lines to rebuild the context.
+        let (mut ctx, keep) = if exhausted {
+            (self.fwd.clone(), 0)
         } else {
-            None
+            (BlockContext::default(), keep)
+        };
+
+        for (i, (time, line)) in self.behind.iter().enumerate() {
+            let stamp = ctx.stamp(*time);
+            if i >= keep {
+                self.ready.push((*time, StampedLine { stamp, line: line.clone() }));
+            }
         }
+        self.behind.truncate(keep);
     }
-
 }
 
 // A semi-sorted iterator over MergedLogs
@@ -151,10 +206,10 @@ impl Iterator for MergedLogsIterator<'_> {
             .iter_mut()
             .map(|iter| iter.peek_next())
             .enumerate()
-            .filter(|(_, v)| v.is_some())
-            .min_by(|(_, line0), (_, line1)| line0.cmp(line1)) {
+            .filter_map(|(i, v)| v.map(|line| (i, line)))
+            .min_by_key(|(i, line)| merge_key(*i, line)) {
                 // We found a minimum line
-                self.iters[i].take_next()
+                self.iters[i].take_next().map(|stamped| stamped.line)
         } else {
             // We ran out of lines
             None
@@ -170,10 +225,10 @@ impl DoubleEndedIterator for MergedLogsIterator<'_> {
             .iter_mut()
             .map(|iter| iter.peek_prev())
             .enumerate()
-            .filter(|(_, v)| v.is_some())
-            .max_by(|(_, line0), (_, line1)| line0.cmp(line1)) {
+            .filter_map(|(i, v)| v.map(|line| (i, line)))
+            .max_by_key(|(i, line)| merge_key(*i, line)) {
                 // We found a maximum line
-                self.iters[i].take_prev()
+                self.iters[i].take_prev().map(|stamped| stamped.line)
         } else {
             // We ran out of lines
             None
@@ -213,7 +268,7 @@ impl MergedLogs {
 #[cfg(test)]
 mod merged_logs_iterator_tests {
 
-    use indexed_file::{files::{CachedStreamReader, CursorLogFile, CursorUtil, LogBase}, indexer::sane_indexer::SaneIndexer, IndexedLog, Log};
+    use indexed_file::{files::{CachedStreamReader, CursorLogFile, CursorUtil, LogBase}, indexer::sane_indexer::SaneIndexer, IndexedLog, Log, LogLine};
     use super::MergedLogs;
 
     #[test]
@@ -231,31 +286,31 @@ mod merged_logs_iterator_tests {
         assert_eq!(doc.iter_lines().count(), lines);
     }
 
+    // Make a log line stamped with the given seconds after midnight
+    fn stamped(secs: usize, text: &str) -> String {
+        format!("Apr  7 {:02}:{:02}:{:02}.000 {}", secs / 3600, secs / 60 % 60, secs % 60, text)
+    }
+
+    // The text at the end of each merged line
+    fn events(lines: impl Iterator<Item = LogLine>) -> Vec<String> {
+        lines.map(|line| line.line.trim_end().rsplit(' ').next().unwrap().to_string()).collect()
+    }
+
     #[test]
     fn test_doc_merge() {
         let lines = 10;
         let mut doc = MergedLogs::new();
 
-        let odds = (0..lines/2).map(|x| x * 2 + 1).collect();
+        let odds = (0..lines/2).map(|x| x * 2 + 1).map(|x| stamped(x, &x.to_string())).collect();
         let odds = CursorLogFile::from_vec(odds).unwrap();
         doc.push_logbase(odds);
 
-        let evens = (0..lines/2).map(|x| x * 2).collect();
+        let evens = (0..lines/2).map(|x| x * 2).map(|x| stamped(x, &x.to_string())).collect();
         let evens = CursorLogFile::from_vec(evens).unwrap();
         doc.push_logbase(evens);
 
-        let mut it = doc.iter_lines();
-        let mut prev = it.next().unwrap();
-
-        print!(">>> {prev}");
-        for line in it {
-            print!(">>> {prev} {line}");
-            assert!(prev <= line);
-            prev = line;
-        }
-        println!(); // flush
-
-        assert_eq!(doc.iter_lines().count(), lines);
+        let expected = (0..lines).map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(events(doc.iter_lines()), expected);
     }
 
     #[test]
@@ -263,26 +318,91 @@ mod merged_logs_iterator_tests {
         let lines = 10;
         let mut doc = MergedLogs::new();
 
-        let odds = (0..lines/2).map(|x| x * 2 + 1).collect();
+        let odds = (0..lines/2).map(|x| x * 2 + 1).map(|x| stamped(x, &x.to_string())).collect();
         let odds = CursorLogFile::from_vec(odds).unwrap();
         doc.push_logbase(odds);
 
-        let evens = (0..lines/2).map(|x| x * 2).collect();
+        let evens = (0..lines/2).map(|x| x * 2).map(|x| stamped(x, &x.to_string())).collect();
         let evens = CursorLogFile::from_vec(evens).unwrap();
         doc.push_logbase(evens);
 
-        let mut it = doc.iter_lines().rev();
-        let mut prev = it.next().unwrap();
+        let expected = (0..lines).rev().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(events(doc.iter_lines().rev()), expected);
+    }
 
-        print!(">>> {prev}");
-        for line in it {
-            print!(">>> {prev} {line}");
-            assert!(prev >= line);
-            prev = line;
+    #[test]
+    fn test_doc_merge_by_time() {
+        // Sorting by line contents would put Feb before Jan
+        let mut doc = MergedLogs::new();
+        let feb = vec!["Feb  1 00:00:00.000 feb1", "Feb  2 00:00:00.000 feb2"];
+        doc.push_logbase(CursorLogFile::from_vec(feb).unwrap());
+        let jan = vec!["Jan 30 00:00:00.000 jan30", "Jan 31 00:00:00.000 jan31"];
+        doc.push_logbase(CursorLogFile::from_vec(jan).unwrap());
+
+        assert_eq!(events(doc.iter_lines()), ["jan30", "jan31", "feb1", "feb2"]);
+        assert_eq!(events(doc.iter_lines().rev()), ["feb2", "feb1", "jan31", "jan30"]);
+    }
+
+    #[test]
+    fn test_doc_merge_untimestamped() {
+        // Without timestamps the files are shown one after the other
+        let mut doc = MergedLogs::new();
+        doc.push_logbase(CursorLogFile::from_vec(vec!["b", "d"]).unwrap());
+        doc.push_logbase(CursorLogFile::from_vec(vec!["a", "c"]).unwrap());
+
+        assert_eq!(events(doc.iter_lines()), ["b", "d", "a", "c"]);
+        assert_eq!(events(doc.iter_lines().rev()), ["c", "a", "d", "b"]);
+    }
+
+    #[test]
+    fn test_doc_merge_blocks() {
+        // Continuation lines stay with the timestamped line before them
+        let mut doc = MergedLogs::new();
+        let first = vec![stamped(1, "one"), "  at one".to_string(), "  and one".to_string(), stamped(3, "three")];
+        doc.push_logbase(CursorLogFile::from_vec(first).unwrap());
+        let second = vec![stamped(2, "two"), "  at two".to_string()];
+        doc.push_logbase(CursorLogFile::from_vec(second).unwrap());
+
+        let expected = ["one", "one", "one", "two", "two", "three"];
+        assert_eq!(events(doc.iter_lines()), expected);
+        assert_eq!(events(doc.iter_lines().rev()), expected.iter().rev().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_doc_merge_out_of_order() {
+        // A line older than the one before it stays in its predecessor's block
+        let mut doc = MergedLogs::new();
+        let first = vec![stamped(10, "ten"), stamped(5, "five"), "  at five".to_string()];
+        doc.push_logbase(CursorLogFile::from_vec(first).unwrap());
+        let second = vec![stamped(7, "seven")];
+        doc.push_logbase(CursorLogFile::from_vec(second).unwrap());
+
+        let expected = ["seven", "ten", "five", "five"];
+        assert_eq!(events(doc.iter_lines()), expected);
+        assert_eq!(events(doc.iter_lines().rev()), expected.iter().rev().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_doc_merge_long_reverse() {
+        // Enough lines to need several batches when reading backwards
+        let lines = 5000;
+        let mut doc = MergedLogs::new();
+        for file in 0..2 {
+            let data = (0..lines).map(|x| {
+                if x % 7 == 0 {
+                    format!("  more {file} {x}")
+                } else {
+                    stamped(x * 2 + file, &format!("{file}-{x}"))
+                }
+            }).collect();
+            doc.push_logbase(CursorLogFile::from_vec(data).unwrap());
         }
-        println!(); // flush
 
-        assert_eq!(doc.iter_lines().rev().count(), lines);
+        let fwd = doc.iter_lines().collect::<Vec<_>>();
+        let mut rev = doc.iter_lines().rev().collect::<Vec<_>>();
+        rev.reverse();
+        assert_eq!(fwd.len(), lines * 2);
+        assert_eq!(fwd, rev);
     }
 
     #[test]
@@ -0,0 +1,100 @@
+// Log lines tagged with the timestamp used to order them in a merged view.
+
+use chrono::NaiveDateTime;
+use indexed_file::LogLine;
+
+/*  Block semantics (see TimeStamper in Design.md):
+    Logs are mostly sorted by time, but some lines carry no timestamp (stack traces, multi-line messages) and some
+    timestamps are a little older than the line before them.  These lines are treated as part of the "block" started
+    by the timestamped line before them so the whole block is presented together in time.
+
+    1. A timestamped line (a block head) is stamped with the later of its own time and the time of the timestamped
+       line before it.
+    2. A line without a timestamp is stamped with the stamp of the block head before it.
+
+    We only look back BLOCK_LINES lines to find the previous timestamped line.  This bounds the work needed to stamp
+    a line when we read a file backwards, and it gives the same result no matter which direction we read from.
+ */
+
+/// Maximum number of lines to look back for the timestamped line that starts a block
+pub(crate) const BLOCK_LINES: usize = 1000;
+
+pub(crate) type Stamp = Option<NaiveDateTime>;
+
+#[derive(Debug, Clone)]
+pub(crate) struct StampedLine {
+    pub stamp: Stamp,
+    pub line: LogLine,
+}
+
+/// Block state carried from one line to the next while reading a file forwards
+#[derive(Default, Clone)]
+pub(crate) struct BlockContext {
+    // Raw and block time of the last timestamped line
+    head: Option<(NaiveDateTime, NaiveDateTime)>,
+
+    // Lines read since the head
+    since: usize,
+}
+
+impl BlockContext {
+    /// Stamp the next line given its own parsed timestamp, if any
+    pub(crate) fn stamp(&mut self, time: Stamp) -> Stamp {
+        let prev = self.head.filter(|_| self.since < BLOCK_LINES);
+        match time {
+            Some(time) => {
+                let stamp = prev.map_or(time, |(prev, _)| prev.max(time));
+                self.head = Some((time, stamp));
+                self.since = 0;
+                Some(stamp)
+            },
+            None => {
+                self.since += 1;
+                prev.map(|(_, stamp)| stamp)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod block_context_tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+    use super::{BlockContext, BLOCK_LINES};
+
+    fn at(sec: u32) -> Option<NaiveDateTime> {
+        NaiveDate::from_ymd_opt(2024, 4, 7).unwrap().and_hms_opt(22, 21, sec)
+    }
+
+    #[test]
+    fn test_continuation_lines() {
+        let mut ctx = BlockContext::default();
+        assert_eq!(ctx.stamp(None), None);
+        assert_eq!(ctx.stamp(at(5)), at(5));
+        assert_eq!(ctx.stamp(None), at(5));
+        assert_eq!(ctx.stamp(None), at(5));
+        assert_eq!(ctx.stamp(at(6)), at(6));
+    }
+
+    #[test]
+    fn test_out_of_order_lines() {
+        let mut ctx = BlockContext::default();
+        assert_eq!(ctx.stamp(at(10)), at(10));
+        // Older than its predecessor: joins the previous block
+        assert_eq!(ctx.stamp(at(5)), at(10));
+        // Its continuation lines stay with it
+        assert_eq!(ctx.stamp(None), at(10));
+        assert_eq!(ctx.stamp(at(11)), at(11));
+    }
+
+    #[test]
+    fn test_block_limit() {
+        let mut ctx = BlockContext::default();
+        assert_eq!(ctx.stamp(at(10)), at(10));
+        for _ in 0..BLOCK_LINES {
+            assert_eq!(ctx.stamp(None), at(10));
+        }
+        // Too far from the head to be part of the block
+        assert_eq!(ctx.stamp(None), None);
+        assert_eq!(ctx.stamp(at(5)), at(5));
+    }
+}
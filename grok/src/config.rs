@@ -1,4 +1,6 @@
 use std::path::PathBuf;
+use chrono::NaiveDate;
+use indexed_file::time_stamper::parse_user_time;
 use itertools::Itertools;
 
 pub enum ConfigItem {
@@ -8,8 +10,13 @@ pub enum ConfigItem {
     Color(bool),
     Visual(bool),
     MouseScroll(u16),
-    // HideBefore(DateTime),
-    // HideAfter(DateTime),
+    Merge(bool),
+    TimeFormats(PathBuf),
+    HideBefore(String),
+    HideAfter(String),
+    Delta(bool),
+    LineNumbers(bool),
+    Buffers(usize),
     // Search(String),
     // FilterIn(String),
     // FilterOut(String),
@@ -29,12 +36,20 @@ pub struct Config {
     pub color: bool,
     pub mouse: bool,
     pub mouse_scroll: u16,      // Number of lines to scroll with mouse-wheel
+    pub merge: bool,            // Merge all files into one view ordered by timestamp
+    pub time_formats: Option<PathBuf>,  // File of user-defined timestamp formats
+    pub hide_before: Option<String>,    // Hide lines earlier than this time
+    pub hide_after: Option<String>,     // Hide lines at or after this time
+    pub delta: bool,            // Show the time elapsed between displayed lines
+    pub line_numbers: bool,     // Show the number of each line in the file
+    pub buffers: Option<usize>, // KB of piped input to keep in memory before spilling it to a temp file
 }
 
 #[derive(Debug)]
 pub enum Error {
     FileNotFound(String),
     ExpectedInt(String),
+    ExpectedTime(String),
     ExpectedArgumentFor(String),
     UnknownArgument(String),
     UnknownSwitch(String),
@@ -53,6 +68,13 @@ OPTIONS:
   -S --chop-long-lines  Chop long lines instead of wrapping
   -X                    Skip terminal config/cleanup such as using the alternate screen
   -C --color            Use color highlighting of parsed lines
+  --merge               Merge all input files into one view ordered by timestamp
+  --time-formats FILE   Read extra timestamp formats from FILE
+  --hide-before TIME    Hide lines earlier than TIME, e.g. \"2024-05-01 13:45\", or \"13:45\" on the day each file begins
+  --hide-after TIME     Hide lines at or after TIME
+  --delta               Show the time elapsed since the previous line in a column
+  -N --LINE-NUMBERS     Show line numbers in a column
+  -b --buffers N        Keep up to N KB of piped input in memory and spill the rest to a temp file
   -V --version          Display version information
 
 ARGS:
@@ -60,7 +82,7 @@ ARGS:
 ";
 
 impl Config {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Config {
             filename: Vec::new(),
             chop: false,
@@ -68,6 +90,13 @@ impl Config {
             color: false,
             mouse: false,
             mouse_scroll: 5,
+            merge: false,
+            time_formats: None,
+            hide_before: None,
+            hide_after: None,
+            delta: false,
+            line_numbers: false,
+            buffers: None,
         }
     }
 
@@ -87,6 +116,13 @@ impl Config {
             ConfigItem::Color(color) => self.color = color,
             ConfigItem::Visual(visual) => self.mouse = visual,
             ConfigItem::MouseScroll(scroll) => self.mouse_scroll = scroll,
+            ConfigItem::Merge(merge) => self.merge = merge,
+            ConfigItem::TimeFormats(path) => self.time_formats = Some(path),
+            ConfigItem::HideBefore(time) => self.hide_before = Some(time),
+            ConfigItem::HideAfter(time) => self.hide_after = Some(time),
+            ConfigItem::Delta(delta) => self.delta = delta,
+            ConfigItem::LineNumbers(numbers) => self.line_numbers = numbers,
+            ConfigItem::Buffers(kb) => self.buffers = Some(kb),
             ConfigItem::Version | ConfigItem::Help => {},
         }
     }
@@ -107,6 +143,9 @@ impl Config {
             "-X" | "--no-alternate-screen" => ConfigItem::AltScreen(!self.altscreen),
             "-C" | "--color" => ConfigItem::Color(!self.color),
             "-M" | "--mouse" => ConfigItem::Visual(!self.mouse),
+            "--merge" => ConfigItem::Merge(!self.merge),
+            "--delta" => ConfigItem::Delta(!self.delta),
+            "-N" | "--LINE-NUMBERS" | "--line-numbers" => ConfigItem::LineNumbers(!self.line_numbers),
             "-H" | "--help" => ConfigItem::Help,
             "-V" | "--version" => ConfigItem::Version,
             "-W" | "--wheel-lines" => {
@@ -121,6 +160,43 @@ impl Config {
                     return Err(Error::ExpectedArgumentFor(item.to_string()));
                 }
             },
+            "-b" | "--buffers" => {
+                if let Some(arg) = arg {
+                    if let Ok(num) = arg.parse::<usize>() {
+                        consumed = used;
+                        ConfigItem::Buffers(num)
+                    } else {
+                        return Err(Error::ExpectedInt(arg.to_string()));
+                    }
+                } else {
+                    return Err(Error::ExpectedArgumentFor(item.to_string()));
+                }
+            },
+            "--time-formats" => {
+                if let Some(arg) = arg.filter(|arg| !arg.is_empty()) {
+                    consumed = used;
+                    ConfigItem::TimeFormats(PathBuf::from(arg))
+                } else {
+                    return Err(Error::ExpectedArgumentFor(item.to_string()));
+                }
+            },
+            "--hide-before" | "--hide-after" => {
+                if let Some(arg) = arg.filter(|arg| !arg.is_empty()) {
+                    // Each log resolves the time in its own zone, and a time of day on its own date, when the files
+                    // are open.  Here we only check the time is valid, the same way the goto prompt does.
+                    if parse_user_time(arg, Some(NaiveDate::default()), None).is_none() {
+                        return Err(Error::ExpectedTime(arg.to_string()));
+                    }
+                    consumed = used;
+                    if item == "--hide-before" {
+                        ConfigItem::HideBefore(arg.to_string())
+                    } else {
+                        ConfigItem::HideAfter(arg.to_string())
+                    }
+                } else {
+                    return Err(Error::ExpectedArgumentFor(item.to_string()));
+                }
+            },
             _ => return Err(Error::UnknownSwitch(item.to_string())),
         };
         Ok((cfg, consumed))
@@ -174,3 +250,17 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hide_times() {
+        let mut cfg = Config::new();
+        for time in ["2024-05-01 13:45", "2024-05-01T13:45:30+02:00", "13:45", "13:45:30.250"] {
+            assert!(cfg.parse_switch("--hide-before", Some(time)).is_ok(), "Testing time: {}", time);
+        }
+        assert!(matches!(cfg.parse_switch("--hide-after", Some("soon")), Err(Error::ExpectedTime(_))));
+    }
+}
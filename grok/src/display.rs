@@ -1,6 +1,8 @@
+use chrono::TimeDelta;
+use crossterm::style::Stylize;
 use crossterm::terminal::ClearType;
-use indexed_file::LogLine;
-use std::{cmp, io::{self, stdout, Write}};
+use indexed_file::{indexer::indexed_log::LineNumber, LogLine};
+use std::{cmp, io::{self, stdout, Write}, path::PathBuf};
 use crossterm::{cursor, execute, queue, terminal};
 
 use crate::{config::Config, styled_text::LineViewMode};
@@ -14,6 +16,7 @@ struct DisplayState {
     height: usize,
     width: usize,
     pan: usize,
+    number_width: usize,
 }
 
 struct ScreenBuffer {
@@ -62,6 +65,36 @@ enum ScrollAction {
     Repaint,
     GotoPercent(f64),
     GotoOffset(usize),
+    GotoLine(usize),
+    GotoMark(char),
+    NextMark(bool),
+    SwitchFile(FileTarget),
+}
+
+impl ScrollAction {
+    // Moves to somewhere else in the file that '' should bring us back from
+    fn is_jump(&self) -> bool {
+        matches!(self, ScrollAction::StartOfFile(_) | ScrollAction::EndOfFile(_) | ScrollAction::Search(..)
+            | ScrollAction::GotoPercent(_) | ScrollAction::GotoOffset(_) | ScrollAction::GotoLine(_)
+            | ScrollAction::GotoMark(_) | ScrollAction::NextMark(_) | ScrollAction::SwitchFile(_))
+    }
+}
+
+/// Which file to switch to when the user changes the active file
+enum FileTarget {
+    Next(usize),
+    Prev(usize),
+    Index(usize),
+}
+
+/// How we keep the end of the file on the screen as it grows
+#[derive(PartialEq)]
+enum Follow {
+    Off,
+    /// Until the user scrolls somewhere else
+    End,
+    /// Until a line arriving after this offset matches the search
+    UntilMatch(usize),
 }
 
 pub struct Display {
@@ -105,6 +138,59 @@ pub struct Display {
     search_forward: bool,
 
     mouse_wheel_height: u16,
+
+    /// Offset of the line the delta-time column measures from, instead of the previous line
+    time_ref: Option<usize>,
+
+    /// Keep the end of the file on the screen as it grows
+    follow: Follow,
+
+    /// Length of the file when we last looked for new data in follow mode
+    follow_len: usize,
+
+    /// Ring the bell on the next refresh
+    bell: bool,
+
+    /// File and top line we were at before the last jump, for ''
+    last_position: Option<(usize, usize)>,
+
+    /// Width of the line number column, sized for the longest line number the document can have
+    number_width: usize,
+}
+
+// Width of the delta-time column, including the space separating it from the line
+const DELTA_WIDTH: usize = 10;
+
+// Least width of the line number column, including the space separating it from the line
+const LINE_NUMBER_WIDTH: usize = 9;
+
+/// Width of the line number column for a document of len bytes.  It has no more lines than bytes, so this fits any
+/// line number in it with the "~" of an estimate.
+fn line_number_width(len: usize) -> usize {
+    let digits = len.checked_ilog10().unwrap_or(0) as usize + 1;
+    LINE_NUMBER_WIDTH.max(digits + 2)
+}
+
+/// Format a line number to fit in the line number column, e.g. "42" or "~1234567" if it is an estimate
+fn format_line_number(number: LineNumber, width: usize) -> String {
+    format!("{:>width$} ", number.to_string(), width = width - 1)
+}
+
+/// Format a time delta to fit in the delta-time column, e.g. "+0.250s", "+12m05s", "-3h20m" or "+2d04h"
+fn format_delta(delta: TimeDelta) -> String {
+    let sign = if delta < TimeDelta::zero() { '-' } else { '+' };
+    let delta = delta.abs();
+    let secs = delta.num_seconds();
+    let text = if secs < 60 {
+        format!("{sign}{}.{:03}s", secs, delta.num_milliseconds() % 1000)
+    } else if secs < 60 * 60 {
+        format!("{sign}{}m{:02}s", secs / 60, secs % 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{sign}{}h{:02}m", secs / 3600, secs / 60 % 60)
+    } else {
+        format!("{sign}{}d{:02}h", secs / 86400, secs / 3600 % 24)
+    };
+    format!("{:>width$} ", text, width = DELTA_WIDTH - 1)
 }
 
 impl Drop for Display {
@@ -128,12 +214,18 @@ impl Display {
             half: 0,
             arg_fraq: 0,
             arg_denom: 0,
-            prev: DisplayState { height: 0, width: 0, pan: 0},
+            prev: DisplayState { height: 0, width: 0, pan: 0, number_width: 0},
             displayed_lines: Vec::new(),
             mouse_wheel_height: config.mouse_scroll,
             pan: 0,
             search_forward: true,
             pan_width: 0,
+            time_ref: None,
+            follow: Follow::Off,
+            follow_len: 0,
+            bell: false,
+            last_position: None,
+            number_width: LINE_NUMBER_WIDTH,
         }
     }
 
@@ -194,6 +286,34 @@ impl Display {
         }
     }
 
+    pub fn add_file(&mut self, doc: &mut Document, path: &str) -> bool {
+        match doc.add_file(&PathBuf::from(path)) {
+            Ok(index) => {
+                self.scroll = ScrollAction::SwitchFile(FileTarget::Index(index));
+                true
+            }
+            Err(e) => {
+                log::error!("Unable to open {}: {}", path, e);
+                self.set_status_msg(format!("Unable to open {}: {}", path, e));
+                false
+            }
+        }
+    }
+
+    pub fn goto_time(&mut self, doc: &mut Document, time: &str) -> bool {
+        let Some(time) = doc.parse_time(time) else {
+            log::error!("Invalid time: {}", time);
+            self.set_status_msg(format!("Invalid time: {}", time));
+            return false;
+        };
+        match doc.find_time(time) {
+            Some(offset) => self.scroll = ScrollAction::GotoOffset(offset),
+            // Every line is earlier than the given time
+            None => self.scroll = ScrollAction::EndOfFile(0),
+        }
+        true
+    }
+
     pub fn clear_filter(&mut self, doc: &mut Document) {
         doc.clear_filter().expect("Failed to clear filter");
     }
@@ -209,6 +329,32 @@ impl Display {
         }
     }
 
+    pub fn add_highlight(&mut self, doc: &mut Document, pattern: &str) -> bool {
+        match doc.add_highlight(pattern) {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("Invalid highlight expression: {}", e);
+                self.set_status_msg(format!("Invalid highlight expression: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Mark the top line on the screen, or the bottom one, with a letter
+    pub fn set_mark(&mut self, doc: &mut Document, c: char, bottom: bool) {
+        let line = if bottom { self.displayed_lines.last() } else { self.displayed_lines.first() };
+        if let Some(&offset) = line {
+            doc.set_mark(c, offset, bottom);
+        }
+    }
+
+    /// Mark or unmark the top line on the screen without a name
+    pub fn toggle_bookmark(&mut self, doc: &mut Document) {
+        if let Some(&offset) = self.displayed_lines.first() {
+            doc.toggle_bookmark(offset);
+        }
+    }
+
     // Half screen width, or sticky previous value, or given argument
     fn get_pan_width(&mut self) -> usize {
         if self.arg_num > 0 {
@@ -292,9 +438,71 @@ impl Display {
             }
     }
 
+    /// Width of the screen left for the log lines
+    fn text_width(&self) -> usize {
+        let mut gutter = 0;
+        if self.config.line_numbers {
+            gutter += self.number_width;
+        }
+        if self.config.delta {
+            gutter += DELTA_WIDTH;
+        }
+        self.width.saturating_sub(gutter)
+    }
+
+    /// Offset of the top line on the screen
+    pub fn top_line(&self) -> usize {
+        self.displayed_lines.first().copied().unwrap_or(0)
+    }
+
+    /// True while we follow the end of the file
+    pub fn following(&self) -> bool {
+        self.follow != Follow::Off
+    }
+
+    /// Follow the end of the file until a line that arrives from now on matches the search
+    pub fn follow_until_match(&mut self, doc: &Document) {
+        self.follow = Follow::UntilMatch(doc.len());
+        self.follow_len = doc.len();
+        self.scroll = ScrollAction::EndOfFile(0);
+    }
+
+    // Scroll to the new end of the file when it grows in follow mode, until the user scrolls somewhere else
+    fn follow_end(&mut self, doc: &mut Document) {
+        match self.scroll {
+            ScrollAction::None => {
+                let len = doc.poll(None);
+                if len != self.follow_len {
+                    self.follow_len = len;
+                    self.scroll = ScrollAction::EndOfFile(0);
+
+                    // The search index remembers what it already searched, so we can search from the start every time
+                    if let Follow::UntilMatch(start) = self.follow
+                            && let Some(offset) = doc.search_next(start, 1) {
+                        self.stop_at_match(offset);
+                    }
+                }
+            }
+            ScrollAction::Repaint | ScrollAction::EndOfFile(_) => {}
+            _ => self.follow = Follow::Off,
+        }
+    }
+
+    // Stop following on the line that matched and tell the user
+    fn stop_at_match(&mut self, offset: usize) {
+        self.follow = Follow::Off;
+        self.bell = true;
+        self.scroll = ScrollAction::GotoOffset(offset);
+    }
+
     /// Direct jump to some location because a previous op completed
     pub fn goto(&mut self, offset: usize) {
-        self.scroll = ScrollAction::GotoOffset(offset);
+        if let Follow::UntilMatch(_) = self.follow {
+            // The search we follow until took a while to finish
+            self.stop_at_match(offset);
+        } else {
+            self.scroll = ScrollAction::GotoOffset(offset);
+        }
     }
 
     pub fn handle_command(&mut self, cmd: UserCommand) {
@@ -366,11 +574,22 @@ impl Display {
                 self.scroll = ScrollAction::EndOfFile(0);
             }
             UserCommand::SeekStartLine => {
-                self.scroll = ScrollAction::StartOfFile(self.get_arg() as usize);
+                self.scroll = if self.arg_num > 0 {
+                    ScrollAction::GotoLine(self.arg_num)
+                } else {
+                    ScrollAction::StartOfFile(0)
+                };
             }
             UserCommand::SeekEndLine => {
                 self.scroll = ScrollAction::EndOfFile(self.get_arg() as usize);
             }
+            UserCommand::FollowMode => {
+                self.follow = Follow::End;
+                self.scroll = ScrollAction::EndOfFile(0);
+            }
+            UserCommand::Interrupt => {
+                self.follow = Follow::Off;
+            }
             UserCommand::RefreshDisplay => {
                 self.scroll = ScrollAction::Repaint;
             }
@@ -407,6 +626,39 @@ impl Display {
             UserCommand::SearchPrev => {
                 self.scroll = ScrollAction::Search(!self.search_forward, self.get_arg() as usize);
             }
+            UserCommand::NextFile => {
+                self.scroll = ScrollAction::SwitchFile(FileTarget::Next(self.get_one()));
+            }
+            UserCommand::PrevFile => {
+                self.scroll = ScrollAction::SwitchFile(FileTarget::Prev(self.get_one()));
+            }
+            UserCommand::GotoFile => {
+                self.scroll = ScrollAction::SwitchFile(FileTarget::Index(self.arg_num.saturating_sub(1)));
+            }
+            UserCommand::GotoBookmark(c) => {
+                self.scroll = ScrollAction::GotoMark(c);
+            }
+            UserCommand::NextBookmark => {
+                self.scroll = ScrollAction::NextMark(true);
+            }
+            UserCommand::PrevBookmark => {
+                self.scroll = ScrollAction::NextMark(false);
+            }
+            UserCommand::ToggleDeltaTime => {
+                self.config.delta = !self.config.delta;
+                self.scroll = ScrollAction::Repaint;
+            }
+            UserCommand::ToggleLineNumbers => {
+                self.config.line_numbers = !self.config.line_numbers;
+                self.scroll = ScrollAction::Repaint;
+            }
+            UserCommand::SetTimeReference => {
+                // Measure deltas from the top line on the screen, or go back to measuring from the previous line
+                let top = self.displayed_lines.first().copied();
+                self.time_ref = if self.time_ref.is_some() { None } else { top };
+                self.config.delta = true;
+                self.scroll = ScrollAction::Repaint;
+            }
             _ => {}
         }
 
@@ -427,9 +679,13 @@ impl Display {
         queue!(buff, crossterm::style::SetBackgroundColor(RGB_BLACK), terminal::Clear(ClearType::UntilNewLine)).unwrap();
     }
 
-    fn draw_log_line(&self, buff: &mut ScreenBuffer, row: usize, line: &LogLine) {
+    fn draw_log_line(&self, buff: &mut ScreenBuffer, row: usize, gutter: Option<&str>, line: &LogLine) {
         queue!(buff, cursor::MoveTo(0, row as u16)).unwrap();
 
+        if let Some(gutter) = gutter {
+            buff.push_raw(&format!("{}", gutter.dark_grey()));
+        }
+
         // Used for LogLines that are already rendered with Stylist.  TODO: New type? StyledLogLine?
         buff.push_raw(line.line.as_str());
 
@@ -547,7 +803,19 @@ impl Display {
             } else if up {
                 queue!(buff, terminal::ScrollUp(1)).unwrap();
             }
-            self.draw_log_line(&mut buff, row, line);
+            let numbers = self.config.line_numbers.then(|| {
+                doc.line_number(line.offset)
+                    .map(|number| format_line_number(number, self.number_width))
+                    .unwrap_or_else(|| " ".repeat(self.number_width))
+            });
+            let delta = self.config.delta.then(|| {
+                doc.delta_time(line.offset, self.time_ref)
+                    .map(format_delta)
+                    .unwrap_or_else(|| " ".repeat(DELTA_WIDTH))
+            });
+            let gutter = (numbers.is_some() || delta.is_some())
+                .then(|| numbers.unwrap_or_default() + &delta.unwrap_or_default());
+            self.draw_log_line(&mut buff, row, gutter.as_deref(), line);
             row += incr;
         }
 
@@ -604,7 +872,7 @@ impl Display {
                 .max();
         if let Some(max) = max {
             // FIXME: +1 because of \n on EOL; but we might strip it in the future
-            max.saturating_sub(self.width + 1)
+            max.saturating_sub(self.text_width() + 1)
         } else {
             0
         }
@@ -626,10 +894,10 @@ impl Display {
         }
 
         if self.config.chop && self.pan == 0 {
-            doc.set_line_mode(LineViewMode::Wrap{width: self.width});
+            doc.set_line_mode(LineViewMode::Wrap{width: self.text_width()});
         } else {
             // Pan the document to the left; override wrap-mode
-            doc.set_line_mode(LineViewMode::Clip{width: self.width, left: self.pan});
+            doc.set_line_mode(LineViewMode::Clip{width: self.text_width(), left: self.pan});
         }
 
         let lines= match scroll {
@@ -683,18 +951,70 @@ impl Display {
         }
     }
 
+    // Make another file the active one, remembering where we were in this one.  Returns the top offset to show.
+    fn switch_file(&mut self, doc: &mut Document, index: usize, top: usize) -> usize {
+        log::trace!("switch to file {}", index);
+        doc.save_top(top);
+        self.time_ref = None;
+        doc.select_file(index)
+    }
+
+    // Go to a marked line, or to the previous position, the start or the end of the file
+    fn goto_mark(&mut self, doc: &mut Document, c: char, previous: Option<(usize, usize)>, top: usize) -> Scroll {
+        let view_height = self.page_size();
+        let (file, offset, bottom) = match c {
+            '^' => return Scroll::goto_top(0, view_height),
+            '$' => return Scroll::goto_bottom(usize::MAX, view_height),
+            '\'' => match previous {
+                Some((file, offset)) => (file, offset, false),
+                None => return Scroll::goto_top(0, view_height),
+            },
+            _ => match doc.mark(c) {
+                Some(mark) => (mark.file, mark.offset, mark.bottom),
+                None => {
+                    log::trace!("no mark {c}");
+                    self.bell = true;
+                    return Scroll::none();
+                }
+            },
+        };
+
+        if file != doc.active_file() {
+            self.switch_file(doc, file, top);
+        }
+        if bottom {
+            // Show the whole marked line at the bottom
+            Scroll::goto_bottom(offset + 1, view_height)
+        } else {
+            Scroll::goto_top(offset, view_height)
+        }
+    }
+
     pub fn refresh_screen(&mut self, doc: &mut Document) -> std::io::Result<()> {
         // FIXME: Discard unused cached lines
 
         let view_height = self.page_size();
 
+        // Widen the line numbers when the file grows enough to need it
+        self.number_width = line_number_width(doc.len());
+
         // Our new display
         let disp = DisplayState {
             height: self.page_size(),
             width: self.width,
             pan: self.pan,
+            number_width: self.number_width,
         };
 
+        if self.follow != Follow::Off {
+            self.follow_end(doc);
+        }
+
+        if self.bell {
+            self.bell = false;
+            execute!(stdout(), crossterm::style::Print('\x07'))?;
+        }
+
         let first_on_screen = *self.displayed_lines.first().unwrap_or(&0);
         let last_on_screen = *self.displayed_lines.last().unwrap_or(&0);
 
@@ -710,11 +1030,21 @@ impl Display {
                 log::trace!("check for more data");
                 Scroll::overwrite(last_on_screen, self.displayed_lines.len(), view_height)
             } else {
+                // Remember where we jumped from; '' goes back there
+                let previous = self.last_position;
+                if self.scroll.is_jump() && self.follow == Follow::Off {
+                    self.last_position = Some((doc.active_file(), first_on_screen));
+                }
+
                 match self.scroll {
                     ScrollAction::GotoOffset(offset) => {
-                        // Scroll to the given offset
+                        // Scroll to the given offset, or show the end of the document if it is past it
                         log::trace!("scroll to offset {}", offset);
-                        Scroll::goto_top(offset, view_height)
+                        if offset < doc.len() {
+                            Scroll::goto_top(offset, view_height)
+                        } else {
+                            Scroll::goto_bottom(usize::MAX, view_height)
+                        }
                     }
                     ScrollAction::GotoPercent(percent) => {
                         // Scroll to the given percentage of the document
@@ -722,6 +1052,31 @@ impl Display {
                         let offset = doc.len() as f64 * percent / 100.0;
                         Scroll::goto_top(offset as usize, view_height)
                     }
+                    ScrollAction::GotoLine(number) => {
+                        // Scroll to the given line of the file, or the end if it has fewer lines
+                        log::trace!("scroll to line {}", number);
+                        match doc.find_line(number) {
+                            Some(offset) if offset < doc.len() => Scroll::goto_top(offset, view_height),
+                            Some(_) => Scroll::goto_bottom(usize::MAX, view_height),
+                            // Still counting lines; the viewer goes there when we find it
+                            None => Scroll::none(),
+                        }
+                    }
+                    ScrollAction::GotoMark(c) => {
+                        log::trace!("go to mark {c}");
+                        self.goto_mark(doc, c, previous, first_on_screen)
+                    }
+                    ScrollAction::NextMark(forward) => {
+                        // Go to the next bookmarked line after the top line on the screen, or the one before it
+                        log::trace!("go to next bookmark, forward={forward}");
+                        match doc.next_bookmark(first_on_screen, forward) {
+                            Some(offset) => Scroll::goto_top(offset, view_height),
+                            None => {
+                                self.bell = true;
+                                Scroll::none()
+                            }
+                        }
+                    }
                     ScrollAction::Repaint => {
                         log::trace!("repaint everything");
                         Scroll::repaint(first_on_screen, view_height)
@@ -762,6 +1117,22 @@ impl Display {
                             Scroll::repaint(first_on_screen, view_height)
                         }
                     }
+                    ScrollAction::SwitchFile(ref target) => {
+                        let active = doc.active_file();
+                        let index = match *target {
+                            FileTarget::Next(n) => active.saturating_add(n),
+                            FileTarget::Prev(n) => active.saturating_sub(n),
+                            FileTarget::Index(n) => n,
+                        };
+                        let index = index.min(doc.file_count() - 1);
+                        if index == active {
+                            Scroll::none()
+                        } else {
+                            // Remember where we were in this file and go back where we left the other one
+                            let top = self.switch_file(doc, index, first_on_screen);
+                            Scroll::goto_top(top, view_height)
+                        }
+                    }
                     ScrollAction::None => Scroll::none()
                 }
             };
@@ -783,3 +1154,56 @@ impl Display {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_delta() {
+        let test_cases = [
+            (TimeDelta::zero(), "  +0.000s "),
+            (TimeDelta::milliseconds(250), "  +0.250s "),
+            (TimeDelta::milliseconds(-59_999), " -59.999s "),
+            (TimeDelta::seconds(12 * 60 + 5), "  +12m05s "),
+            (TimeDelta::minutes(-200), "   -3h20m "),
+            (TimeDelta::hours(52), "   +2d04h "),
+        ];
+
+        for (delta, expected) in test_cases {
+            let text = format_delta(delta);
+            assert_eq!(text, expected, "Testing delta: {}", delta);
+            assert_eq!(text.len(), DELTA_WIDTH);
+        }
+    }
+
+    #[test]
+    fn test_format_line_number() {
+        let test_cases = [
+            (LineNumber::Exact(42), "      42 "),
+            (LineNumber::Estimate(1234567), "~1234567 "),
+            (LineNumber::Unknown, "       ~ "),
+        ];
+
+        for (number, expected) in test_cases {
+            let text = format_line_number(number, LINE_NUMBER_WIDTH);
+            assert_eq!(text, expected, "Testing line number: {}", number);
+            assert_eq!(text.len(), LINE_NUMBER_WIDTH);
+        }
+    }
+
+    #[test]
+    fn test_line_number_width() {
+        assert_eq!(line_number_width(0), LINE_NUMBER_WIDTH);
+        assert_eq!(line_number_width(9_999_999), LINE_NUMBER_WIDTH);
+
+        // The longest line numbers and estimates of bigger files still fit
+        for len in [99_999_999, 100_000_000, 12_345_678_901] {
+            let width = line_number_width(len);
+            for number in [LineNumber::Exact(len), LineNumber::Estimate(len)] {
+                assert_eq!(format_line_number(number, width).len(), width, "Testing line number: {}", number);
+            }
+        }
+        assert_eq!(line_number_width(100_000_000), 11);
+    }
+}
@@ -1,91 +1,701 @@
-/// A wrapper for a LogFileLines that applies color, filtering, caching, etc.
+// A wrapper for a LogFileLines that applies color, filtering, caching, etc.
 
 use crate::{config::Config, styled_text::{styled_line::{PattColor, StyledLine}, stylist::Stylist, LineViewMode}};
+use chrono::{NaiveDateTime, TimeDelta};
+use crossterm::style::Color;
 use regex::Regex;
-use indexed_file::{files::Stream, indexer::indexed_log::IndexStats, IndexedLog, Log, LogLine, LogStack};
+use indexed_file::time_stamper::{parse_user_time, TimeStamper};
+use indexed_file::{files::Stream, indexer::indexed_log::{IndexStats, LineNumber}, IndexedLog, Log, LogLine, LogStack};
+use document::MergedLogFile;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// One of the files opened in the Document, with its own filters, search and view position
+struct DocFile {
+    name: String,
+    log: LogStack,
+    search: Option<String>,
+    top: usize,
+
+    /// Timestamp parser for the delta-time column
+    stamper: TimeStamper,
+
+    /// Start offsets of the lines marked without a name
+    bookmarks: BTreeSet<usize>,
+}
+
+impl DocFile {
+    fn open(path: Option<&PathBuf>) -> std::io::Result<Self> {
+        let name = match path {
+            Some(path) => path.display().to_string(),
+            None => "-".to_string(),
+        };
+        let log = Log::open_cached(path)?;
+        Ok(Self {
+            name,
+            log: LogStack::new(log),
+            search: None,
+            top: 0,
+            stamper: TimeStamper::default(),
+            bookmarks: BTreeSet::new(),
+        })
+    }
+
+    /// Open several files merged into one view ordered by timestamp
+    fn merged(paths: &[PathBuf]) -> std::io::Result<Self> {
+        let name = paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" + ");
+        let logs = paths.iter().map(|path| Log::open_cached(Some(path))).collect::<std::io::Result<Vec<_>>>()?;
+        let log = Log::from(MergedLogFile::new(logs));
+        Ok(Self {
+            name,
+            log: LogStack::new(log),
+            search: None,
+            top: 0,
+            stamper: TimeStamper::default(),
+            bookmarks: BTreeSet::new(),
+        })
+    }
+
+    /// Parse a time given by the user. A time of day with no date refers to the day this log begins.  A time with no
+    /// UTC offset is in the same zone as the log.
+    fn parse_time(&mut self, text: &str) -> Option<NaiveDateTime> {
+        let offset = self.log.first_offset();
+        let date = self.log.first_time().map(|time| (time + offset.unwrap_or_default()).date());
+        parse_user_time(text, date, offset)
+    }
+
+    /// Hide the lines outside the time window [from, to), with the times resolved for this log
+    fn filter_time(&mut self, from: Option<&str>, to: Option<&str>) -> Result<(), String> {
+        let mut parse = |text: Option<&str>| match text {
+            Some(text) => self.parse_time(text)
+                .map(Some)
+                .ok_or_else(|| format!("Unable to find the time {} in {}: it has no timestamps", text, self.name)),
+            None => Ok(None),
+        };
+        let from = parse(from)?;
+        let to = parse(to)?;
+        self.log.filter_time(from, to);
+        Ok(())
+    }
+}
+
+/// True if offset is the start of a line, and not the start of a wrapped row
+fn starts_line(log: &mut LogStack, offset: usize) -> bool {
+    offset == 0 || log.read_line(offset - 1).is_some_and(|line| line.line == "\n")
+}
+
+// Maximum number of lines to look back for the previous timestamp in the delta-time column
+const DELTA_SCAN_LINES: usize = 100;
+
+// Colors for highlighted searches, used in turn
+const HIGHLIGHT_COLORS: [Color; 6] = [Color::Yellow, Color::Cyan, Color::Magenta, Color::Green, Color::Red, Color::Blue];
+
+/// A search kept highlighted in its own color
+struct Highlight {
+    pattern: String,
+    color: Color,
+    enabled: bool,
+}
+
+/// Which searches n and N move between
+#[derive(Clone, Copy, PartialEq)]
+enum SearchTarget {
+    /// The last search entered with / or ?
+    Current,
+    /// Matches of any search or highlight
+    All,
+    /// One of the highlights
+    Highlight(usize),
+}
+
+/// What an entry in the filter manager does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    Include,
+    Exclude,
+    Search,
+    Highlight,
+}
+
+/// A filter, search or highlight of the active file, as listed in the filter manager
+pub struct FilterEntry {
+    pub kind: FilterKind,
+    /// The expression, without the leading '!' of exclude filters
+    pub pattern: String,
+    pub enabled: bool,
+    pub color: Option<Color>,
+    pub stats: Option<IndexStats>,
+}
+
+/// A line marked with a letter to return to later
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mark {
+    /// Index of the file the line is in
+    pub file: usize,
+    /// Start of the marked line
+    pub offset: usize,
+    /// Marked at the bottom of the screen, so we show it at the bottom again
+    pub bottom: bool,
+}
+
+// Where a filter manager entry lives
+enum EntryRef {
+    Filter(usize),
+    Search,
+    Highlight(usize),
+}
+
 pub struct Document {
     // FIXME: StyledLine caching -- premature optimization?
     // File contents
-    log: LogStack,
+    files: Vec<DocFile>,
+    active: usize,
     stylist: Stylist,
+
+    // Time window applied to every file, as the user gave it.  Each file resolves a time of day to its own date.
+    hide_before: Option<String>,
+    hide_after: Option<String>,
+
+    // Searches kept highlighted in every file
+    highlights: Vec<Highlight>,
+    target: SearchTarget,
+
+    // Lines marked with a letter in any file
+    marks: HashMap<char, Mark>,
+
+    // Show only the bookmarked lines of every file
+    bookmarks_only: bool,
 }
 
 impl Document {
 
     pub fn get_lines_range<'a, R>(&'a mut self, range: &'a R) -> impl DoubleEndedIterator<Item = LogLine> + 'a
     where R: std::ops::RangeBounds<usize> {
-        self.stylist.iter_range(&mut self.log, range)
+        self.stylist.iter_range(&mut self.files[self.active].log, range)
     }
 
     pub fn get_plain_lines<'a, R>(&'a mut self, range: &'a R) -> impl DoubleEndedIterator<Item = LogLine> + 'a
     where R: std::ops::RangeBounds<usize> {
-        self.log.iter_lines_range(range)
+        self.log_mut().iter_lines_range(range)
     }
 
     pub fn set_search(&mut self, search: &str) -> Result<(), regex::Error> {
+        let matcher = Regex::new(search)?;
         self.stylist.remove_match(crate::styled_text::StyleReason::Search);
-        self.stylist.add_match(crate::styled_text::StyleReason::Search, Regex::new(search)?, PattColor::Inverse);
+        self.stylist.add_match(crate::styled_text::StyleReason::Search, matcher, PattColor::Inverse);
+
+        let file = &mut self.files[self.active];
+        file.search = Some(search.to_string());
         // TODO: force viewer to refresh page
-        self.log.search_regex(search)
+        file.log.add_search(search)?;
+
+        // Drop the previous search, unless it is also a highlight
+        self.sync_highlights();
+        Ok(())
+    }
+
+    /// Stop searching for anything with n and N in the active file
+    fn clear_search(&mut self) {
+        self.stylist.remove_match(crate::styled_text::StyleReason::Search);
+        self.files[self.active].search = None;
+        self.sync_highlights();
+    }
+
+    /// Keep highlighting a search expression in its own color in every file.  If string is empty, clears all
+    /// highlights.
+    pub fn add_highlight(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        if pattern.is_empty() {
+            self.highlights.clear();
+        } else if let Some(highlight) = self.highlights.iter_mut().find(|highlight| highlight.pattern == pattern) {
+            highlight.enabled = true;
+        } else {
+            Regex::new(pattern)?;
+            self.highlights.push(Highlight {
+                pattern: pattern.to_string(),
+                color: HIGHLIGHT_COLORS[self.highlights.len() % HIGHLIGHT_COLORS.len()],
+                enabled: true,
+            });
+        }
+        self.sync_highlights();
+        Ok(())
+    }
+
+    // Bring the stylist and the searches in every file up to date with our highlights
+    fn sync_highlights(&mut self) {
+        self.stylist.remove_match(crate::styled_text::StyleReason::Highlight);
+        for highlight in self.highlights.iter().filter(|highlight| highlight.enabled) {
+            let matcher = Regex::new(&highlight.pattern).expect("highlight was validated when it was added");
+            self.stylist.add_match(crate::styled_text::StyleReason::Highlight, matcher, PattColor::Mark(highlight.color));
+        }
+
+        let wanted = |file: &DocFile, pattern: &str| {
+            file.search.as_deref() == Some(pattern)
+                || self.highlights.iter().any(|highlight| highlight.enabled && highlight.pattern == pattern)
+        };
+        for file in self.files.iter_mut() {
+            let stale = file.log.searches()
+                .filter(|&search| !wanted(file, search))
+                .map(|search| search.to_string())
+                .collect::<Vec<_>>();
+            for search in stale {
+                file.log.remove_search(&search);
+            }
+            for highlight in self.highlights.iter().filter(|highlight| highlight.enabled) {
+                file.log.add_search(&highlight.pattern).expect("highlight was validated when it was added");
+            }
+        }
+
+        if let SearchTarget::Highlight(i) = self.target
+            && !self.highlights.get(i).is_some_and(|highlight| highlight.enabled) {
+            self.target = SearchTarget::Current;
+        }
+    }
+
+    // The first enabled highlight at or after index
+    fn next_highlight(&self, index: usize) -> Option<usize> {
+        (index..self.highlights.len()).find(|&i| self.highlights[i].enabled)
+    }
+
+    /// Switch n and N between the current search, all searches and highlights, and each highlight in turn
+    pub fn next_search_target(&mut self) {
+        self.target = match self.target {
+            SearchTarget::Current if self.next_highlight(0).is_some() => SearchTarget::All,
+            SearchTarget::Current => SearchTarget::Current,
+            SearchTarget::All => self.next_highlight(0).map_or(SearchTarget::Current, SearchTarget::Highlight),
+            SearchTarget::Highlight(i) => self.next_highlight(i + 1).map_or(SearchTarget::Current, SearchTarget::Highlight),
+        };
+    }
+
+    /// Describe what n and N will find, if there is any choice
+    pub fn describe_search_target(&self) -> Option<String> {
+        self.next_highlight(0)?;
+        match self.search_target() {
+            Some(pattern) => Some(format!("n/N: \"{pattern}\"")),
+            None => Some("n/N: all".to_string()),
+        }
+    }
+
+    /// The search expression n and N follow in the active file, or None to follow all of them
+    fn search_target(&self) -> Option<&str> {
+        match self.target {
+            SearchTarget::Current => self.files[self.active].search.as_deref(),
+            SearchTarget::All => None,
+            SearchTarget::Highlight(i) => self.highlights.get(i).map(|highlight| highlight.pattern.as_str()),
+        }
     }
 
     pub fn clear_filter(&mut self) -> Result<(), regex::Error> {
-        self.log.filter_regex("")
+        self.log_mut().filter_regex("")
     }
 
     pub fn set_filter(&mut self, filter: &str) -> Result<(), regex::Error> {
-        self.log.filter_regex(filter)
+        self.log_mut().filter_regex(filter)
     }
 
     pub fn search_next(&mut self, line: usize, repeat: usize) -> Option<usize> {
-        self.log.search_next(repeat, line)
+        let target = self.search_target().map(|target| target.to_string());
+        self.log_mut().set_search_target(target.as_deref());
+        self.log_mut().search_next(repeat, line)
     }
 
     pub fn search_back(&mut self, line: usize, repeat: usize) -> Option<usize> {
-        self.log.search_next_back(repeat, line)
+        let target = self.search_target().map(|target| target.to_string());
+        self.log_mut().set_search_target(target.as_deref());
+        self.log_mut().search_next_back(repeat, line)
+    }
+
+    /// Parse a time given by the user for the active file
+    pub fn parse_time(&mut self, text: &str) -> Option<NaiveDateTime> {
+        self.files[self.active].parse_time(text)
+    }
+
+    /// Hide lines outside the time window [from, to) in every file.  Fails if a file can't resolve one of the times.
+    pub fn set_time_range(&mut self, from: Option<String>, to: Option<String>) -> Result<(), String> {
+        for file in self.files.iter_mut() {
+            file.filter_time(from.as_deref(), to.as_deref())?;
+        }
+        self.hide_before = from;
+        self.hide_after = to;
+        Ok(())
+    }
+
+    /// Find the first line at or after the given time
+    pub fn find_time(&mut self, time: NaiveDateTime) -> Option<usize> {
+        self.log_mut().find_time(time)
+    }
+
+    /// Time elapsed between the line starting at offset and the displayed line before it that has a timestamp.  If
+    /// reference is given, measure from the line holding that offset instead.
+    /// Returns None if offset is not the start of a line or if either line has no timestamp.
+    pub fn delta_time(&mut self, offset: usize, reference: Option<usize>) -> Option<TimeDelta> {
+        let DocFile { log, stamper, .. } = &mut self.files[self.active];
+
+        // Wrapped lines only get a delta on their first row
+        if !starts_line(log, offset) {
+            return None;
+        }
+        let time = stamper.time(&log.read_line(offset)?.line)?;
+
+        let since = match reference {
+            Some(reference) => {
+                let line = log.iter_lines_range(&(reference..)).next()?;
+                stamper.time(&line.line)?
+            }
+            None => {
+                // Lines without timestamps are usually continuations of the one before
+                log.iter_lines_range(&(..offset)).rev()
+                    .take(DELTA_SCAN_LINES)
+                    .find_map(|line| stamper.time(&line.line))?
+            }
+        };
+        Some(time - since)
+    }
+
+    /// Number of the line starting at offset in the file, or None if offset is a wrapped row of a line
+    pub fn line_number(&mut self, offset: usize) -> Option<LineNumber> {
+        let log = self.log_mut();
+        starts_line(log, offset).then(|| log.line_number(offset))
+    }
+
+    /// Find the line with the given number in the file, or the end of the file if it has fewer lines.  Returns None
+    /// if we are still looking; run() returns the offset when we find it.
+    pub fn find_line(&mut self, number: usize) -> Option<usize> {
+        self.log_mut().find_line(number)
+    }
+
+    /// Describe where the line holding offset is in the file, e.g. "line 12 of 345"
+    pub fn describe_line(&self, offset: usize) -> String {
+        format!("line {} of {}", self.log().line_number(offset), self.log().line_count())
     }
 
     pub fn run(&mut self, timeout: u64) -> Option<usize> {
-        self.log.run_pending(timeout)
+        self.log_mut().run_pending(timeout)
     }
 
     pub fn describe_pending(&self) -> String {
-        self.log.describe_pending()
+        self.log().describe_pending()
     }
 
     pub fn has_pending(&self) -> bool {
-        self.log.has_pending()
+        self.log().has_pending()
     }
 
     pub fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize {
-        self.log.poll(timeout)
+        self.log_mut().poll(timeout)
+    }
+
+    fn log(&self) -> &LogStack {
+        &self.files[self.active].log
+    }
+
+    fn log_mut(&mut self) -> &mut LogStack {
+        &mut self.files[self.active].log
     }
 }
 
+// File list management
 impl Document {
-    pub fn new(config: Config) -> Self {
-        let filename = config.filename.first();
-        let log = Log::open(filename).expect("Failed to open file");
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
 
-        Self {
-            log: LogStack::new(log),
-            stylist: Stylist::default(),
+    pub fn active_file(&self) -> usize {
+        self.active
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.files[self.active].name
+    }
+
+    /// Open another file and append it to the file list.  Returns the index of the new file.
+    pub fn add_file(&mut self, path: &PathBuf) -> std::io::Result<usize> {
+        let mut file = DocFile::open(Some(path))?;
+        file.filter_time(self.hide_before.as_deref(), self.hide_after.as_deref())
+            .map_err(std::io::Error::other)?;
+        self.files.push(file);
+        self.sync_highlights();
+        self.sync_bookmarks();
+        Ok(self.files.len() - 1)
+    }
+
+    /// Remember the top line displayed for the active file so we can return to it later
+    pub fn save_top(&mut self, offset: usize) {
+        self.files[self.active].top = offset;
+    }
+
+    /// Make another file the active one.  Returns the top offset last displayed for it.
+    pub fn select_file(&mut self, index: usize) -> usize {
+        self.active = index.min(self.files.len() - 1);
+
+        // Restore the search highlight for this file
+        self.stylist.remove_match(crate::styled_text::StyleReason::Search);
+        if let Some(search) = &self.files[self.active].search {
+            let matcher = Regex::new(search).expect("search was validated when it was set");
+            self.stylist.add_match(crate::styled_text::StyleReason::Search, matcher, PattColor::Inverse);
+        }
+
+        self.files[self.active].top
+    }
+}
+
+// Bookmarks.  Marks are kept at the start of their lines so they don't move when the lines wrap differently.
+impl Document {
+    // Start of the line holding offset in the active file
+    fn line_start(&mut self, offset: usize) -> usize {
+        self.log_mut().iter_lines_range(&(..offset + 1)).next_back().map_or(offset, |line| line.offset)
+    }
+
+    /// Mark the line holding offset in the active file with a letter, replacing any line marked with it before
+    pub fn set_mark(&mut self, c: char, offset: usize, bottom: bool) {
+        let offset = self.line_start(offset);
+        self.marks.insert(c, Mark { file: self.active, offset, bottom });
+        self.sync_bookmarks();
+    }
+
+    pub fn clear_mark(&mut self, c: char) {
+        self.marks.remove(&c);
+        self.sync_bookmarks();
+    }
+
+    pub fn mark(&self, c: char) -> Option<Mark> {
+        self.marks.get(&c).copied()
+    }
+
+    /// Mark or unmark the line holding offset in the active file, without a name
+    pub fn toggle_bookmark(&mut self, offset: usize) {
+        let offset = self.line_start(offset);
+        let bookmarks = &mut self.files[self.active].bookmarks;
+        if !bookmarks.remove(&offset) {
+            bookmarks.insert(offset);
+        }
+        self.sync_bookmarks();
+    }
+
+    // Every line marked in a file, named or not
+    fn bookmarked(&self, file: usize) -> BTreeSet<usize> {
+        let named = self.marks.values().filter(|mark| mark.file == file).map(|mark| mark.offset);
+        self.files[file].bookmarks.iter().copied().chain(named).collect()
+    }
+
+    /// The first bookmarked line after the line holding offset in the active file, or the last one before it
+    pub fn next_bookmark(&mut self, offset: usize, forward: bool) -> Option<usize> {
+        let offset = self.line_start(offset);
+        let bookmarked = self.bookmarked(self.active);
+        if forward {
+            bookmarked.range(offset + 1..).next().copied()
+        } else {
+            bookmarked.range(..offset).next_back().copied()
+        }
+    }
+
+    /// Switch between showing only the bookmarked lines and showing all of them
+    pub fn toggle_bookmarks_only(&mut self) {
+        self.bookmarks_only = !self.bookmarks_only;
+        if !self.bookmarks_only {
+            for file in self.files.iter_mut() {
+                file.log.filter_bookmarks(None);
+            }
+        }
+        self.sync_bookmarks();
+    }
+
+    pub fn describe_bookmarks(&self) -> Option<String> {
+        self.bookmarks_only.then(|| "Bookmarked lines only".to_string())
+    }
+
+    // Bring the bookmark filter of every file up to date with our marks
+    fn sync_bookmarks(&mut self) {
+        if self.bookmarks_only {
+            for i in 0..self.files.len() {
+                let bookmarked = self.bookmarked(i);
+                self.files[i].log.filter_bookmarks(Some(bookmarked));
+            }
+        }
+    }
+}
+
+// Filter manager operations.  Entries are the filters of the active file, then its search, then the highlights.
+impl Document {
+    pub fn filter_entries(&self) -> Vec<FilterEntry> {
+        let file = &self.files[self.active];
+        let filters = file.log.filters().enumerate().map(|(i, (pattern, enabled))| {
+            let (kind, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (FilterKind::Exclude, pattern),
+                None => (FilterKind::Include, pattern),
+            };
+            FilterEntry {
+                kind,
+                pattern: pattern.to_string(),
+                enabled,
+                color: None,
+                stats: file.log.filter_stats(i).cloned(),
+            }
+        });
+        let search = file.search.iter().map(|search| FilterEntry {
+            kind: FilterKind::Search,
+            pattern: search.clone(),
+            enabled: true,
+            color: None,
+            stats: file.log.search_stats(search).cloned(),
+        });
+        let highlights = self.highlights.iter().map(|highlight| FilterEntry {
+            kind: FilterKind::Highlight,
+            pattern: highlight.pattern.clone(),
+            enabled: highlight.enabled,
+            color: Some(highlight.color),
+            stats: file.log.search_stats(&highlight.pattern).cloned(),
+        });
+        filters.chain(search).chain(highlights).collect()
+    }
+
+    fn locate_entry(&self, index: usize) -> Option<EntryRef> {
+        let filters = self.log().filters().count();
+        let searches = self.files[self.active].search.iter().count();
+        if index < filters {
+            Some(EntryRef::Filter(index))
+        } else if index < filters + searches {
+            Some(EntryRef::Search)
+        } else if index - filters - searches < self.highlights.len() {
+            Some(EntryRef::Highlight(index - filters - searches))
+        } else {
+            None
+        }
+    }
+
+    /// Enable or disable an entry.  The search is always enabled.
+    pub fn toggle_entry(&mut self, index: usize) {
+        match self.locate_entry(index) {
+            Some(EntryRef::Filter(i)) => {
+                let enabled = self.log().filters().nth(i).is_some_and(|(_, enabled)| enabled);
+                self.log_mut().enable_filter(i, !enabled);
+            },
+            Some(EntryRef::Highlight(i)) => {
+                self.highlights[i].enabled = !self.highlights[i].enabled;
+                self.sync_highlights();
+            },
+            Some(EntryRef::Search) | None => {},
+        }
+    }
+
+    /// Swap an entry with its neighbor among the entries of the same sort.  Returns the entry's new index.
+    pub fn move_entry(&mut self, index: usize, up: bool) -> usize {
+        let Some(to) = (if up { index.checked_sub(1) } else { Some(index + 1) }) else { return index };
+        match (self.locate_entry(index), self.locate_entry(to)) {
+            (Some(EntryRef::Filter(i)), Some(EntryRef::Filter(j))) => {
+                self.log_mut().move_filter(i, j);
+                to
+            },
+            (Some(EntryRef::Highlight(i)), Some(EntryRef::Highlight(j))) => {
+                self.highlights.swap(i, j);
+                if self.target == SearchTarget::Highlight(i) {
+                    self.target = SearchTarget::Highlight(j);
+                } else if self.target == SearchTarget::Highlight(j) {
+                    self.target = SearchTarget::Highlight(i);
+                }
+                self.sync_highlights();
+                to
+            },
+            _ => index,
+        }
+    }
+
+    /// Give a highlight the next color in our palette
+    pub fn recolor_entry(&mut self, index: usize) {
+        if let Some(EntryRef::Highlight(i)) = self.locate_entry(index) {
+            let current = HIGHLIGHT_COLORS.iter().position(|&color| color == self.highlights[i].color).unwrap_or(0);
+            self.highlights[i].color = HIGHLIGHT_COLORS[(current + 1) % HIGHLIGHT_COLORS.len()];
+            self.sync_highlights();
+        }
+    }
+
+    /// Switch a filter between showing and hiding the lines it matches
+    pub fn invert_entry(&mut self, index: usize) -> Result<(), regex::Error> {
+        if let Some(EntryRef::Filter(i)) = self.locate_entry(index) {
+            let pattern = self.log().filters().nth(i).map(|(pattern, _)| pattern.to_string()).unwrap_or_default();
+            let inverted = match pattern.strip_prefix('!') {
+                Some(pattern) => pattern.to_string(),
+                None => format!("!{pattern}"),
+            };
+            self.log_mut().edit_filter(i, &inverted)?;
+        }
+        Ok(())
+    }
+
+    /// Change the expression of an entry.  Filters keep their include or exclude sort.
+    pub fn edit_entry(&mut self, index: usize, pattern: &str) -> Result<(), regex::Error> {
+        match self.locate_entry(index) {
+            Some(EntryRef::Filter(i)) => {
+                let exclude = self.log().filters().nth(i).is_some_and(|(pattern, _)| pattern.starts_with('!'));
+                let pattern = if exclude { format!("!{pattern}") } else { pattern.to_string() };
+                self.log_mut().edit_filter(i, &pattern)
+            },
+            Some(EntryRef::Search) => self.set_search(pattern),
+            Some(EntryRef::Highlight(i)) => {
+                Regex::new(pattern)?;
+                self.highlights[i].pattern = pattern.to_string();
+                self.sync_highlights();
+                Ok(())
+            },
+            None => Ok(()),
         }
     }
 
+    pub fn remove_entry(&mut self, index: usize) {
+        match self.locate_entry(index) {
+            Some(EntryRef::Filter(i)) => self.log_mut().remove_filter(i),
+            Some(EntryRef::Search) => self.clear_search(),
+            Some(EntryRef::Highlight(i)) => {
+                self.highlights.remove(i);
+                self.target = SearchTarget::Current;
+                self.sync_highlights();
+            },
+            None => {},
+        }
+    }
+}
+
+impl Document {
+    /// Open the files given in the config.  Fails if we can't apply its time window to one of them.
+    pub fn new(config: Config) -> Result<Self, String> {
+        let mut files = Vec::new();
+        if config.filename.is_empty() {
+            files.push(DocFile::open(None).expect("Failed to open stdin"));
+        } else if config.merge && config.filename.len() > 1 {
+            files.push(DocFile::merged(&config.filename).expect("Failed to open file"));
+        } else {
+            for filename in config.filename.iter() {
+                files.push(DocFile::open(Some(filename)).expect("Failed to open file"));
+            }
+        }
+
+        let mut doc = Self {
+            files,
+            active: 0,
+            stylist: Stylist::default(),
+            hide_before: None,
+            hide_after: None,
+            highlights: Vec::new(),
+            target: SearchTarget::Current,
+            marks: HashMap::new(),
+            bookmarks_only: false,
+        };
+        doc.set_time_range(config.hide_before, config.hide_after)?;
+        Ok(doc)
+    }
+
     pub fn set_line_mode(&mut self, mode: LineViewMode) {
         self.stylist.mode = mode;
     }
 
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
-        self.log.len()
+        self.log().len()
     }
 
     pub fn info(&self) -> impl Iterator<Item = &IndexStats> + '_
     where Self: Sized
     {
-        self.log.info()
+        self.log().info()
     }
 
     // FIXME: Move to Stylist?
@@ -94,3 +704,49 @@ impl Document {
         StyledLine::new(line, PattColor::NoCrumb)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("felon-{}-{}", std::process::id(), name));
+        std::fs::File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_hide_time_of_day_per_file() {
+        let first = temp_file("first.log", "2024-05-01 13:00:00 early\n2024-05-01 14:00:00 late\n");
+        let second = temp_file("second.log", "2024-06-02 13:00:00 early\n2024-06-02 14:00:00 late\n");
+        let config = Config {
+            filename: vec![first.clone(), second.clone()],
+            hide_before: Some("13:45".to_string()),
+            ..Config::new()
+        };
+
+        // Each file hides the lines before 13:45 on its own day
+        let mut doc = Document::new(config).unwrap();
+        for index in 0..2 {
+            doc.select_file(index);
+            let lines = doc.get_plain_lines(&(0..)).map(|line| line.line).collect::<Vec<_>>();
+            assert_eq!(lines.len(), 1, "file {index}: {lines:?}");
+            assert!(lines[0].ends_with("late\n"));
+        }
+
+        // A file with no timestamps has no day for the time, so we say so instead of showing every line
+        let untimed = temp_file("untimed.log", "no\ntimestamps\n");
+        assert!(doc.add_file(&untimed).is_err());
+        let config = Config {
+            filename: vec![untimed.clone()],
+            hide_before: Some("13:45".to_string()),
+            ..Config::new()
+        };
+        assert!(Document::new(config).is_err());
+
+        for path in [first, second, untimed] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
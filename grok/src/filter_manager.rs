@@ -0,0 +1,156 @@
+use std::io::{stdout, Write};
+use crossterm::{QueueableCommand, cursor, terminal, style, style::{Color, Stylize}};
+use crate::config::Config;
+use crate::document::{Document, FilterEntry, FilterKind};
+use crate::keyboard::UserCommand;
+
+// Widest we make the popup
+const MAX_WIDTH: usize = 100;
+
+const HELP: &str = "Space:on/off  e:edit  d:delete  !:show/hide  c:color  J/K:move  q:close";
+
+/// A popup over the log listing the filters, search and highlights of the active file, so they can be changed in place.
+/// Changes apply to the log as they are made.
+pub struct FilterManager {
+    selected: usize,
+
+    // First entry shown when there are more than fit on the screen
+    scroll: usize,
+
+    // Error from the last change, shown in place of the help line
+    message: Option<String>,
+}
+
+impl FilterManager {
+    pub fn new(_config: &Config) -> Self {
+        Self {
+            selected: 0,
+            scroll: 0,
+            message: None,
+        }
+    }
+
+    /// The expression of the selected entry, to start the edit prompt with
+    pub fn selected_pattern(&self, doc: &Document) -> Option<String> {
+        doc.filter_entries().into_iter().nth(self.selected).map(|entry| entry.pattern)
+    }
+
+    pub fn handle_command(&mut self, doc: &mut Document, cmd: &UserCommand) {
+        self.message = None;
+        let result = match cmd {
+            UserCommand::ScrollUp => {
+                self.selected = self.selected.saturating_sub(1);
+                Ok(())
+            },
+            UserCommand::ScrollDown => {
+                self.selected += 1;
+                Ok(())
+            },
+            UserCommand::FilterMoveUp => {
+                self.selected = doc.move_entry(self.selected, true);
+                Ok(())
+            },
+            UserCommand::FilterMoveDown => {
+                self.selected = doc.move_entry(self.selected, false);
+                Ok(())
+            },
+            UserCommand::FilterToggle => {
+                doc.toggle_entry(self.selected);
+                Ok(())
+            },
+            UserCommand::FilterRecolor => {
+                doc.recolor_entry(self.selected);
+                Ok(())
+            },
+            UserCommand::FilterDelete => {
+                doc.remove_entry(self.selected);
+                Ok(())
+            },
+            UserCommand::FilterInvert => doc.invert_entry(self.selected),
+            UserCommand::FilterEdit(pattern) if !pattern.is_empty() => doc.edit_entry(self.selected, pattern),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            log::error!("Invalid filter expression: {}", e);
+            self.message = Some(format!("Invalid expression: {}", e));
+        }
+    }
+
+    fn describe(entry: &FilterEntry) -> (&'static str, String) {
+        let kind = match entry.kind {
+            FilterKind::Include => "show",
+            FilterKind::Exclude => "hide",
+            FilterKind::Search => "search",
+            FilterKind::Highlight => "highlight",
+        };
+        let count = match &entry.stats {
+            Some(stats) if stats.bytes_indexed < stats.bytes_total => {
+                let indexed = stats.bytes_indexed as f64 / stats.bytes_total as f64 * 100.0;
+                format!("{} lines ({:.0}%)", stats.lines_indexed, indexed)
+            },
+            Some(stats) => format!("{} lines", stats.lines_indexed),
+            None => String::new(),
+        };
+        (kind, count)
+    }
+
+    pub fn refresh_screen(&mut self, doc: &Document) -> std::io::Result<()> {
+        let (width, height) = terminal::size().expect("Unable to get terminal size");
+        let entries = doc.filter_entries();
+        self.selected = self.selected.min(entries.len().saturating_sub(1));
+
+        // Leave a margin around us, and room for the title and help lines
+        let width = (width as usize).min(MAX_WIDTH + 4).saturating_sub(4);
+        let rows = (height as usize).saturating_sub(5).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let mut stdout = stdout();
+        let mut row = 1;
+        let mut print_row = |content: style::StyledContent<String>| -> std::io::Result<()> {
+            stdout.queue(cursor::MoveTo(2, row))?;
+            stdout.queue(style::PrintStyledContent(content))?;
+            row += 1;
+            Ok(())
+        };
+
+        print_row(fit(" Filters, searches and highlights", width).reverse())?;
+        if entries.is_empty() {
+            print_row(fit("   (none)", width).stylize())?;
+        }
+
+        for (i, entry) in entries.iter().enumerate().skip(self.scroll).take(rows) {
+            let (kind, count) = Self::describe(entry);
+            let check = if entry.enabled { "[x]" } else { "[ ]" };
+            let pattern_width = width.saturating_sub(30).max(1);
+            let line = format!(" {} {:<9} {:<pattern_width$} {:>14} ", check, kind, fit(&entry.pattern, pattern_width), count);
+            let line = fit(&line, width);
+            let line = match entry.color {
+                Some(color) if entry.enabled => line.with(color),
+                _ if !entry.enabled => line.with(Color::DarkGrey),
+                _ => line.stylize(),
+            };
+            print_row(if i == self.selected { line.reverse() } else { line })?;
+        }
+
+        let footer = match &self.message {
+            Some(message) => fit(&format!(" {}", message), width).reverse().with(Color::Red),
+            None => fit(&format!(" {}", HELP), width).reverse(),
+        };
+        print_row(footer)?;
+
+        stdout.flush()
+    }
+}
+
+// Pad or truncate text to exactly width chars
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = text.chars().take(width).collect::<String>();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
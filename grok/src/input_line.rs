@@ -1,33 +1,48 @@
 use std::path::PathBuf;
 use directories::ProjectDirs;
-use lazy_static::lazy_static;
 use reedline::{DefaultPrompt, DefaultPromptSegment, FileBackedHistory, Reedline, Signal};
 use {
     reedline::{KeyCode, KeyModifiers},
-    reedline::{default_emacs_keybindings, Emacs, ReedlineEvent},
+    reedline::{default_emacs_keybindings, EditCommand, Emacs, ReedlineEvent},
   };
 
-#[derive(Default)]
-pub struct InputLine { }
+pub struct InputLine {
+    /// Name of the file the history of this prompt is kept in
+    history: &'static str,
+}
 
 // FIXME: Make this a config option
 const HISTORY_FILE: &str = "search_history";
 
+/// History for the file names entered at the :e prompt, so they don't mix with the searches
+pub const FILE_HISTORY_FILE: &str = "file_history";
+
+impl Default for InputLine {
+    fn default() -> Self {
+        Self::with_history(HISTORY_FILE)
+    }
+}
+
+// Where we keep the history file with the given name
+fn history_path(name: &str) -> PathBuf {
+    if let Some(proj_dirs) = ProjectDirs::from("com", "Phord Software", "Felon") {
+        let mut dir = proj_dirs.config_dir().to_path_buf();
+        dir.push(name);
+        log::trace!("History path: {:?}", dir);
+        dir
+    } else {
+        // FIXME: Make this a hidden file?
+        PathBuf::from(name)
+    }
+}
+
 impl InputLine {
-    pub fn run(&mut self, prompt: &str) -> Option<String> {
+    pub fn with_history(history: &'static str) -> Self {
+        Self { history }
+    }
 
-        lazy_static! {
-            static ref HISTORY_PATH: PathBuf =
-                if let Some(proj_dirs) = ProjectDirs::from("com", "Phord Software", "Felon") {
-                    let mut dir = proj_dirs.config_dir().to_path_buf();
-                    dir.push(HISTORY_FILE);
-                    log::trace!("History path: {:?}", dir);
-                    dir
-                } else {
-                    // FIXME: Make this a hidden file?
-                    PathBuf::from(HISTORY_FILE)
-                };
-            }
+    /// Read a line from the user, starting with the given text already entered
+    pub fn run(&mut self, prompt: &str, initial: &str) -> Option<String> {
 
         let mut keybindings = default_emacs_keybindings();
         keybindings.add_binding(
@@ -38,13 +53,14 @@ impl InputLine {
         let edit_mode = Box::new(Emacs::new(keybindings));
 
         let history = Box::new(
-          FileBackedHistory::with_file(500, HISTORY_PATH.to_path_buf())
+          FileBackedHistory::with_file(500, history_path(self.history))
             .expect("Error configuring history with file"),
         );
 
         let mut line_editor = Reedline::create()
             .with_history(history)
             .with_edit_mode(edit_mode);
+        line_editor.run_edit_commands(&[EditCommand::InsertString(initial.to_string())]);
         let prompt = DefaultPrompt {
                 left_prompt: DefaultPromptSegment::Basic(prompt.to_string()),
                 .. DefaultPrompt::default()
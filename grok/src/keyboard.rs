@@ -37,6 +37,35 @@ const BASE_KEYMAP: &[(&str, UserCommand)] = &[
     ("?", UserCommand::BackwardSearchPrompt),
     ("N", UserCommand::SearchNext),
     ("Shift+N", UserCommand::SearchPrev),
+    ("Shift+T", UserCommand::ToggleDeltaTime),
+    ("Shift+L", UserCommand::ToggleLineNumbers),
+    ("Ctrl+F2", UserCommand::ToggleBookmark),
+    ("F2", UserCommand::NextBookmark),
+    ("Shift+F2", UserCommand::PrevBookmark),
+    ("Esc T", UserCommand::SetTimeReference),
+    ("Esc &", UserCommand::FilterManager),
+    ("Ctrl+C", UserCommand::Interrupt),
+];
+
+// Keys for the filter manager popup
+const FILTER_MANAGER_KEYMAP: &[(&str, UserCommand)] = &[
+    ("Up", UserCommand::ScrollUp),
+    ("K", UserCommand::ScrollUp),
+    ("Down", UserCommand::ScrollDown),
+    ("J", UserCommand::ScrollDown),
+    ("Shift+Up", UserCommand::FilterMoveUp),
+    ("Shift+K", UserCommand::FilterMoveUp),
+    ("Shift+Down", UserCommand::FilterMoveDown),
+    ("Shift+J", UserCommand::FilterMoveDown),
+    ("Space", UserCommand::FilterToggle),
+    ("Enter", UserCommand::FilterToggle),
+    ("!", UserCommand::FilterInvert),
+    ("C", UserCommand::FilterRecolor),
+    ("E", UserCommand::FilterEditPrompt),
+    ("D", UserCommand::FilterDelete),
+    ("Delete", UserCommand::FilterDelete),
+    ("Esc", UserCommand::FilterManagerClose),
+    ("Q", UserCommand::FilterManagerClose),
 ];
 
 // Additional keys for "less" compatibility
@@ -70,7 +99,6 @@ const LESS_KEYMAP: &[(&str, UserCommand)] = &[
     ("Esc >", UserCommand::SeekEndLine),
 
     ("Esc Shift+G", UserCommand::SeekEndLine),        // TODO: Different from Shift+G, which should keep searching on stdin
-    ("Esc Shift+F", UserCommand::SeekEndLine),        // TODO: Stop on pattern match
 
     // p - go to percentage point in file
     // P - go to byte offset in file
@@ -104,17 +132,23 @@ const LESS_KEYMAP: &[(&str, UserCommand)] = &[
     ("U", UserCommand::HalfPageUp),
     ("Ctrl+U", UserCommand::HalfPageUp),
 
-    // F - go to end of file and try to read more data
-    ("Shift+F", UserCommand::SeekEndLine),        // TODO: and read more data
+    // F - go to end of file and keep reading more data as it arrives; ^C or scrolling stops
+    ("Shift+F", UserCommand::FollowMode),
+
+    // ESC-F - like F, but stop on the first new line that matches the search and ring the bell
+    ("Esc Shift+F", UserCommand::FollowUntilMatch),
 
     // m <x> - bookmark first line on screen with letter given (x is any alpha, upper or lower)
     // M <x> - bookmark last line on screen with letter given
     // ' <x> - go to bookmark with letter given (and position as it was marked, at top or bottom)
-    // ^X^X <n> - got to bookmark
-    ("M", UserCommand::SetBookmarkTop),
-    ("Shift+M", UserCommand::SetBookmarkBottom),
-    ("'", UserCommand::GotoBookmark),
-    ("Ctrl+X", UserCommand::GotoBookmark),
+    // ' ' - go to the previous position;  ' ^ - go to the start of the file;  ' $ - go to the end of the file
+    // ^X^X <x> - same as '
+    // ESC-m <x> - clear the bookmark with letter given
+    ("M [A-Za-z]", UserCommand::SetBookmarkTop(' ')),
+    ("Shift+M [A-Za-z]", UserCommand::SetBookmarkBottom(' ')),
+    ("' [A-Za-z'^$]", UserCommand::GotoBookmark(' ')),
+    ("Ctrl+X Ctrl+X [A-Za-z'^$]", UserCommand::GotoBookmark(' ')),
+    ("Esc M [A-Za-z]", UserCommand::ClearBookmark(' ')),
 
     // Digits: accumulate a number argument for the next command
     ("0", UserCommand::CollectDigits(0)),
@@ -142,7 +176,6 @@ const LESS_KEYMAP: &[(&str, UserCommand)] = &[
 
     // ("Esc Ctrl+F [:print:] [:print:]", UserCommand::NextMatchingBraceCustom),
     // ("Esc Ctrl+B [:print:] [:print:]", UserCommand::PrevMatchingBraceCustom),
-    // ("Esc M [A-Za-z]", UserCommand::MarkClear),
     // ("Esc /", UserCommand::SearchNextFiles),
     // ("Esc ?", UserCommand::SearchPrevFiles),
     // ("Esc N", UserCommand::SearchNextFiles),
@@ -150,25 +183,35 @@ const LESS_KEYMAP: &[(&str, UserCommand)] = &[
     // ("Esc U", UserCommand::DisableSearchHighlight),
     // ("Esc Shift+U", UserCommand::SearchClear),
 
-    // ("' [A-Za-z'^$]", UserCommand::MarkGoto),        // Goto named mark, or "Previous", "Top" or "Bottom"
-    // ("m [A-Za-z]", UserCommand::MarkTop),
-    // ("Shift+M [A-Za-z]", UserCommand::MarkBottom),
-
-    // ("Ctrl+X Ctrl+X [A-Za-z'^$]", UserCommand::MarkGoto),        // Goto named mark, or "Previous", "Top" or "Bottom"
     // ("Ctrl+X Ctrl+V", UserCommand::AddFile),
 
     // ("Shift+E", UserCommand::AddFile),
 
-    // (": E", UserCommand::AddFile),
+    // :e <file> - examine a new file
+    // :n :p - go to the next (or previous) file; N files if N was given first
+    // :x - go to the first file, or the Nth file if N was given first
+    (": E", UserCommand::AddFilePrompt),
     // ("Shift+E", UserCommand::AddFile),
-    // (": N", UserCommand::NextFile),
-    // (": P", UserCommand::PrevFile),
-    // (": X", UserCommand::GotoFile),
+    (": N", UserCommand::NextFile),
+    (": P", UserCommand::PrevFile),
+    (": X", UserCommand::GotoFile),
+
+    // :goto <time> - go to the first line at or after the given time
+    (": G", UserCommand::GotoTimePrompt),
+
+    // :highlight <pattern> - keep highlighting a pattern in its own color
+    // :s - choose which searches n and N move between
+    (": H", UserCommand::HighlightPrompt),
+    (": S", UserCommand::SearchTarget),
     // (": D", UserCommand::RemoveFile),
 
-    // ("=", UserCommand::ShowInfo),
-    // ("Ctrl+G", UserCommand::ShowInfo),
-    // (": Shift+F", UserCommand::ShowInfo),
+    // :m - show only the bookmarked lines, or all of them again
+    (": M", UserCommand::FilterBookmarks),
+
+    // = ^G :f - show the line number of the top line and how many lines the file has
+    ("=", UserCommand::ShowInfo),
+    ("Ctrl+G", UserCommand::ShowInfo),
+    (": F", UserCommand::ShowInfo),
 
     (": Q", UserCommand::Quit),
     (": Shift+Q", UserCommand::Quit),
@@ -200,15 +243,46 @@ pub enum UserCommand {
     BackwardSearch(String),
     Filter(String),
     ForwardSearch(String),
+    FilterManager,
+    FilterManagerClose,
+    FilterToggle,
+    FilterMoveUp,
+    FilterMoveDown,
+    FilterInvert,
+    FilterRecolor,
+    FilterDelete,
+    FilterEditPrompt,
+    FilterEdit(String),
+    AddFilePrompt,
+    AddFile(String),
+    NextFile,
+    PrevFile,
+    GotoFile,
+    GotoTimePrompt,
+    GotoTime(String),
+    HighlightPrompt,
+    Highlight(String),
+    SearchTarget,
+    ToggleDeltaTime,
+    SetTimeReference,
+    ToggleLineNumbers,
+    ShowInfo,
     HalfPageDown,
     HalfPageUp,
-    GotoBookmark,
-    SetBookmarkTop,
-    SetBookmarkBottom,
+    GotoBookmark(char),
+    SetBookmarkTop(char),
+    SetBookmarkBottom(char),
+    ClearBookmark(char),
+    ToggleBookmark,
+    NextBookmark,
+    PrevBookmark,
+    FilterBookmarks,
     GotoOffset,
     GotoPercent,
     SeekStartLine,
     SeekEndLine,
+    FollowMode,
+    FollowUntilMatch,
     CollectDigits(u8),
     CollectDecimal,
     MouseScrollDown,
@@ -218,6 +292,7 @@ pub enum UserCommand {
     PageUp,
     PageUpSticky,
     Cancel,     // Cancel the current input mode
+    Interrupt,  // Stop a long-running mode, like following the end of the file
     Quit,
     RefreshDisplay,
     ScrollDown,
@@ -238,6 +313,19 @@ pub enum UserCommand {
     Chord(String),      // FIXME: Deprecated
 }
 
+impl UserCommand {
+    /// Fill in the character of a command bound to a set of characters, like "M [A-Za-z]"
+    fn with_char(&self, c: char) -> Self {
+        match self {
+            UserCommand::GotoBookmark(_) => UserCommand::GotoBookmark(c),
+            UserCommand::SetBookmarkTop(_) => UserCommand::SetBookmarkTop(c),
+            UserCommand::SetBookmarkBottom(_) => UserCommand::SetBookmarkBottom(c),
+            UserCommand::ClearBookmark(_) => UserCommand::ClearBookmark(c),
+            _ => self.clone(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,6 +337,8 @@ mod tests {
             ("Ctrl+Q", Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL))),
             ("Shift+N", Event::Key(KeyEvent::new(KeyCode::Char('n'), KeyModifiers::SHIFT))),
             ("Ctrl+Shift+PageUp", Event::Key(KeyEvent::new(KeyCode::PageUp, KeyModifiers::CONTROL | KeyModifiers::SHIFT))),
+            ("F2", Event::Key(KeyEvent::new(KeyCode::F(2), KeyModifiers::NONE))),
+            ("Shift+F12", Event::Key(KeyEvent::new(KeyCode::F(12), KeyModifiers::SHIFT))),
         ];
 
         for (input, expected) in test_cases {
@@ -294,6 +384,32 @@ mod tests {
             ("MouseWheelDown", UserCommand::MouseScrollDown),
             ("Esc V", UserCommand::PageUp),
             ("Esc >", UserCommand::SeekEndLine),
+            (": N", UserCommand::NextFile),
+            (": E", UserCommand::AddFilePrompt),
+            (": G", UserCommand::GotoTimePrompt),
+            ("Shift+T", UserCommand::ToggleDeltaTime),
+            ("Esc T", UserCommand::SetTimeReference),
+            ("Shift+L", UserCommand::ToggleLineNumbers),
+            ("=", UserCommand::ShowInfo),
+            (": F", UserCommand::ShowInfo),
+            (": H", UserCommand::HighlightPrompt),
+            (": S", UserCommand::SearchTarget),
+            ("Esc &", UserCommand::FilterManager),
+            ("M A", UserCommand::SetBookmarkTop('a')),
+            ("M Shift+Z", UserCommand::SetBookmarkTop('Z')),
+            ("Shift+M B", UserCommand::SetBookmarkBottom('b')),
+            ("' C", UserCommand::GotoBookmark('c')),
+            ("' '", UserCommand::GotoBookmark('\'')),
+            ("' $", UserCommand::GotoBookmark('$')),
+            ("Ctrl+X Ctrl+X ^", UserCommand::GotoBookmark('^')),
+            ("Esc M D", UserCommand::ClearBookmark('d')),
+            ("Ctrl+F2", UserCommand::ToggleBookmark),
+            ("F2", UserCommand::NextBookmark),
+            ("Shift+F2", UserCommand::PrevBookmark),
+            (": M", UserCommand::FilterBookmarks),
+            ("Shift+F", UserCommand::FollowMode),
+            ("Esc Shift+F", UserCommand::FollowUntilMatch),
+            ("Ctrl+C", UserCommand::Interrupt),
         ];
 
         for (key_str, expected_cmd) in test_cases {
@@ -303,6 +419,80 @@ mod tests {
                 None => panic!("Keymap missing entry for: {}", key_str),
             }
         }
+
+        // Only the characters in the set, and nothing on less's tag key
+        for key_str in ["M 1", "' Shift+4", "T"] {
+            let events = KeyCodes::parse(key_str).unwrap();
+            assert!(!reader.keymap.keymap.contains_key(&events), "Unexpected keymap entry: {}", key_str);
+        }
+    }
+
+    #[test]
+    fn test_expand_chars() {
+        let test_cases = [
+            ("M", vec![("M".to_string(), None)]),
+            ("Esc [", vec![("Esc [".to_string(), None)]),
+            ("M [a-cX]", vec![
+                ("M a".to_string(), Some('a')),
+                ("M b".to_string(), Some('b')),
+                ("M c".to_string(), Some('c')),
+                ("M Shift+X".to_string(), Some('X')),
+            ]),
+            ("[-'^$]", vec![
+                ("-".to_string(), Some('-')),
+                ("'".to_string(), Some('\'')),
+                ("^".to_string(), Some('^')),
+                ("$".to_string(), Some('$')),
+            ]),
+        ];
+
+        for (key_str, expected) in test_cases {
+            assert_eq!(KeyMap::expand_chars(key_str), expected, "Testing key set: {}", key_str);
+        }
+    }
+
+    #[test]
+    fn test_goto_time_typed_in_full() {
+        use crate::search_prompt::SearchPromptMode;
+        use indexed_file::time_stamper::parse_user_time;
+
+        // Typing ":goto" opens the prompt at the "g" and leaves the rest of the line for it
+        let mut reader = Reader::new();
+        let mut keys = ":goto 2024-05-01 13:45".chars();
+        let cmd = keys.by_ref()
+            .map(|c| reader.process_event(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))))
+            .find(|cmd| *cmd != UserCommand::PartialChord);
+        assert_eq!(cmd, Some(UserCommand::GotoTimePrompt));
+
+        let cmd = SearchPromptMode::GotoTime.command(keys.as_str());
+        assert_eq!(cmd, UserCommand::GotoTime("2024-05-01 13:45".to_string()));
+        if let UserCommand::GotoTime(time) = cmd {
+            assert!(parse_user_time(&time, None, None).is_some());
+        }
+
+        // Or the time follows ":g" directly
+        for (text, expected) in [(" 13:45", "13:45"), ("oto", ""), ("otofu", "otofu")] {
+            let cmd = SearchPromptMode::GotoTime.command(text);
+            assert_eq!(cmd, UserCommand::GotoTime(expected.to_string()), "Testing prompt text: {}", text);
+        }
+    }
+
+    #[test]
+    fn test_filter_manager_keymap() {
+        let reader = Reader::from(KeyMap::new(FILTER_MANAGER_KEYMAP));
+        let test_cases = [
+            ("J", UserCommand::ScrollDown),
+            ("Shift+K", UserCommand::FilterMoveUp),
+            ("Space", UserCommand::FilterToggle),
+            ("!", UserCommand::FilterInvert),
+            ("E", UserCommand::FilterEditPrompt),
+            ("Esc", UserCommand::FilterManagerClose),
+        ];
+
+        for (key_str, expected_cmd) in test_cases {
+            let events = KeyCodes::parse(key_str).unwrap();
+            assert_eq!(reader.keymap.keymap.get(&events), Some(&expected_cmd), "Testing keymap entry: {}", key_str);
+        }
     }
 
     #[test]
@@ -489,8 +679,15 @@ impl KeyMap {
     fn new(mappings: &[(&str, UserCommand)]) -> Self {
         let mut keymap = HashMap::new();
 
+        let mappings = mappings.iter().flat_map(|(key_str, cmd)| {
+            Self::expand_chars(key_str).into_iter().map(move |(key_str, c)| match c {
+                Some(c) => (key_str, cmd.with_char(c)),
+                None => (key_str, cmd.clone()),
+            })
+        });
+
         for (key_str, cmd) in mappings {
-            let events = match KeyCodes::parse(key_str) {
+            let events = match KeyCodes::parse(&key_str) {
                 Ok(events) => events,
                 Err(e) => {
                     log::error!("Error parsing key combo: {}", e);
@@ -515,7 +712,7 @@ impl KeyMap {
             }
 
             // Add the full sequence
-            keymap.insert(events, cmd.clone());
+            keymap.insert(events, cmd);
         }
 
         Self {
@@ -523,6 +720,39 @@ impl KeyMap {
         }
     }
 
+    // Expand a key combo ending in a set of characters, like "M [A-Za-z]", into one combo for each character in the
+    // set, with the character it stands for.  Other key combos are returned as they are.
+    fn expand_chars(key_str: &str) -> Vec<(String, Option<char>)> {
+        let (prefix, last) = match key_str.rsplit_once(' ') {
+            Some((prefix, last)) => (format!("{prefix} "), last),
+            None => (String::new(), key_str),
+        };
+        let Some(set) = last.strip_prefix('[').and_then(|set| set.strip_suffix(']')).filter(|set| !set.is_empty()) else {
+            return vec![(key_str.to_string(), None)];
+        };
+
+        let set = set.chars().collect::<Vec<_>>();
+        let mut chars = Vec::new();
+        let mut i = 0;
+        while i < set.len() {
+            if i + 2 < set.len() && set[i + 1] == '-' {
+                chars.extend(set[i]..=set[i + 2]);
+                i += 3;
+            } else {
+                chars.push(set[i]);
+                i += 1;
+            }
+        }
+
+        chars.into_iter()
+            .map(|c| {
+                // Our key names are not case sensitive, so capitals need Shift
+                let key = if c.is_ascii_uppercase() { format!("Shift+{c}") } else { c.to_string() };
+                (prefix.clone() + &key, Some(c))
+            })
+            .collect()
+    }
+
     fn extend(&mut self, keymap: KeyMap) {
         self.keymap.extend(keymap.keymap);
     }
@@ -578,9 +808,8 @@ impl KeyCodes {
                 k => {
                     if k.len() == 1 {
                         Some(KeyCode::Char(k.chars().next().unwrap()))
-                    } else if k.len() > 1 && k.starts_with("F") && k.len() < 4 {
-                        // FIXME: handle errors
-                        Some(KeyCode::F(k[1..].parse().unwrap()))
+                    } else if k.len() > 1 && k.starts_with('f') && k.len() < 4 {
+                        k[1..].parse().ok().map(KeyCode::F)
                     } else {
                         None
                     }
@@ -726,6 +955,15 @@ impl Input {
         Ok(())
     }
 
+    /// Input for the filter manager popup, which has its own keys
+    pub fn filter_manager(config: &Config) -> Self {
+        Self {
+            reader: Reader::from(KeyMap::new(FILTER_MANAGER_KEYMAP)),
+            started: false,
+            mouse: config.mouse,
+        }
+    }
+
     pub fn reset_chord(&mut self) {
         self.reader.reset_chord();
     }
@@ -5,13 +5,40 @@ pub mod keyboard;
 pub mod styled_text;
 pub mod document;
 pub mod status_line;
+pub mod filter_manager;
 pub mod search_prompt;
 pub mod input_line;
 pub mod user_input;
 
 use config::Config;
+use directories::ProjectDirs;
 use viewer::Viewer;
 
+const TIME_FORMATS_FILE: &str = "timestamps";
+
+// Load user-defined timestamp formats from the file given on the command line, or else from our config dir if present
+fn load_time_formats(cfg: &Config) {
+    let path = match &cfg.time_formats {
+        Some(path) => path.clone(),
+        None => {
+            let Some(proj_dirs) = ProjectDirs::from("com", "Phord Software", "Felon") else { return };
+            let path = proj_dirs.config_dir().join(TIME_FORMATS_FILE);
+            if !path.exists() {
+                return;
+            }
+            path
+        },
+    };
+
+    match indexed_file::time_stamper::load_user_formats(&path) {
+        Ok(count) => log::trace!("Loaded {} timestamp formats from {:?}", count, path),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 pub fn run() -> std::io::Result<()> {
     let cfg = match Config::from_env() {
         Ok(cfg) => cfg,
@@ -27,7 +54,18 @@ pub fn run() -> std::io::Result<()> {
         std::process::exit(1);
     }
 
-    let mut viewer = Viewer::new(cfg);
+    load_time_formats(&cfg);
+    if let Some(kb) = cfg.buffers {
+        indexed_file::files::set_stream_memory_limit(kb.saturating_mul(1024));
+    }
+
+    let mut viewer = match Viewer::new(cfg) {
+        Ok(viewer) => viewer,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
     viewer.start()?;
 
     while viewer.run()? {}
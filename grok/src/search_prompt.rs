@@ -5,12 +5,41 @@ use crate::keyboard::UserCommand;
 use crate::user_input::UserInput;
 use crossterm::{QueueableCommand, cursor, terminal};
 use crate::styled_text::styled_line::RGB_BLACK;
-use crate::input_line::InputLine;
+use crate::input_line::{InputLine, FILE_HISTORY_FILE};
 
 pub enum SearchPromptMode {
     Forward,
     Backward,
     Filter,
+    AddFile,
+    GotoTime,
+    Highlight,
+    EditFilter,
+}
+
+impl SearchPromptMode {
+    /// The command for the text the user entered at the prompt
+    pub fn command(&self, text: &str) -> UserCommand {
+        let text = text.to_string();
+        match self {
+            SearchPromptMode::Forward => UserCommand::ForwardSearch(text),
+            SearchPromptMode::Backward => UserCommand::BackwardSearch(text),
+            SearchPromptMode::Filter => UserCommand::Filter(text),
+            SearchPromptMode::AddFile => UserCommand::AddFile(text),
+            SearchPromptMode::GotoTime => UserCommand::GotoTime(goto_argument(&text).to_string()),
+            SearchPromptMode::Highlight => UserCommand::Highlight(text),
+            SearchPromptMode::EditFilter => UserCommand::FilterEdit(text),
+        }
+    }
+}
+
+/// The time after ":g", skipping the rest of the command name if the user typed ":goto" in full
+fn goto_argument(text: &str) -> &str {
+    let text = match text.strip_prefix("oto") {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => rest,
+        _ => text,
+    };
+    text.trim()
 }
 
 pub struct Search {
@@ -24,27 +53,37 @@ impl Search {
             SearchPromptMode::Forward => "/",
             SearchPromptMode::Backward => "?",
             SearchPromptMode::Filter => "&/",
+            SearchPromptMode::AddFile => "Examine: ",
+            // The user may type the rest of the command name after ":g"
+            SearchPromptMode::GotoTime => ":g",
+            SearchPromptMode::Highlight => ":highlight ",
+            SearchPromptMode::EditFilter => "Edit: ",
         };
 
+        let mut prompt = SearchPrompt::new(config, prompt_string);
+        if let SearchPromptMode::AddFile = mode {
+            prompt.history = Some(FILE_HISTORY_FILE);
+        }
+
         Self {
-            prompt: SearchPrompt::new(config, prompt_string),
+            prompt,
             mode,
         }
     }
+
+    /// Prompt with some text already entered for the user to edit
+    pub fn edit(config: &Config, mode: SearchPromptMode, text: &str) -> Self {
+        let mut search = Self::new(config, mode);
+        search.prompt.initial = text.to_string();
+        search
+    }
 }
 
 impl UserInput for Search {
     // Note: timeout is ignored because our string input does not timeout yet.  This is a blocking call.
     fn get_command(&mut self, _timeout: u64) -> std::io::Result<UserCommand> {
         match self.prompt.run() {
-            Some(srch) => {
-                let srch = srch.trim_end_matches('\r').to_string();
-                match self.mode {
-                    SearchPromptMode::Forward => Ok(UserCommand::ForwardSearch(srch)),
-                    SearchPromptMode::Backward => Ok(UserCommand::BackwardSearch(srch)),
-                    SearchPromptMode::Filter => Ok(UserCommand::Filter(srch)),
-                }
-            },
+            Some(srch) => Ok(self.mode.command(srch.trim_end_matches('\r'))),
             None => Ok(UserCommand::Cancel),
         }
     }
@@ -58,6 +97,10 @@ impl UserInput for Search {
 pub struct SearchPrompt {
     color: bool,
     prompt: String,
+    initial: String,
+
+    /// History to recall from instead of the searches
+    history: Option<&'static str>,
 }
 
 impl SearchPrompt {
@@ -65,6 +108,8 @@ impl SearchPrompt {
         let mut sp = Self {
             color: config.color,
             prompt: prompt.to_string(),
+            initial: String::new(),
+            history: None,
         };
         sp.start().expect("Unable to start search prompt");
         sp
@@ -88,8 +133,11 @@ impl SearchPrompt {
     }
 
     pub fn run(&mut self) -> Option<String> {
-        let mut input_line = InputLine::default();
-        input_line.run(&self.prompt)
+        let mut input_line = match self.history {
+            Some(history) => InputLine::with_history(history),
+            None => InputLine::default(),
+        };
+        input_line.run(&self.prompt, &self.initial)
     }
 
 }
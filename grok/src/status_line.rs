@@ -6,12 +6,16 @@ use crate::document::Document;
 
 pub struct StatusLine {
     color: bool,
+
+    /// Message shown until the next command
+    message: Option<String>,
 }
 
 impl StatusLine {
     pub fn new(config: &Config) -> Self {
         Self {
             color: config.color,
+            message: None,
         }
     }
 
@@ -20,15 +24,34 @@ impl StatusLine {
         1
     }
 
-    pub fn refresh_screen(&mut self, doc: &mut Document) -> std::io::Result<()> {
+    /// Show a message at the start of the status line until the next command
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn clear_message(&mut self) {
+        self.message = None;
+    }
+
+    pub fn refresh_screen(&mut self, doc: &mut Document, following: bool) -> std::io::Result<()> {
         let (width, height) = terminal::size().expect("Unable to get terminal size");
 
         // FIXME: Don't print the status line again if nothing changed
 
         // status line:   curr_line of total_lines | "search": hit of total (hidden) | "filter": hit of total (hidden)
         let mut stdout = stdout();
+        let files = if doc.file_count() > 1 {
+            Some(format!("{} (file {} of {})", doc.file_name(), doc.active_file() + 1, doc.file_count()))
+        } else {
+            None
+        };
         let message =
-            std::iter::once(format!("Bytes: {}  {}", doc.len(), doc.describe_pending()))
+            self.message.clone().into_iter()
+            .chain(files)
+            .chain(std::iter::once(format!("Bytes: {}  {}", doc.len(), doc.describe_pending())))
+            .chain(doc.describe_search_target())
+            .chain(doc.describe_bookmarks())
+            .chain(following.then(|| "Waiting for data... (^C to stop)".to_string()))
             .chain(doc.info()
                 .map(|stats| {
                     let indexed = stats.bytes_indexed as f64 / doc.len() as f64 * 100.0;
@@ -142,11 +142,10 @@ impl<LOG: IndexedLog> DoubleEndedIterator for GrokLineIterator<'_, LOG> {
                 self.rev.from(&self.fwd, self.range.end);
             }
         }
-        if let Some(line) = self.rev.next_back() {
-            if line.offset >= self.range.start {
-                self.range = self.range.start..line.offset;
-                return Some(line);
-            }
+        if let Some(line) = self.rev.next_back()
+            && line.offset >= self.range.start {
+            self.range = self.range.start..line.offset;
+            return Some(line);
         }
         None
     }
@@ -164,11 +163,10 @@ impl<LOG: IndexedLog> Iterator for GrokLineIterator<'_, LOG> {
                 self.fwd.from(&self.rev, self.range.start);
             }
         }
-        if let Some(line) = self.fwd.next() {
-            if line.offset < self.range.end {
-                self.range = line.offset.saturating_add(1)..self.range.end;
-                return Some(line)
-            }
+        if let Some(line) = self.fwd.next()
+            && line.offset < self.range.end {
+            self.range = line.offset.saturating_add(1)..self.range.end;
+            return Some(line)
         }
         None
     }
@@ -239,6 +239,7 @@ pub enum PattColor {
     Info,
     NoCrumb,
     Module(Color),
+    Mark(Color),    // Highlighted search
 }
 
 /// Line section coloring
@@ -264,6 +265,7 @@ fn to_style(patt: PattColor) -> ContentStyle {
         PattColor::Info => style.with(Color::White).on(RGB_BLACK),
         PattColor::NoCrumb => style.with(Color::White).on(RGB_BLACK), // .italic(),
         PattColor::Module(c) => style.with(c).on(RGB_BLACK).bold(),
+        PattColor::Mark(c) => style.with(Color::Black).on(c),
     }
 }
 
@@ -129,13 +129,12 @@ impl Stylist {
                 let matched = capture.get(0).unwrap();
                 styled.apply(matched.as_str(), matched.range(), style.pattern);
                 for group_name in style.matcher.capture_names().flatten() {
-                    if let Some(group) = capture.name(group_name) {
-                        if let Some(patt) = self.named_styles.get(group_name) {
-                            let range = group.range();
-                            if !itertools::any(&named_ranges, |r: &std::ops::Range<usize>| r.contains(&range.start) || range.contains(&r.start)) {
-                                styled.apply(group.as_str(), group.range(), *patt);
-                                named_ranges.push(group.range());
-                            }
+                    if let Some(group) = capture.name(group_name)
+                        && let Some(patt) = self.named_styles.get(group_name) {
+                        let range = group.range();
+                        if !itertools::any(&named_ranges, |r: &std::ops::Range<usize>| r.contains(&range.start) || range.contains(&r.start)) {
+                            styled.apply(group.as_str(), group.range(), *patt);
+                            named_ranges.push(group.range());
                         }
                     }
                 }
@@ -166,6 +165,7 @@ pub enum StyleSource {
 #[derive(PartialEq)]
 pub enum StyleReason {
     Search,
+    Highlight,
     Config,
     Builtin,
 }
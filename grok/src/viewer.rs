@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::display::Display;
+use crate::filter_manager::FilterManager;
 use crate::status_line::StatusLine;
 use crate::search_prompt::{Search, SearchPromptMode};
 use crate::keyboard::{Input, UserCommand};
@@ -13,19 +14,23 @@ pub struct Viewer {
     modalinput: Box<dyn UserInput>,
     doc: Document,
     fill_timeout: u64,
+
+    // Popup for editing the filters, when it is open
+    filter_manager: Option<FilterManager>,
 }
 
 impl Viewer {
-    pub fn new(config: Config) -> Self {
-        let doc = Document::new(config.clone());
-        Self {
+    pub fn new(config: Config) -> Result<Self, String> {
+        let doc = Document::new(config.clone())?;
+        Ok(Self {
             _config: config.clone(),
             display: Display::new(config.clone()),
             status: StatusLine::new(&config),
             modalinput: Box::new(Input::new(&config)),
             doc,
             fill_timeout: 0,
-        }
+            filter_manager: None,
+        })
     }
 
     // Begin owning the terminal
@@ -51,24 +56,42 @@ impl Viewer {
         self.display.refresh_screen(&mut self.doc)?;
 
         // FIXME: Only refresh status if modalinput is still Input
-        self.status.refresh_screen(&mut self.doc)?;
+        self.status.refresh_screen(&mut self.doc, self.display.following())?;
+
+        if let Some(manager) = &mut self.filter_manager {
+            manager.refresh_screen(&self.doc)?;
+        }
 
         let cmd = self.modalinput.get_command(event_timeout)?;
         match cmd {
             UserCommand::None => { self.fill_timeout += 3; },
-            _ => {  self.fill_timeout = 0; log::trace!("Got command: {:?}", cmd); }
+            _ => {  self.fill_timeout = 0; self.status.clear_message(); log::trace!("Got command: {:?}", cmd); }
         };
 
+        if self.filter_manager.is_some() {
+            self.run_filter_manager(cmd);
+            return Ok(true);
+        }
+
         match &cmd {
             UserCommand::Quit => return Ok(false),
 
+            UserCommand::FilterManager => {
+                self.modalinput.stop().expect("Failed to stop modal input");
+                self.modalinput = Box::new(Input::filter_manager(&self._config));
+                self.filter_manager = Some(FilterManager::new(&self._config));
+            },
+
             // Begin prompts
-            UserCommand::ForwardSearchPrompt | UserCommand::BackwardSearchPrompt | UserCommand::FilterPrompt => {
+            UserCommand::ForwardSearchPrompt | UserCommand::BackwardSearchPrompt | UserCommand::FilterPrompt | UserCommand::AddFilePrompt | UserCommand::GotoTimePrompt | UserCommand::HighlightPrompt => {
                 self.modalinput.stop().expect("Failed to stop modal input");
                 let mode = match cmd {
                     UserCommand::ForwardSearchPrompt => SearchPromptMode::Forward,
                     UserCommand::BackwardSearchPrompt => SearchPromptMode::Backward,
                     UserCommand::FilterPrompt => SearchPromptMode::Filter,
+                    UserCommand::AddFilePrompt => SearchPromptMode::AddFile,
+                    UserCommand::GotoTimePrompt => SearchPromptMode::GotoTime,
+                    UserCommand::HighlightPrompt => SearchPromptMode::Highlight,
                     _ => unreachable!(),
                 };
                 self.modalinput = Box::new(Search::new(&self._config, mode));
@@ -86,21 +109,70 @@ impl Viewer {
                 self.display.set_filter(&mut self.doc, filt);
                 self.display.handle_command(UserCommand::RefreshDisplay);
             },
+            UserCommand::AddFile(path) => {
+                // A new file is shown by add_file; otherwise redraw the one we were on
+                let opened = !path.is_empty() && self.display.add_file(&mut self.doc, path);
+                if !opened {
+                    self.display.handle_command(UserCommand::RefreshDisplay);
+                }
+            },
+            UserCommand::Highlight(pattern) => {
+                self.display.add_highlight(&mut self.doc, pattern);
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+            UserCommand::SearchTarget => {
+                self.doc.next_search_target();
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+            UserCommand::GotoTime(time) => {
+                // goto_time scrolls to the time it finds; otherwise stay where we were
+                let found = !time.is_empty() && self.display.goto_time(&mut self.doc, time);
+                if !found {
+                    self.display.handle_command(UserCommand::RefreshDisplay);
+                }
+            },
             UserCommand::Cancel => {
                 self.display.handle_command(UserCommand::RefreshDisplay);
             },
+            UserCommand::FollowUntilMatch => {
+                self.display.follow_until_match(&self.doc);
+            },
+            UserCommand::ShowInfo => {
+                let info = self.doc.describe_line(self.display.top_line());
+                self.status.show_message(info);
+            },
+            UserCommand::SetBookmarkTop(c) | UserCommand::SetBookmarkBottom(c) => {
+                let bottom = matches!(cmd, UserCommand::SetBookmarkBottom(_));
+                self.display.set_mark(&mut self.doc, *c, bottom);
+            },
+            UserCommand::ToggleBookmark => {
+                self.display.toggle_bookmark(&mut self.doc);
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+            UserCommand::ClearBookmark(c) => {
+                self.doc.clear_mark(*c);
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+            UserCommand::FilterBookmarks => {
+                self.doc.toggle_bookmarks_only();
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
             _ => {},
         }
 
         match cmd {
-            UserCommand::ForwardSearchPrompt | UserCommand::BackwardSearchPrompt | UserCommand::FilterPrompt => {
+            UserCommand::ForwardSearchPrompt | UserCommand::BackwardSearchPrompt | UserCommand::FilterPrompt | UserCommand::AddFilePrompt | UserCommand::GotoTimePrompt | UserCommand::HighlightPrompt => {
                 // FIXME: Move this special-handling down into Display?
             },
             // Prompt finish cleanup
-            UserCommand::Cancel | UserCommand::ForwardSearch(_) | UserCommand::BackwardSearch(_) | UserCommand::Filter(_) => {
+            UserCommand::Cancel | UserCommand::ForwardSearch(_) | UserCommand::BackwardSearch(_) | UserCommand::Filter(_) | UserCommand::AddFile(_) | UserCommand::GotoTime(_) | UserCommand::Highlight(_) => {
                 self.modalinput = Box::new(Input::new(&self._config));
             },
 
+            UserCommand::SearchTarget | UserCommand::FollowUntilMatch | UserCommand::ShowInfo => {},
+            UserCommand::SetBookmarkTop(_) | UserCommand::SetBookmarkBottom(_) | UserCommand::ToggleBookmark
+                | UserCommand::ClearBookmark(_) | UserCommand::FilterBookmarks => {},
+
             // Forward everything else to display
             _ => self.display.handle_command(cmd),
         }
@@ -108,6 +180,37 @@ impl Viewer {
         Ok(true)
     }
 
+    // Commands while the filter manager popup is open
+    fn run_filter_manager(&mut self, cmd: UserCommand) {
+        let Some(manager) = &mut self.filter_manager else { return };
+        match cmd {
+            UserCommand::None | UserCommand::PartialChord => {},
+            UserCommand::TerminalResize => self.display.handle_command(cmd),
+            UserCommand::ScrollUp | UserCommand::ScrollDown => manager.handle_command(&mut self.doc, &cmd),
+            UserCommand::FilterManagerClose => {
+                self.filter_manager = None;
+                self.modalinput = Box::new(Input::new(&self._config));
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+            UserCommand::FilterEditPrompt => {
+                if let Some(pattern) = manager.selected_pattern(&self.doc) {
+                    self.modalinput.stop().expect("Failed to stop modal input");
+                    self.modalinput = Box::new(Search::edit(&self._config, SearchPromptMode::EditFilter, &pattern));
+                }
+            },
+            // Finished or cancelled the edit prompt
+            UserCommand::FilterEdit(_) | UserCommand::Cancel => {
+                manager.handle_command(&mut self.doc, &cmd);
+                self.modalinput = Box::new(Input::filter_manager(&self._config));
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+            _ => {
+                manager.handle_command(&mut self.doc, &cmd);
+                self.display.handle_command(UserCommand::RefreshDisplay);
+            },
+        }
+    }
+
 }
 
 impl Drop for Viewer {
@@ -76,7 +76,7 @@ mod sub_line_iterator_helper {
         &STYLIST_WHOLE
     }
 
-    pub(crate) fn new<LOG: IndexedLog>(log: &mut LOG) -> GrokLineIterator<LOG> {
+    pub(crate) fn new<LOG: IndexedLog>(log: &mut LOG) -> GrokLineIterator<'_, LOG> {
         GrokLineIterator::new(log, stylist_whole())
     }
 
@@ -501,7 +501,7 @@ mod sub_line_wrap_tests {
         &STYLIST_WRAP
     }
 
-    fn wrapped_new(log: &mut Log, _width: usize) -> GrokLineIterator<Log> {
+    fn wrapped_new(log: &mut Log, _width: usize) -> GrokLineIterator<'_, Log> {
         GrokLineIterator::new(log, stylist_wrap())
     }
 
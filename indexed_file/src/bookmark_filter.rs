@@ -0,0 +1,27 @@
+use std::collections::BTreeSet;
+
+/**
+ * Filter that shows only the lines the user bookmarked.
+ *
+ * We are given the offsets of the lines, so like the TimeFilter we never scan the log to find them. We step from one
+ * bookmark to the next and let the other filters decide whether each one is shown.
+ */
+pub(crate) struct BookmarkFilter {
+    lines: BTreeSet<usize>,
+}
+
+impl BookmarkFilter {
+    pub fn new(lines: BTreeSet<usize>) -> Self {
+        Self { lines }
+    }
+
+    /// The first bookmark at or after offset
+    pub fn next(&self, offset: usize) -> Option<usize> {
+        self.lines.range(offset..).next().copied()
+    }
+
+    /// The last bookmark before offset
+    pub fn next_back(&self, offset: usize) -> Option<usize> {
+        self.lines.range(..offset).next_back().copied()
+    }
+}
@@ -0,0 +1,154 @@
+// Test lines against all of our filters and searches at once
+//
+// Each filter and search keeps its own index of the lines it matches, but filling them one at a time means a scan of
+// the whole file for each one.  Instead we fold all of their expressions into one RegexSet, so each line is read and
+// scanned once, and then we update every index from the result.
+
+use std::ops::Range;
+
+use regex::RegexSet;
+
+use crate::index_filter::LineMatcher;
+
+/// The index a test result belongs to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Target {
+    /// A filter, by its place in the FilterSet
+    Filter(usize),
+
+    /// A search, by its place in the LogStack
+    Search(usize),
+}
+
+/// The lines each target matched in a span of the log
+pub(crate) struct Matches {
+    /// The lines we tested, from the start of the first one to the end of the last one
+    pub span: Range<usize>,
+
+    /// The lines matching each target, in the order of CombinedFilter::targets
+    pub lines: Vec<Vec<Range<usize>>>,
+}
+
+impl Matches {
+    pub fn new(targets: usize, start: usize) -> Self {
+        Self {
+            span: start..start,
+            lines: vec![Vec::new(); targets],
+        }
+    }
+}
+
+/// Filters and searches combined to test each line in one pass
+pub(crate) struct CombinedFilter {
+    /// The filters, then the searches
+    targets: Vec<Target>,
+
+    /// Whether each filter shows the lines it matches (true) or hides the lines it doesn't
+    includes: Vec<bool>,
+
+    set: RegexSet,
+
+    /// For each target, its pattern in the set if it has one, and whether it wants the lines that don't match it
+    tests: Vec<(Option<usize>, bool)>,
+}
+
+impl CombinedFilter {
+    /// Combine the enabled filters and the searches.  Searches only match the lines the filters show.
+    pub fn new(filters: &[(Target, &LineMatcher, bool)], searches: &[(Target, &LineMatcher)]) -> Result<Self, regex::Error> {
+        let mut patterns = Vec::new();
+        let mut tests = Vec::new();
+        let matchers = filters.iter().map(|(_, matcher, _)| *matcher)
+            .chain(searches.iter().map(|(_, matcher)| *matcher));
+        for matcher in matchers {
+            let (pattern, negate) = matcher.set_pattern();
+            let index = pattern.map(|pattern| {
+                patterns.push(pattern);
+                patterns.len() - 1
+            });
+            tests.push((index, negate));
+        }
+
+        Ok(Self {
+            targets: filters.iter().map(|(target, ..)| *target)
+                .chain(searches.iter().map(|(target, _)| *target))
+                .collect(),
+            includes: filters.iter().map(|(_, _, include)| *include).collect(),
+            set: RegexSet::new(patterns)?,
+            tests,
+        })
+    }
+
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Test one line without its line ending, adding its range to the matches of each target it matches
+    pub fn test(&self, line: &str, range: &Range<usize>, matches: &mut Matches) {
+        matches.span.end = range.end;
+        let found = self.set.matches(line);
+        let passes = |&(index, negate): &(Option<usize>, bool)| index.is_none_or(|index| found.matched(index)) != negate;
+
+        let filters = self.includes.len();
+        let mut included = false;
+        let mut excluded = false;
+        for ((test, include), lines) in self.tests.iter().zip(&self.includes).zip(matches.lines.iter_mut()) {
+            let shown = passes(test);
+            if shown {
+                lines.push(range.clone());
+            }
+            if *include {
+                included |= shown;
+            } else {
+                excluded |= !shown;
+            }
+        }
+
+        // A line is shown if it matches any include filter, or if there are none, and no exclude filter hides it
+        let has_includes = self.includes.iter().any(|include| *include);
+        if (included || !has_includes) && !excluded {
+            for (test, lines) in self.tests[filters..].iter().zip(matches.lines[filters..].iter_mut()) {
+                if passes(test) {
+                    lines.push(range.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index_filter::{IndexFilter, SearchType};
+
+    fn matcher(pattern: &str) -> LineMatcher {
+        IndexFilter::new(SearchType::new(pattern).unwrap(), 0, true).matcher().clone()
+    }
+
+    #[test]
+    fn test_combined_filter() {
+        let (apple, crumble, pie) = (matcher("apple|cherry"), matcher("!crumble"), matcher("pie"));
+        let combined = CombinedFilter::new(
+            &[(Target::Filter(0), &apple, true), (Target::Filter(2), &crumble, false)],
+            &[(Target::Search(0), &pie)],
+        ).unwrap();
+        assert_eq!(combined.targets(), &[Target::Filter(0), Target::Filter(2), Target::Search(0)]);
+
+        let mut matches = Matches::new(3, 0);
+        let mut offset = 0;
+        for line in ["apple pie", "banana split", "apple crumble", "cherry pie"] {
+            let range = offset..offset + line.len() + 1;
+            combined.test(line, &range, &mut matches);
+            offset = range.end;
+        }
+        assert_eq!(matches.span, 0..48);
+        assert_eq!(matches.lines, vec![
+            vec![0..10, 23..37, 37..48],
+            vec![0..10, 10..23, 37..48],
+            vec![0..10, 37..48],
+        ]);
+    }
+}
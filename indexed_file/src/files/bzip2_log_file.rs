@@ -0,0 +1,169 @@
+// Reader of compressed bzip2 files
+use bzip2::{Decompress, Status};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::fs::File;
+
+use crate::files::compressed_file::{CompressedFile, Decoder};
+
+pub type Bzip2LogFile = CompressedFile<Bzip2Decoder<BufReader<File>>>;
+
+// Compressed bytes to read at a time
+const INPUT_CHUNK: usize = 64 * 1024;
+
+// Decompressed bytes to produce at a time
+const OUTPUT_CHUNK: usize = 256 * 1024;
+
+// "BZh" and a block size from 1 to 9
+fn is_stream_header(buf: &[u8]) -> bool {
+    buf.len() >= 4 && buf[..3] == *b"BZh" && (b'1'..=b'9').contains(&buf[3])
+}
+
+/**
+ * Decoder for bzip2 files, using bzip2 streams as breadcrumbs.
+ *
+ * bzip2 compresses blocks of up to 900KB independently, but the blocks are not aligned to bytes and their sizes
+ * are not recorded anywhere, so we can't begin decoding from one.  Parallel compressors like pbzip2 and lbzip2
+ * write many streams instead, which we can find as we decode the file.  Files written by plain `bzip2` have only
+ * one stream, which we can only read from the start.
+ */
+pub struct Bzip2Decoder<R> {
+    /// The source (compressed) file reader
+    file: R,
+
+    /// Compressed input we read from the file, and the physical offset of its start
+    input: Vec<u8>,
+    input_pos: usize,
+    input_start: u64,
+
+    /// The stream decoder, or None when we finished the stream
+    decomp: Option<Decompress>,
+}
+
+impl<R: Read + Seek> Bzip2Decoder<R> {
+    // Read more compressed input if we used up what we had.  Returns false at the end of the file.
+    fn fill_input(&mut self) -> std::io::Result<bool> {
+        if self.input_pos < self.input.len() {
+            return Ok(true);
+        }
+        self.input_start += self.input.len() as u64;
+        self.input.resize(INPUT_CHUNK, 0);
+        let count = self.file.read(&mut self.input)?;
+        self.input.truncate(count);
+        self.input_pos = 0;
+        Ok(count > 0)
+    }
+}
+
+impl<R: Read + Seek> Decoder for Bzip2Decoder<R> {
+    type Reader = R;
+    type Context = ();
+
+    fn is_recognized(file: &mut R) -> bool {
+        // Check the magic number
+        let mut buf = [0; 4];
+        file.read_exact(&mut buf).is_ok() && is_stream_header(&buf)
+    }
+
+    fn new(file: R) -> std::io::Result<Self> {
+        Ok(Self {
+            file,
+            input: Vec::new(),
+            input_pos: 0,
+            input_start: 0,
+            decomp: None,
+        })
+    }
+
+    fn begin(&mut self, physical: u64, _context: Option<&()>) -> std::io::Result<bool> {
+        self.file.seek(SeekFrom::Start(physical))?;
+        self.input.clear();
+        self.input_pos = 0;
+        self.input_start = physical;
+
+        // Anything after the last stream is ignored
+        self.fill_input()?;
+        if !is_stream_header(&self.input) {
+            self.decomp = None;
+            return Ok(false);
+        }
+        self.decomp = Some(Decompress::new(false));
+        Ok(true)
+    }
+
+    fn decode(&mut self) -> std::io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(OUTPUT_CHUNK);
+        while output.is_empty() {
+            if self.decomp.is_none() {
+                break;
+            }
+            if !self.fill_input()? {
+                log::warn!("bzip2 file is truncated at {}", self.input_start + self.input_pos as u64);
+                self.decomp = None;
+                break;
+            }
+
+            let decomp = self.decomp.as_mut().unwrap();
+            let total_in = decomp.total_in();
+            let status = decomp.decompress_vec(&self.input[self.input_pos..], &mut output)
+                .map_err(|e| std::io::Error::other(format!("Error in the bzip2 decoder: {:?}", e)))?;
+            self.input_pos += (decomp.total_in() - total_in) as usize;
+            if let Status::StreamEnd = status {
+                self.decomp = None;
+            }
+        }
+        Ok(output)
+    }
+
+    fn physical(&mut self) -> std::io::Result<u64> {
+        Ok(self.input_start + self.input_pos as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::Stream;
+    use bzip2::{write::BzEncoder, Compression};
+    use std::io::{Cursor, Write};
+
+    type Bzip2Cursor = CompressedFile<Bzip2Decoder<Cursor<Vec<u8>>>>;
+
+    fn make_text(lines: usize, seed: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..lines {
+            writeln!(text, "{:08} line {} of the test {:x}", i, seed, i * 7919 % 1000003).unwrap();
+        }
+        text
+    }
+
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_bzip2_streams() {
+        let parts = [make_text(20000, 1), make_text(10, 2), make_text(30000, 3)];
+        let compressed = parts.iter().flat_map(|part| bzip2(part)).collect::<Vec<_>>();
+        let text = parts.concat();
+        let mut file = Bzip2Cursor::new(Cursor::new(compressed)).unwrap();
+        assert!(file.len() < text.len());
+
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+        assert_eq!(file.len(), text.len());
+
+        // One breadcrumb at the start of each stream
+        let logical = file.frames.iter().map(|frame| frame.logical as usize).collect::<Vec<_>>();
+        assert_eq!(logical, vec![0, parts[0].len(), parts[0].len() + parts[1].len()]);
+
+        for pos in [parts[0].len() + 5, 100, text.len() - 10] {
+            file.seek(SeekFrom::Start(pos as u64)).unwrap();
+            let mut buf = vec![0; 1000];
+            let count = file.read(&mut buf).unwrap();
+            assert_eq!(&buf[..count], &text[pos..(pos + 1000).min(text.len())], "read at {}", pos);
+        }
+    }
+}
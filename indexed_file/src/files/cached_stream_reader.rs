@@ -1,10 +1,12 @@
-/**
+/*
  * CachedStreamReader is a non-blocking stream reader that implements Read, BufRead and Seek. It
  * supports Stdin from a terminal or a redirect, and pipes. A better name might be UnboundedReadBuffer because that
  * is how it functions internally to provide Seek and non-blocking Read.
  *
- * Random seeks are supported by keeping a copy of all the data ever received from stdin in memory. This is possibly
- * wasteful on some systems that already cache the stdin data somewhere, but it can't be helped in any portable way.
+ * Random seeks are supported by keeping a copy of all the data ever received from stdin. This is possibly wasteful on
+ * some systems that already cache the stdin data somewhere, but it can't be helped in any portable way. We keep the
+ * newest data in memory, up to a limit set with set_stream_memory_limit(). Beyond that, we spill the oldest data to an
+ * anonymous temp file and read it back from there when it's needed again.
  *
  * It is non-blocking because, unlike reading from Stdin, when we try to read past the end of the available data, we
  * do not block waiting for more data to arrive to fulfill the read. Instead, we return a short read (or no bytes) as
@@ -23,17 +25,67 @@
  * more data as well, thus throttling the whole pipeline if needed.
  */
 
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, Write};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::TryRecvError;
 use std::{thread, time};
 
+use crate::files::stream_decoder::decode_stream;
+
 const QUEUE_SIZE:usize = 100;
 const READ_THRESHOLD:usize = 10240;
 
+// Bytes to read back from the spool file at a time
+const SPOOL_PAGE:usize = 1024 * 1024;
+
+// Most stream data each reader keeps in memory
+static MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(512 * 1024 * 1024);
+
+/// Set how much data each stream keeps in memory before it spills the oldest of it to a temp file.
+/// Applies to streams opened after this.
+pub fn set_stream_memory_limit(bytes: usize) {
+    MEMORY_LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+// Create a temp file that disappears when we close it
+fn spool_file() -> std::io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("felon-{}-{}.spool", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+    let path = std::env::temp_dir().join(name);
+    let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+
+    // Unix frees the space when we close the file.  Windows refuses to remove an open file, so it stays behind there.
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::warn!("Unable to remove spool file {:?}: {}", path, e);
+    }
+    Ok(file)
+}
+
+// Read from the spool file without moving its cursor
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
 pub trait Stream {
     /// Returns current length of file/stream
     fn len(&self) -> usize;
@@ -53,21 +105,45 @@ pub trait Stream {
 
     /// Check if empty
     fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Number of times the source was truncated.  Data we read from it before then is gone.
+    fn truncations(&self) -> usize { 0 }
 }
 
 pub struct CachedStreamReader {
+    /// The newest data we received, which follows the data in the spool file
     buffer: Vec<u8>,
     rx: Option<Receiver<Vec<u8>>>,
     pos:u64,
+
+    /// Most data we keep in memory before we spill the oldest of it to the spool file
+    limit: usize,
+
+    /// Temp file holding the oldest data, once we have received too much to keep in memory
+    spool: Option<File>,
+    spooled: usize,
+
+    /// Data we read back from the spool file, and its offset
+    page: Vec<u8>,
+    page_start: usize,
 }
 
 impl CachedStreamReader {
-    pub fn new(pipe: Option<&PathBuf>) -> std::io::Result<Self> {
-        let base = Self {
+    fn empty() -> Self {
+        Self {
             rx: None,
             buffer: Vec::default(),
             pos: 0,
-        };
+            limit: MEMORY_LIMIT.load(Ordering::Relaxed),
+            spool: None,
+            spooled: 0,
+            page: Vec::default(),
+            page_start: 0,
+        }
+    }
+
+    pub fn new(pipe: Option<&PathBuf>) -> std::io::Result<Self> {
+        let base = Self::empty();
 
         let stream = if let Some(pipe) = pipe {
             Self {
@@ -87,8 +163,7 @@ impl CachedStreamReader {
     pub fn from_reader<LOG: BufRead + Send + 'static>(pipe: LOG) -> std::io::Result<Self> {
         let mut stream = Self {
             rx: Some(Self::reader(Some(pipe))),
-            buffer: Vec::default(),
-            pos: 0,
+            ..Self::empty()
         };
 
         // Try to init some read
@@ -147,27 +222,75 @@ impl CachedStreamReader {
         }
     }
 
+    // Move the oldest data to the spool file if we have too much in memory, keeping the newest half of our limit
+    fn spill(&mut self) -> std::io::Result<()> {
+        if self.buffer.len() <= self.limit {
+            return Ok(());
+        }
+        let count = self.buffer.len() - self.limit / 2;
+        let spool = match &mut self.spool {
+            Some(spool) => spool,
+            None => self.spool.insert(spool_file()?),
+        };
+        spool.seek(SeekFrom::End(0))?;
+        spool.write_all(&self.buffer[..count])?;
+        self.buffer.drain(..count);
+        self.spooled += count;
+        Ok(())
+    }
+
+    // The data we have at our position, without waiting for more
+    fn buffered(&mut self) -> std::io::Result<&[u8]> {
+        let pos = self.pos as usize;
+        if pos >= self.spooled {
+            return Ok(&self.buffer[pos - self.spooled..]);
+        }
+
+        if !(self.page_start..self.page_start + self.page.len()).contains(&pos) {
+            self.page.resize(SPOOL_PAGE.min(self.spooled - pos), 0);
+            read_exact_at(self.spool.as_ref().unwrap(), &mut self.page, pos as u64)?;
+            self.page_start = pos;
+        }
+        Ok(&self.page[pos - self.page_start..])
+    }
+
     fn reader<LOG: BufRead + Send + 'static>(pipe: Option<LOG>) -> Receiver<Vec<u8>>
     {
         // Use a bounded channel to prevent stdin from running away from us
         let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(QUEUE_SIZE);
         thread::spawn(move || {
-            let mut rdr: Box<dyn std::io::BufRead> = match pipe {
+            let rdr: Box<dyn std::io::BufRead> = match pipe {
                 Some(file) => Box::new(file),
                 None => Box::new(std::io::stdin().lock()),
             };
+            // Decompress the stream if it's compressed
+            let mut rdr = match decode_stream(rdr) {
+                Ok(rdr) => rdr,
+                Err(e) => {
+                    log::warn!("Unable to read the stream: {}", e);
+                    return;
+                }
+            };
             loop {
-                if let Ok(buf) = rdr.as_mut().fill_buf() {
-                    let bytes = buf.len();
-                    if bytes == 0 {
-                        break
-                    }
+                match rdr.as_mut().fill_buf() {
+                    Ok(buf) => {
+                        let bytes = buf.len();
+                        if bytes == 0 {
+                            break
+                        }
 
-                    let buf = buf.to_vec();
-                    if tx.send(buf).is_err() {
-                        break  // Broken pipe?
-                    }
-                    rdr.consume(bytes);
+                        let buf = buf.to_vec();
+                        if tx.send(buf).is_err() {
+                            break  // Broken pipe?
+                        }
+                        rdr.consume(bytes);
+                    },
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                    Err(e) => {
+                        // Corrupt compressed data, most likely.  Keep what we decoded so far.
+                        log::warn!("Error reading the stream: {}", e);
+                        break
+                    },
                 }
             }
         });
@@ -177,7 +300,7 @@ impl CachedStreamReader {
 
 impl Stream for CachedStreamReader {
     fn len(&self) -> usize {
-        self.buffer.len()
+        self.spooled + self.buffer.len()
     }
 
     fn is_open(&self) -> bool {
@@ -197,6 +320,10 @@ impl Stream for CachedStreamReader {
             };
             if let Some(mut data) = data {
                 self.buffer.append(&mut data);
+                if let Err(e) = self.spill() {
+                    log::warn!("Unable to spill stream data to a temp file; keeping it all in memory: {}", e);
+                    self.limit = usize::MAX;
+                }
             } else {
                 // queue is drained or we timed out
                 break
@@ -206,7 +333,7 @@ impl Stream for CachedStreamReader {
                 break
             }
         }
-        self.buffer.len()
+        self.len()
     }
 }
 
@@ -215,11 +342,16 @@ impl Read for CachedStreamReader {
         // Blocking read
         let start = self.pos as usize;
         self.fill_buffer(start + buf.len());
-        let len = buf.len().min(self.len().saturating_sub(start));
-        if len > 0 {
-            let end = start + len;
-            buf[..len].copy_from_slice(&self.buffer[start..end]);
-            self.pos = self.pos.saturating_add(len as u64);
+        let mut len = 0;
+        while len < buf.len() {
+            let data = self.buffered()?;
+            let bytes = data.len().min(buf.len() - len);
+            if bytes == 0 {
+                break;
+            }
+            buf[len..len + bytes].copy_from_slice(&data[..bytes]);
+            self.consume(bytes);
+            len += bytes;
         }
         Ok(len)
     }
@@ -261,10 +393,42 @@ impl Seek for CachedStreamReader {
 impl BufRead for CachedStreamReader {
     fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
         self.fill_buffer(self.pos as usize);
-        Ok(&self.buffer[self.pos as usize..])
+        self.buffered()
     }
 
     fn consume(&mut self, amt: usize) {
         self.pos += amt as u64;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_spill_to_spool_file() {
+        let text = (0..20000).map(|i| format!("line {}\n", i)).collect::<String>();
+        // Send it in small chunks so we spill as they arrive after we set our limit
+        let input = BufReader::with_capacity(1000, Cursor::new(text.clone().into_bytes()));
+        let mut stream = CachedStreamReader::from_reader(input).unwrap();
+        stream.limit = 10000;
+        stream.wait_for_end();
+        assert_eq!(stream.len(), text.len());
+        assert!(stream.spooled > 0);
+        assert!(stream.buffer.len() <= stream.limit);
+
+        // Reads cross from the spool file into memory
+        let mut all = String::new();
+        stream.read_to_string(&mut all).unwrap();
+        assert_eq!(all, text);
+
+        for pos in [text.len() - 100, 5, stream.spooled - 3, 70000] {
+            stream.seek(SeekFrom::Start(pos as u64)).unwrap();
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            let expected = text[pos..].split_inclusive('\n').next().unwrap();
+            assert_eq!(line, expected, "line at {}", pos);
+        }
+    }
+}
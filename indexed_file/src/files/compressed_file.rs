@@ -1,75 +1,124 @@
-// Currently just zstd compression
-use ruzstd;
+// Random-access reader of compressed files.  The formats themselves are handled by a Decoder for each one.
+use std::fs::File;
 use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
-
-use ruzstd::frame::ReadFrameHeaderError;
-use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoderError};
-use ruzstd::decoding::block_decoder;
-use ruzstd::frame::read_frame_header;
+use std::path::PathBuf;
 
 use crate::files::Stream;
 
-/// FrameInfo holds information about individual frames in a compressed file.
+/// Breadcrumb holds information about subsections of the compressed data we can seek to.
 ///
-/// Frames are useful because they can be decoded independently from each other. This means we can position
-/// the input file to the start of any frame and decompress from there. Thus we can treat a compressed file
-/// made of multiple frames as one logical decompressed file supporting random-access reads if we know the
-/// compressed and decompressed sizes of all the frames.  This structure holds that information for a single
-/// frame.
+/// Some formats support these breadcrumbs natively (like gzip members, zstd frames or xz blocks).  But even when they
+/// are supported, they're not always available. These kinds of crumbs are ideal because they let us decompress
+/// sections of the file independently from others, making random read access very fast.
 ///
-/// In practice this is only useful if we have a file compressed with multiple frames. Ideally each frame
-/// also stores its decompressed size in the block headers.  However, this (decomp size) is optional. If it
-/// is not available, the only way to find it is to decompress the frames to calculate it, which is slow.
+/// If the native breadcrumbs are missing or unsupported, we may be able to save some decompressor context
+/// information the first time we decompress the file, and then use this context later to decompress just a
+/// subsection again.
 ///
-/// By convention here, if the len == 0 it means we have not determined the length of this frame yet. Therefore
-/// we won't know the logical position of any frames after this one. Since we currently need to know the logical
-/// position of each chunk we process elsewhere, we don't bother recording any frames after an "unknown" frame
-/// until the unknown one can be replaced with a known (len) value.
+/// If context resume is not supported, we may have to decompress the whole segment again to get to the right
+/// spot. This is slow, but it's better than not being able to seek at all.  The read_buffer struct helps by
+/// preserving part of a decompressed file for this kind of navigation.
 ///
-/// scan_frames() will attempt to index the whole file by recording the FrameInfo for every frame in the
-/// file. If a frame's uncompressed size is unknown, we stop scanning and leave the rest of the index
-/// "unknown". As we decode frames through normal reads, we will learn the length of each frame and we can
-/// fill in the missing information (len). We will then push a new unknown frame size into the index
-/// representing the new unknown frontier of the logical space in frames.
+/// Decoder::scan_frames() will attempt to index the whole file by recording every native breadcrumb in the
+/// file. If a crumb's uncompressed size is unknown, we stop scanning and leave the rest of the index
+/// "unknown". As we decode data through normal reads, we will learn the length of each crumb and we can
+/// fill in the missing information (len). We will then push a new unknown crumb into the index
+/// representing the new unknown frontier of the logical space.
+pub struct Breadcrumb<C> {
+    // The physical offset of the start of the crumb in the compressed file
+    pub physical: u64,
+
+    // The logical offset of the decoded data in the decompressed file for this crumb
+    pub logical: u64,
+
+    // The length of the decompressed data in this crumb in bytes, if known. Zero means frontier.
+    pub len: u64,
+
+    // Decoder state to resume from, or None if the crumb is the start of a segment
+    pub context: Option<C>,
+}
+
+impl<C> Breadcrumb<C> {
+    pub fn new(physical: u64, logical: u64, len: u64) -> Self {
+        Self { physical, logical, len, context: None }
+    }
+}
+
+/// A Decoder handles one compression format for CompressedFile.
 ///
-/// In the future we may represent this unknown space differently to allow for alternate forms of traversal
-/// of the file data.
-struct FrameInfo{
-    // The offset of the start of the frame in the compressed file
-    physical: u64,
+/// Decoders read the compressed file in segments, which are the parts of the file that can be decoded
+/// independently of each other, like zstd frames, gzip members, xz blocks, bzip2 streams or lz4 frames.
+pub trait Decoder: Sized {
+    /// The compressed source file
+    type Reader: Read + Seek;
+
+    /// Saved decoder state to resume decoding from the middle of a segment, for formats that support it
+    type Context: Clone;
+
+    /// Check the magic number at the start of the file
+    fn is_recognized(file: &mut Self::Reader) -> bool;
+
+    fn new(file: Self::Reader) -> std::io::Result<Self>;
+
+    /// Record the segments we can find without decoding them, up to the first one whose length is unknown.
+    /// That one, if any, is last and has len == 0.
+    fn scan_frames(&mut self) -> std::io::Result<Vec<Breadcrumb<Self::Context>>> {
+        Ok(Vec::new())
+    }
 
-    // The offset of the decoded data in the decompressed file for this frame
-    logical: u64,
+    /// Prepare to decode the segment at the physical offset, or to resume from a context saved there.
+    /// Returns false if there is no segment there, as at the end of the file.
+    fn begin(&mut self, physical: u64, context: Option<&Self::Context>) -> std::io::Result<bool>;
 
-    // The length of the decompressed data in this frame
-    len: u64,
+    /// Decode the next bytes of the segment. Returns an empty buffer at the end of the segment.
+    fn decode(&mut self) -> std::io::Result<Vec<u8>>;
+
+    /// Offset in the compressed file of the next byte to decode, or of the end of the segment once decode is done
+    fn physical(&mut self) -> std::io::Result<u64>;
+
+    /// Save our state to resume from here later, if the format allows it
+    fn checkpoint(&self) -> Option<Self::Context> {
+        None
+    }
 }
 
-mod read_buffer;
+pub(crate) mod read_buffer;
 use read_buffer::ReadBuffer;
 
-// TODO: Rename this to something Zstd specific
-// TODO: Refactor a CompressedFile trait to allow for other compression types
-// TODO: Use miniz_oxide for a zlib implementation
+// How much to decode between saved contexts on the first pass through the file
+const CHECKPOINT_SPACING: u64 = 16 * 1024 * 1024;
 
-pub struct CompressedFile<R> {
-    /// The source (compressed) file reader
-    file: R,
+/**
+ * A seekable reader of compressed files that doesn't hold the decompressed file in memory.
+ *
+ * We don't always know the decompressed size of a file until we have decoded all of it.  Until then our length is
+ * an estimate based on the compressed bytes we haven't decoded yet, which grows as we read further.
+ */
+pub struct CompressedFile<D: Decoder> {
+    /// The format decoder, which owns the source file
+    pub(crate) decoder: D,
 
     /// The size of the compressed file in bytes
     source_bytes: u64,
 
-    /// The zstd frame reader and decoder
-    decoder: ruzstd::FrameDecoder,
-
     /// Sorted logical -> physical file offsets
-    frames: Vec<FrameInfo>,
+    pub(crate) frames: Vec<Breadcrumb<D::Context>>,
+
+    /// Logical bytes to decode between saved decoder contexts
+    pub(crate) spacing: u64,
 
-    /// The last frame we seeked to
-    cur_frame: usize,
+    /// The furthest (physical, logical) position we have decoded
+    decoded: (u64, u64),
+
+    /// Logical offset of the next byte the decoder produces
+    out_pos: u64,
+
+    /// True when the decoder has no more segments to read
+    at_eof: bool,
 
     /// Logical position in file (decompressed bytes position)
     pos: u64,
@@ -77,350 +126,249 @@ pub struct CompressedFile<R> {
     /// Logical position in decode stream
     seek_pos: Option<u64>,
 
-    /// Buffer for BufRead
+    /// Buffer for BufRead. Its end is always out_pos.
     read_buffer: ReadBuffer,
 }
 
-impl<R> CompressedFile<R> {
-    /// Find the indexed frame that holds or is closest to a given uncompressed offset
-    fn lookup_frame_index(&self, pos: u64) -> usize {
-        // Avoid binary-search lookup if target frame is near the current_frame
-        const NEARBY:usize = 2;
-        let start = if pos < self.pos { self.cur_frame.saturating_sub(NEARBY) } else { self.cur_frame };
-        let end =  if pos > self.pos { (start + NEARBY).min(self.frames.len()) } else { self.cur_frame };
-        let mut index = end;
-        for ind in start..end {
-            let frame = &self.frames[ind];
-            let frame_range = frame.logical..frame.logical+frame.len;
-            if frame_range.contains(&pos) {
-                index = ind;
-                break;
-            }
-        }
-
-        if index == end {
-            // Did not find it. Search the slow way.
+impl<D: Decoder<Reader = BufReader<File>>> CompressedFile<D> {
+    pub fn from_path(filename: &PathBuf) -> std::io::Result<Self> {
+        Self::open(filename)?.ok_or_else(|| std::io::Error::other("Unrecognized file type".to_string()))
+    }
 
-            index = match self.frames.binary_search_by_key(&pos, |f| f.logical) {
-                Err(n) => n - 1,
-                Ok(n) => n,
-            };
+    /// Open the file if it has our magic number, or return None if it doesn't.  Once the magic number matches, any
+    /// error is an error in this format.
+    pub fn open(filename: &PathBuf) -> std::io::Result<Option<Self>> {
+        let mut file = BufReader::new(File::open(filename)?);
+        if D::is_recognized(&mut file) {
+            Self::new(file).map(Some)
+        } else {
+            Ok(None)
         }
-        index
     }
 }
 
-impl<R: Read + Seek> CompressedFile<R> {
-    pub fn new(mut file: R) -> std::io::Result<Self> {
-        // TODO: Return error if no file or not known type
-        // let file = File::open(path)?;
+impl<D: Decoder> CompressedFile<D> {
+    pub fn new(mut file: D::Reader) -> std::io::Result<Self> {
         let source_bytes = file.seek(SeekFrom::End(0))?;
         file.seek(SeekFrom::Start(0))?;
-        let decoder = ruzstd::FrameDecoder::new();
+        let mut decoder = D::new(file)?;
+
+        // Read all native breadcrumbs we can find into frames.
+        let mut frames = decoder.scan_frames()?;
+        if frames.is_empty() {
+            frames.push(Breadcrumb::new(0, 0, 0));
+        }
+        let last = frames.last().unwrap();
+        let decoded = (last.physical, last.logical);
 
         let mut cf = Self {
-            file,
-            source_bytes,
             decoder,
+            source_bytes,
+            frames,
+            spacing: CHECKPOINT_SPACING,
+            decoded,
+            out_pos: 0,
+            at_eof: false,
             pos: 0,
             seek_pos: None,
-            frames: Vec::new(),
-            cur_frame: 0,
             read_buffer: ReadBuffer::new(0),
         };
-
-        // Read all physical frame sizes into self.frames.
-        cf.scan_frames().expect("File is valid zstd file");
-
-        cf.file.seek(SeekFrom::Start(0))?;
-
+        cf.goto_frame(0)?;
         Ok(cf)
     }
 
-    pub fn is_recognized(mut file: R) -> bool {
-        if file.seek(SeekFrom::Start(0)).is_err() {
-            false
-        } else {
-            match read_frame_header(&mut file) {
-                Ok((frame, _bytes_read)) => {
-                    frame.check_valid().is_ok()
-                },
-                _ => false,
-            }
-        }
+    pub fn is_recognized(mut file: D::Reader) -> bool {
+        file.seek(SeekFrom::Start(0)).is_ok() && D::is_recognized(&mut file)
     }
 
-    // Scan all the zstd frame headers in the file and record their positions and sizes, if known
-    fn scan_frames(&mut self) -> Result<(), ReadFrameHeaderError> {
-        let mut pos = 0;
-
-        let mut fpos = 0;
-        while fpos < self.source_bytes {
-            // Starting a new frame.  Record details.
-            let (uncompressed_bytes, frame_bytes) = self.skip_frame()?;
-            match uncompressed_bytes {
-                None => {
-                    // No point continuing the scan because we don't know the uncompressed size
-                    // Leave an empty marker for the last physical frame position
-                    let frame = FrameInfo { physical: fpos, logical: pos, len: 0};
-                    self.frames.push(frame);
-                    break
-                },
-                Some(0) => { /* Skippable; no action */ },
-                Some(size) => {
-                    let frame = FrameInfo { physical: fpos, logical: pos, len: size};
-                    // eprintln!("Frame @ {fpos} holds {pos} to {}", pos+size);
-                    self.frames.push(frame);
-                    pos += size;
-                }
-            }
-            fpos += frame_bytes;
-            assert_eq!(fpos, { self.file.stream_position().unwrap() });
-        }
-        Ok(())
+    /// Find the indexed frame that holds or is closest to a given uncompressed offset
+    fn lookup_frame_index(&self, pos: u64) -> usize {
+        self.frames.partition_point(|frame| frame.logical <= pos).saturating_sub(1)
     }
 
-    fn skip_frame(&mut self) -> Result<(Option<u64>, u64), ReadFrameHeaderError> {
-        match read_frame_header(&mut self.file) {
-            Err(ReadFrameHeaderError::SkipFrame(_magic_num, skip_size,)) => {
-                self.file.seek(SeekFrom::Current(skip_size as i64)).unwrap();
-                // Skipped a frame with no uncompressible bytes
-                // FIXME: Magic number "4" is the size of the frame header we parsed. read_frame_header should tell us that.
-                Ok((Some(0), 4u64 + skip_size as u64))
-            }
-            Ok((frame, bytes_read)) => {
-                // Started a new frame. Skip all the blocks.
-                let mut bytes_read = bytes_read as u64;
-                let mut block_dec = block_decoder::new();
-                loop {
-                    let (block_header, block_header_size) = block_dec
-                        .read_block_header(&mut self.file)
-                        .map_err(FrameDecoderError::FailedToReadBlockHeader).expect("TODO: Map error to some common err");
-
-                    // block_header.decompressed_size is usually filled only after decoding the block  :-(
-                    bytes_read += block_header_size as u64;
-                    self.file.seek(SeekFrom::Current(block_header.content_size as i64)).unwrap();
-                    bytes_read += block_header.content_size as u64;
-                    if block_header.last_block {
-                        break;
-                    }
-                }
-                if frame.header.descriptor.content_checksum_flag() {
-                    self.file.seek(SeekFrom::Current(4)).unwrap();
-                    bytes_read += 4;
-                }
-                // Return the uncompressed size or None if we don't know
-                let uncompressed_bytes = match frame.header.frame_content_size() {
-                    Ok(size) => Some(size),
-                    Err(_) => None,
-                };
-                Ok((uncompressed_bytes, bytes_read))
-            },
-            Err(other) => {
-                // Some error.  Quit early.
-                Err(other)
-            },
-        }
+    fn has_file_size(&self) -> bool {
+        let last = self.frames.last().unwrap();
+        last.len != 0 || last.physical >= self.source_bytes
     }
 
     // Position to the start of a different frame because of an explicit seek()
-    fn goto_frame(&mut self, index: usize) {
+    fn goto_frame(&mut self, index: usize) -> std::io::Result<()> {
         let frame = &self.frames[index];
-
-        // Position file to start of frame
-        if self.file.stream_position().unwrap() != frame.physical {
-            self.file.seek(SeekFrom::Start(frame.physical)).expect("Seek does not fail");
-        }
-        // reset read_buffer
-        if frame.logical != self.read_buffer.end() {
-            self.read_buffer = ReadBuffer::new(frame.logical);
+        let logical = frame.logical;
+        let found = self.decoder.begin(frame.physical, frame.context.as_ref())?;
+        self.out_pos = logical;
+        self.read_buffer = ReadBuffer::new(logical);
+        self.at_eof = false;
+        if !found {
+            self.end_of_file();
         }
-
-        self.pos = frame.logical;
-        self.begin_frame();
-        self.cur_frame = index;
+        Ok(())
     }
 
-    fn has_file_size(&self) -> bool {
-        self.frames.last().unwrap().len != 0
+    // Close the last frame at out_pos and start a new unknown frontier frame after it
+    fn push_frontier(&mut self, physical: u64, context: Option<D::Context>) {
+        let last = self.frames.last_mut().unwrap();
+        last.len = self.out_pos - last.logical;
+        self.frames.push(Breadcrumb { physical, logical: self.out_pos, len: 0, context });
     }
-    // Update last frame if we just decoded the last byte
-    fn end_frame(&mut self) {
-        // We may not be on the last frame in the index, but we only update the last frame.  We assume that we are not
-        // decoding some earlier frame because we cannot know the logical offset of any frame after the unknown frontier one.
-        let frame = self.frames.last_mut().unwrap();
-        if frame.len == 0 {
-            let logical_pos = self.pos + self.decoder.can_collect() as u64;
-            if logical_pos > frame.logical {
-                frame.len = logical_pos - frame.logical;
-
-                // Push a new last-unknown-frame if we're not at EOF yet
-                let fpos = self.file.stream_position().unwrap();
-                assert!(fpos > frame.physical);
-
-                if fpos < self.source_bytes {
-                    self.frames.push(FrameInfo { physical: fpos, logical: logical_pos, len: 0 } );
-                }
-            }
+
+    // Save the decoder context periodically on the first pass through a long segment
+    fn add_checkpoint(&mut self) {
+        let last = self.frames.last().unwrap();
+        if last.len == 0 && self.out_pos >= last.logical.saturating_add(self.spacing)
+                && let Some(context) = self.decoder.checkpoint() {
+            self.push_frontier(self.decoded.0, Some(context));
         }
     }
 
-    // Parse a frame header and automatically skip over Skippable Frames
-    fn begin_frame(&mut self) {
-        while self.file.stream_position().unwrap() < self.source_bytes {
-            match self.decoder.reset(&mut self.file) {
-                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame(
-                    _magic_num,
-                    skip_size,
-                ))) => {
-                    self.file.seek(SeekFrom::Current(skip_size as i64)).unwrap();
-                    // TODO: If last self.frame points to us, we should move it to point to the next frame instead.
-                    continue;
-                }
-                Ok(_) => {
-                    break
-                },
-                other => {
-                    // FIXME: Report this error upstream
-                    other.unwrap(); // Report the error and panic
-                    break
-                },
+    // Record the end of a segment if it's new to us, and begin the next one
+    fn end_segment(&mut self) -> std::io::Result<()> {
+        let physical = self.decoder.physical()?;
+        let last = self.frames.last_mut().unwrap();
+        if last.len == 0 && self.out_pos >= last.logical {
+            if self.out_pos > last.logical {
+                self.push_frontier(physical, None);
+            } else {
+                // Nothing was decoded after the frontier crumb. Move it past the end of this segment.
+                last.physical = physical;
+                last.context = None;
             }
+            self.decoded = (physical, self.out_pos);
         }
-    }
 
-    fn apply_seek(&mut self) -> Result<(), std::io::Error> {
-        if let Some(pos) = self.seek_pos {
-            // Forget this for next time
-            self.seek_pos = None;
+        // The next segment starts where this one ended, unless we know a better place
+        let next = self.frames.partition_point(|frame| frame.logical < self.out_pos);
+        let start = match self.frames.get(next) {
+            Some(frame) if frame.logical == self.out_pos && frame.context.is_none() => frame.physical,
+            _ => physical,
+        };
+        if !self.decoder.begin(start, None)? {
+            self.end_of_file();
+        }
+        Ok(())
+    }
 
-            if pos == self.pos {
-                // no-op
-                return Ok(())
-            }
+    fn end_of_file(&mut self) {
+        self.at_eof = true;
 
-            // Move to a new position
-            if self.read_buffer.seek_to(pos) {
-                // Found pos in read_buffer.  All done.
-                self.pos = pos;
-                // TODO: Adjust cur_frame to match?
+        // Anything after the frontier is not compressed data we can use
+        let count = self.frames.len();
+        let last = self.frames.last_mut().unwrap();
+        if last.len == 0 && last.logical == self.out_pos {
+            if count > 1 {
+                self.frames.pop();
             } else {
-                let index = self.lookup_frame_index(pos);
-
-                let frame = &self.frames[index];
-                let frame_range = frame.logical..frame.logical+frame.len;
-                assert!(frame_range.contains(&pos) || frame.len == 0);
-                if pos < self.pos || !frame_range.contains(&self.pos) {
-                    // Open a new frame
-                    self.goto_frame(index);
-                }
-
-                if pos > self.pos {
-                    // We're in the right frame, but we're behind
-                    self.skip_bytes(pos - self.pos)?;
-                    assert_eq!(pos, self.pos, "seek pos is outside of file range");
-                }
-                assert_eq!(pos, self.pos);
+                last.physical = self.source_bytes;
             }
         }
-        Ok(())
     }
 
-    // Ok(true) at eof
-    fn decode_more_bytes(&mut self) -> Result<bool, std::io::Error> {
-        loop {
-            if self.decoder.can_collect() > 0 {
-                // You've already got bytes.  Go away.
-                return Ok(false)
-            } else if self.decoder.is_finished() {
-                if self.file.stream_position().unwrap() >= self.source_bytes {
-                    // EOF
-                    return Ok(true)
-                }
-                // Start a new frame
-                self.begin_frame();
-            } else {
-                // Decode more bytes
-                match self.decoder.decode_blocks(&mut self.file, BlockDecodingStrategy::UptoBlocks(1)) {
-                    Ok(_) => {
-                        if self.decoder.is_finished() {
-                            // Reached end of frame
-                            self.end_frame();
-                        }
-                    }
-                    Err(e) => {
-                        let err = std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("Error in the zstd decoder: {:?}", e),
-                        );
-                        return Err(err);
-                    }
-                }
+    // Decode the next bytes of the file.  Returns an empty buffer at the end of the file.
+    fn decode_more(&mut self) -> std::io::Result<Vec<u8>> {
+        while !self.at_eof {
+            let data = self.decoder.decode()?;
+            if data.is_empty() {
+                self.end_segment()?;
+                continue;
+            }
+
+            self.out_pos += data.len() as u64;
+            if self.out_pos > self.decoded.1 {
+                self.decoded = (self.decoder.physical()?, self.out_pos);
+                self.add_checkpoint();
             }
+            return Ok(data);
         }
+        Ok(Vec::new())
     }
 
-    fn skip_bytes(&mut self, count:u64) -> Result<(), std::io::Error> {
-        let target = self.pos + count;
-        while self.pos < target {
-            self.decode_into_buffer()?;
-            let avail = self.read_buffer.remaining().min(target - self.pos);
-            if avail > 0 {
-                self.pos += avail;
-                self.read_buffer.consume(avail);
+    fn apply_seek(&mut self) -> std::io::Result<()> {
+        let Some(pos) = self.seek_pos.take() else { return Ok(()) };
+        if pos == self.pos {
+            // no-op
+            return Ok(());
+        }
+
+        // Move to a new position
+        if self.read_buffer.seek_to(pos) {
+            // Found pos in read_buffer.  All done.
+            self.pos = pos;
+            return Ok(());
+        }
+
+        // Go back to the nearest breadcrumb if the target is behind us, or if it's closer than where we are
+        let index = self.lookup_frame_index(pos);
+        if pos < self.out_pos || self.frames[index].logical > self.out_pos {
+            self.goto_frame(index)?;
+        }
+
+        // Decode up to the target
+        self.read_buffer = ReadBuffer::new(self.out_pos);
+        while self.out_pos <= pos {
+            let start = self.out_pos;
+            let data = self.decode_more()?;
+            if data.is_empty() {
+                break;
             }
+            self.read_buffer = ReadBuffer::new(start);
+            self.read_buffer.extend(data);
+        }
+        self.pos = pos.min(self.out_pos);
+        if !self.read_buffer.seek_to(self.pos) {
+            self.read_buffer = ReadBuffer::new(self.out_pos);
         }
         Ok(())
     }
 
     // Decode more bytes from the compressed source into our buffer if needed
-    fn decode_into_buffer(&mut self) -> Result<(), std::io::Error> {
+    fn decode_into_buffer(&mut self) -> std::io::Result<()> {
         const BUFFER_THRESHOLD_EDGE:u64 = 40 * 1024;
         const BUFFER_THRESHOLD_CAPACITY:u64 = 10 * 1024 * 1024;
-        if self.read_buffer.remaining() < BUFFER_THRESHOLD_EDGE {
-            self.decode_more_bytes()?;
-            if self.decoder.can_collect() > 0 {
-                if let Some(buffer) = self.decoder.collect() {
-                    // Add more bytes to our internal buffer
-                    self.read_buffer.extend(buffer);
-
-                    // TODO: Add a test to ensure this bounding works as expected
-                    // Discard start of buffer if we're well past it now
-                    let cap = BUFFER_THRESHOLD_CAPACITY;
-                    // TODO: Push this down into ReadBuffer::extend()
-                    if self.read_buffer.len() > cap as usize * 3
-                            && self.read_buffer.consumed >= cap * 2 {
-                        self.read_buffer.discard_front(cap);
-                    }
-                }
+        while self.read_buffer.remaining() < BUFFER_THRESHOLD_EDGE {
+            let data = self.decode_more()?;
+            if data.is_empty() {
+                break;
             }
+            self.read_buffer.extend(data);
+        }
+
+        // TODO: Add a test to ensure this bounding works as expected
+        // Discard start of buffer if we're well past it now
+        let cap = BUFFER_THRESHOLD_CAPACITY;
+        // TODO: Push this down into ReadBuffer::extend()
+        if self.read_buffer.len() > cap as usize * 3
+                && self.read_buffer.consumed >= cap * 2 {
+            self.read_buffer.discard_front(cap);
         }
         Ok(())
     }
 
     // Move the stream position to read more bytes at current logical pos
-    fn update_stream(&mut self) -> Result<(), std::io::Error> {
+    fn update_stream(&mut self) -> std::io::Result<()> {
         self.apply_seek()?;
         self.decode_into_buffer()
     }
 }
 
-impl<R: Read + Seek> Seek for CompressedFile<R> {
-    fn seek(&mut self, target: SeekFrom) -> Result<u64, std::io::Error> {
+impl<D: Decoder> Seek for CompressedFile<D> {
+    fn seek(&mut self, target: SeekFrom) -> std::io::Result<u64> {
         let (start, offset) = match target {
             SeekFrom::Start(n) => (0, n as i64),
-            SeekFrom::Current(n) => (self.pos, n),
+            SeekFrom::Current(n) => (self.seek_pos.unwrap_or(self.pos), n),
             SeekFrom::End(n) =>
                 if self.has_file_size() {
-                    (self.source_bytes, n)
+                    (self.len() as u64, n)
                 } else {
                     // Ideally we could SeekFrom::End(-1000) and only decode the last frame even if we don't know
                     // all the frames' decompressed sizes yet. But we wouldn't be able to return the current offset
                     // from Start in that case, which the API requires.
-                    todo!("We don't know if we know the end-of-file pos yet");
+                    return Err(std::io::Error::other("The decompressed size is not known yet"));
                 },
         };
-        let pos = start.saturating_add_signed(offset).min(self.len() as u64);
+
+        // Our length is only an estimate until we know the file size; apply_seek stops at the real end
+        let pos = start.saturating_add_signed(offset);
+        let pos = if self.has_file_size() { pos.min(self.len() as u64) } else { pos };
 
         // Save the seek position for the future
         self.seek_pos = Some(pos);
@@ -428,7 +376,7 @@ impl<R: Read + Seek> Seek for CompressedFile<R> {
     }
 }
 
-impl<R: Read + Seek> Read for CompressedFile<R> {
+impl<D: Decoder> Read for CompressedFile<D> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let mut bytes = 0;
         while bytes < buf.len() {
@@ -448,11 +396,8 @@ impl<R: Read + Seek> Read for CompressedFile<R> {
     }
 }
 
-impl<R: Read + Seek> BufRead for CompressedFile<R> {
+impl<D: Decoder> BufRead for CompressedFile<D> {
     fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
-        // FIXME: We have to copy bytes twice here: Once from the Decoder buffer to ours, and once again
-        // to our reader.  We could skip the first copy if we had access to Decoder::buffer::as_slices(), but
-        // Decoder::buffer is private.  Shucks.  For now, we must copy.
         self.update_stream()?;
         Ok(self.read_buffer.get_buffer())
     }
@@ -464,15 +409,16 @@ impl<R: Read + Seek> BufRead for CompressedFile<R> {
     }
 }
 
-impl<R> Stream for CompressedFile<R> {
+impl<D: Decoder> Stream for CompressedFile<D> {
     fn len(&self) -> usize {
-        let last = &self.frames.last().unwrap();
-        let len = last.logical + last.len +
-            if last.len > 0 { 0 } else {
-                // estimate some extra bytes based on remaining compressed data
-                assert!(self.source_bytes >= last.physical);
-                self.source_bytes - last.physical
-            };
+        let last = self.frames.last().unwrap();
+        let len = if last.len > 0 {
+            last.logical + last.len
+        } else {
+            // estimate some extra bytes based on remaining compressed data
+            let (physical, logical) = if self.decoded.1 > last.logical { self.decoded } else { (last.physical, last.logical) };
+            logical + self.source_bytes.saturating_sub(physical)
+        };
         len as usize
     }
 
@@ -489,27 +435,25 @@ impl<R> Stream for CompressedFile<R> {
 
 #[test]
 fn test_compressed_file() {
-    use std::fs::File;
-    // HACKY FILENAME
-    let path = "/home/phord/git/mine/igrok/test.zst".to_owned();
-    let file = File::open(path).expect("File exists");
-
-    let mut comp = CompressedFile::new(&file).unwrap();
-    if let Err(e) = std::io::copy(&mut comp, &mut std::io::stdout().lock()) {
-        eprintln!("Error: {:?}", e);
-    }
+    use crate::files::zstd_log_file::ZstdDecoder;
+    use crate::files::zstd_log_file::tests::{make_text, zstd_frame};
+    let text = make_text(1000);
+    let file = std::io::Cursor::new(zstd_frame(&text));
+
+    let mut comp = CompressedFile::<ZstdDecoder<_>>::new(file).unwrap();
+    let mut decoded = Vec::new();
+    std::io::copy(&mut comp, &mut decoded).unwrap();
+    assert_eq!(decoded, text);
 }
 
 
 #[test]
 fn test_compressed_file_seek() {
-    use std::io::{BufRead, BufReader};
-    use std::fs::File;
-    // HACKY FILENAME
-    let path = "/home/phord/git/mine/igrok/test.zst".to_owned();
-    let file = File::open(path).expect("File exists");
+    use crate::files::zstd_log_file::ZstdDecoder;
+    use crate::files::zstd_log_file::tests::{make_text, zstd_frame};
+    let compressed = zstd_frame(&make_text(1000));
 
-    let comp = CompressedFile::new(&file).unwrap();
+    let comp = CompressedFile::<ZstdDecoder<_>>::new(std::io::Cursor::new(compressed.clone())).unwrap();
     let mut reader = BufReader::new(comp);
     let mut line6 = String::default();
     let mut first_5_lines = String::default();
@@ -521,7 +465,7 @@ fn test_compressed_file_seek() {
 
     assert!(!line6.is_empty());
 
-    let mut comp = CompressedFile::new(&file).unwrap();
+    let mut comp = CompressedFile::<ZstdDecoder<_>>::new(std::io::Cursor::new(compressed)).unwrap();
     comp.seek(SeekFrom::Start(count)).expect("Seek should work");
     let mut reader = BufReader::new(comp);
     let mut line6b = String::default();
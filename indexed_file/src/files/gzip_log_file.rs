@@ -1,33 +1,301 @@
 // Reader of compressed gzip files
-use flate2::bufread;
-use std::path::PathBuf;
-use std::io::{BufReader, Read, Seek};
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide, TINFL_LZ_DICT_SIZE};
+use miniz_oxide::inflate::TINFLStatus;
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::fs::File;
 
-use super::CachedStreamReader;
+use crate::files::compressed_file::{CompressedFile, Decoder};
 
-// FIXME: rename this to GzipCachedLogFile
-pub type GzipLogFile = CachedStreamReader;
+pub type GzipLogFile = CompressedFile<GzipDecoder<BufReader<File>>>;
 
-impl GzipLogFile {
-    fn is_recognized(file: &File) -> bool {
+// Compressed bytes to read at a time
+const INPUT_CHUNK: usize = 64 * 1024;
+
+// gzip header flags
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// The inflate decoder and its window of the last 32KB of output, which back-references may copy from.
+/// Together these are everything needed to resume decoding in the middle of a deflate stream.
+#[derive(Clone)]
+pub struct InflateState {
+    decomp: Box<DecompressorOxide>,
+    dict: Box<[u8]>,
+    dict_ofs: usize,
+}
+
+impl InflateState {
+    fn new() -> Self {
+        Self {
+            decomp: Box::default(),
+            dict: vec![0; TINFL_LZ_DICT_SIZE].into_boxed_slice(),
+            dict_ofs: 0,
+        }
+    }
+}
+
+/**
+ * Decoder for gzip files.
+ *
+ * Each member of a multi-member gzip file is a segment we can begin decoding from, but most files have only one
+ * member.  gzip has no native index, so on the first pass through the file we also save the inflate state
+ * periodically, like zlib's zran example does.  Later seeks decode only from the nearest saved state before
+ * the target.
+ */
+pub struct GzipDecoder<R> {
+    /// The source (compressed) file reader
+    file: R,
+
+    /// Compressed input we read from the file, and the physical offset of its start
+    input: Vec<u8>,
+    input_pos: usize,
+    input_start: u64,
+
+    /// The inflate decoder, and whether it has finished the member
+    state: InflateState,
+    done: bool,
+}
+
+impl<R: Read + Seek> GzipDecoder<R> {
+    fn seek_input(&mut self, physical: u64) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(physical))?;
+        self.input.clear();
+        self.input_pos = 0;
+        self.input_start = physical;
+        Ok(())
+    }
+
+    // Read more compressed input if we used up what we had.  Returns false at the end of the file.
+    fn fill_input(&mut self) -> std::io::Result<bool> {
+        if self.input_pos < self.input.len() {
+            return Ok(true);
+        }
+        self.input_start += self.input.len() as u64;
+        self.input.resize(INPUT_CHUNK, 0);
+        let count = self.file.read(&mut self.input)?;
+        self.input.truncate(count);
+        self.input_pos = 0;
+        Ok(count > 0)
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        if !self.fill_input()? {
+            return Ok(None);
+        }
+        self.input_pos += 1;
+        Ok(Some(self.input[self.input_pos - 1]))
+    }
+
+    fn skip_input(&mut self, count: usize) -> std::io::Result<bool> {
+        for _ in 0..count {
+            if self.read_byte()?.is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Parse the header of a gzip member.  Returns false if there isn't one; anything after the last member is ignored.
+    fn read_header(&mut self) -> std::io::Result<bool> {
+        let mut header = [0u8; 10];
+        for byte in header.iter_mut() {
+            match self.read_byte()? {
+                Some(b) => *byte = b,
+                None => return Ok(false),
+            }
+        }
+        if header[0..3] != [0x1f, 0x8b, 8] {
+            return Ok(false);
+        }
+
+        let flags = header[3];
+        if flags & FEXTRA != 0 {
+            let (Some(lo), Some(hi)) = (self.read_byte()?, self.read_byte()?) else { return Ok(false) };
+            if !self.skip_input(lo as usize | (hi as usize) << 8)? {
+                return Ok(false);
+            }
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                // Zero-terminated string
+                loop {
+                    match self.read_byte()? {
+                        Some(0) => break,
+                        Some(_) => {},
+                        None => return Ok(false),
+                    }
+                }
+            }
+        }
+        if flags & FHCRC != 0 && !self.skip_input(2)? {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> Decoder for GzipDecoder<R> {
+    type Reader = R;
+    type Context = InflateState;
+
+    fn is_recognized(file: &mut R) -> bool {
         // Check the magic number
         let mut buf = [0; 2];
-        let mut file = file;
         file.read_exact(&mut buf).is_ok() && buf == [0x1f, 0x8b]
     }
 
-    pub fn from_path(filename: &PathBuf) -> std::io::Result<GzipLogFile> {
-        let mut file = File::open(filename)?;
-        if !Self::is_recognized(&file) {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "Unrecognized file type".to_string()))
-        } else {
-            file.seek(std::io::SeekFrom::Start(0))?;
-            let file = BufReader::new(file);
-            let gzf = bufread::GzDecoder::new(file);
-            let gzf = BufReader::new(gzf);
-            let gzf = CachedStreamReader::from_reader(gzf)?;
-            Ok(gzf)
+    fn new(file: R) -> std::io::Result<Self> {
+        Ok(Self {
+            file,
+            input: Vec::new(),
+            input_pos: 0,
+            input_start: 0,
+            state: InflateState::new(),
+            done: true,
+        })
+    }
+
+    fn begin(&mut self, physical: u64, context: Option<&InflateState>) -> std::io::Result<bool> {
+        self.seek_input(physical)?;
+        self.state = match context {
+            Some(state) => state.clone(),
+            None if self.read_header()? => InflateState::new(),
+            None => return Ok(false),
+        };
+        self.done = false;
+        Ok(true)
+    }
+
+    fn decode(&mut self) -> std::io::Result<Vec<u8>> {
+        while !self.done {
+            let more = self.fill_input()?;
+            let flags = if more { inflate_flags::TINFL_FLAG_HAS_MORE_INPUT } else { 0 };
+            let state = &mut self.state;
+            let (status, consumed, written) =
+                decompress(&mut state.decomp, &self.input[self.input_pos..], &mut state.dict, state.dict_ofs, flags);
+            self.input_pos += consumed;
+            let data = state.dict[state.dict_ofs..state.dict_ofs + written].to_vec();
+            state.dict_ofs = (state.dict_ofs + written) & (TINFL_LZ_DICT_SIZE - 1);
+
+            match status {
+                TINFLStatus::Done => {
+                    // Skip the CRC32 and ISIZE trailer
+                    self.skip_input(8)?;
+                    self.done = true;
+                },
+                TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput if more || written > 0 => {},
+                TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput => {
+                    log::warn!("gzip file is truncated at {}", self.input_start + self.input_pos as u64);
+                    self.done = true;
+                },
+                _ => return Err(std::io::Error::other(format!("Error in the gzip decoder: {:?}", status))),
+            }
+            if written > 0 {
+                return Ok(data);
+            }
         }
+        Ok(Vec::new())
+    }
+
+    fn physical(&mut self) -> std::io::Result<u64> {
+        Ok(self.input_start + self.input_pos as u64)
+    }
+
+    fn checkpoint(&self) -> Option<InflateState> {
+        (!self.done).then(|| self.state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::Stream;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Cursor, Write};
+
+    // Some text that doesn't compress too well, so checkpoints land in the middle of deflate blocks
+    fn make_text(lines: usize, seed: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..lines {
+            let x = (i * 7919 + seed) * 104729 % 1000003;
+            writeln!(text, "{:08} line {} of the test {:x} {}", i, seed, x, "z".repeat(x % 40)).unwrap();
+        }
+        text
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    type GzipCursor = CompressedFile<GzipDecoder<Cursor<Vec<u8>>>>;
+
+    fn open(compressed: Vec<u8>, spacing: u64) -> GzipCursor {
+        let mut file = GzipCursor::new(Cursor::new(compressed)).unwrap();
+        file.spacing = spacing;
+        file
+    }
+
+    fn read_at(file: &mut GzipCursor, pos: usize, count: usize) -> Vec<u8> {
+        file.seek(SeekFrom::Start(pos as u64)).unwrap();
+        let mut buf = vec![0; count];
+        let actual = file.read(&mut buf).unwrap();
+        buf.truncate(actual);
+        buf
+    }
+
+    #[test]
+    fn test_gzip_read_all() {
+        let text = make_text(10000, 1);
+        let mut file = open(gzip(&text), 64 * 1024);
+        assert!(file.len() < text.len());
+
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+        assert_eq!(file.len(), text.len());
+        assert!(file.frames.len() > 5);
+    }
+
+    #[test]
+    fn test_gzip_seek() {
+        let text = make_text(20000, 2);
+        let mut file = open(gzip(&text), 64 * 1024);
+
+        // Seeking ahead decodes to there, leaving checkpoints behind
+        let end = text.len() - 100;
+        file.seek(SeekFrom::Start(end as u64)).unwrap();
+        assert_eq!(read_at(&mut file, end, 1000), &text[end..]);
+        assert_eq!(file.len(), text.len());
+        let frames = file.frames.len();
+
+        // Seeking back resumes from the checkpoints
+        for pos in [0, 12345, 300000, 65536 * 3, 65536 * 3 - 1, 5, text.len() / 2, 1] {
+            assert_eq!(read_at(&mut file, pos, 5000), &text[pos..pos + 5000], "read at {}", pos);
+        }
+        assert_eq!(file.frames.len(), frames);
+    }
+
+    #[test]
+    fn test_gzip_members() {
+        let parts = [make_text(3000, 3), make_text(10, 4), Vec::new(), make_text(5000, 5)];
+        let compressed = parts.iter().flat_map(|part| gzip(part)).collect::<Vec<_>>();
+        let text = parts.concat();
+        let mut file = open(compressed, u64::MAX);
+
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+
+        // One breadcrumb at the start of each member with data
+        let logical = file.frames.iter().map(|frame| frame.logical as usize).collect::<Vec<_>>();
+        assert_eq!(logical, vec![0, parts[0].len(), parts[0].len() + parts[1].len()]);
+
+        let pos = parts[0].len() + parts[1].len() + 10;
+        assert_eq!(read_at(&mut file, pos, 100), &text[pos..pos + 100]);
+        assert_eq!(read_at(&mut file, 17, 100), &text[17..117]);
     }
 }
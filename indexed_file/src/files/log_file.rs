@@ -1,21 +1,22 @@
 // Generic wrapper of different readable file types
 
 use std::fs::File;
-use std::io::BufReader;
 use std::io::Seek;
 use std::io::SeekFrom;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use bstr::io::BufReadExt;
 
 use crate::files::CursorLogFile;
 use crate::files::MockLogFile;
+use crate::files::PreprocessedFile;
 use crate::files::TextLogFile;
 use crate::files::TextLogStream;
-use crate::files::ZstdLogFile;
+use crate::files::{Bzip2LogFile, GzipLogFile, Lz4LogFile, XzLogFile, ZstdLogFile};
 
 use super::CachedStreamReader;
-use super::GzipLogFile;
+use super::{CompressedFile, Decoder};
 use super::Stream;
 
 
@@ -36,34 +37,50 @@ pub trait LogBase: LogFile {
 
 // All of these can be promoted to LogSource
 impl LogFile for CachedStreamReader {}
-impl LogFile for TextLogFile {}
-impl LogFile for ZstdLogFile {}
+impl LogFile for TextLogFile {
+    fn shared_reader(&self) -> Option<Arc<dyn ReadAt>> { self.segments_reader() }
+    fn reads_file(&self) -> bool { true }
+}
+impl<D: Decoder> LogFile for CompressedFile<D> {
+    fn reads_file(&self) -> bool { true }
+}
 impl LogFile for CursorLogFile {}
+impl LogFile for PreprocessedFile {}
 
 impl LogBase for CursorLogFile {}
 impl LogBase for MockLogFile {}
+impl LogBase for PreprocessedFile {}
 impl LogBase for TextLogFile {}
 impl LogBase for TextLogStream {}
-impl LogBase for ZstdLogFile {}
+impl<D: Decoder> LogBase for CompressedFile<D> {}
+
+/// Reader of the data in a log by offset, which other threads can use while we keep reading the log ourselves
+pub trait ReadAt: Send + Sync {
+    /// Read some bytes from the given offset.  Returns 0 at the end of the data.
+    fn read_at(&self, buf: &mut [u8], offset: usize) -> std::io::Result<usize>;
+}
 
 pub trait LogFile: BufReadExt + Seek + Stream {
 
-    // Read a line from a given offset
-    fn read_line_at(&mut self, start: usize) -> std::io::Result<String> {
+    // Read the bytes of a line from a given offset, including its newline
+    fn read_bytes_at(&mut self, start: usize) -> std::io::Result<Vec<u8>> {
         self.seek(SeekFrom::Start(start as u64))?;
 
+        let mut buf = vec![];
+        // FIXME: Does this end early when some utf-8 code sequence inludes 0x10?
+        self.read_until(b'\n', &mut buf)?;
+        Ok(buf)
+    }
+
+    // Read a line from a given offset
+    fn read_line_at(&mut self, start: usize) -> std::io::Result<String> {
         // We could return this, except it will not handle invalid utf-8 data (and it strips \n)
         // return self.lines().next().unwrap()
 
         // FIXME: We strip invalid utf-8 data from the file here. But we should probably do this higher up the chain.
         // Note this from_utf8_lossy means we can't pass binary files through our toy cat tool. Not a goal, but worth knowing.
-
-        let mut buf = vec![];
-        // FIXME: Does this end early when some utf-8 code sequence inludes 0x10?
-        match self.read_until(b'\n', &mut buf) {
-            Ok(_) => Ok(String::from_utf8_lossy(&buf).into_owned()),
-            Err(e) => Err(e),
-        }
+        // The line may be longer than the bytes it came from, so use read_bytes_at to find where the next line starts.
+        self.read_bytes_at(start).map(|buf| String::from_utf8_lossy(&buf).into_owned())
     }
 
     /// Parse a block of data from the file and return the offsets of the lines (byte after each LF)
@@ -98,38 +115,79 @@ pub trait LogFile: BufReadExt + Seek + Stream {
         let start = end.saturating_sub(chunk_size);
         (start, end)
     }
+
+    // A reader worker threads can use to scan the log in parallel, if this kind of log can give them one
+    fn shared_reader(&self) -> Option<Arc<dyn ReadAt>> {
+        None
+    }
+
+    // True if we read the data from the file itself, so it is the same every time we open the file unchanged.  The
+    // output of a preprocessor or a stream can change while the file doesn't, so we don't cache indexes of those.
+    fn reads_file(&self) -> bool {
+        false
+    }
 }
 
 impl Stream for LogSource {
     #[inline(always)] fn len(&self) -> usize { self.as_ref().len() }
     #[inline(always)] fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize { self.as_mut().poll(timeout) }
     #[inline(always)] fn is_open(&self) -> bool { self.as_ref().is_open() }
+    #[inline(always)] fn truncations(&self) -> usize { self.as_ref().truncations() }
 }
 
 impl LogFile for LogSource {
     #[inline(always)] fn chunk(&self, target: usize) -> (usize, usize) { self.as_ref().chunk(target) }
+    #[inline(always)] fn read_bytes_at(&mut self, start: usize) -> std::io::Result<Vec<u8>> { self.as_mut().read_bytes_at(start) }
     #[inline(always)] fn read_line_at(&mut self, start: usize) -> std::io::Result<String> { self.as_mut().read_line_at(start) }
+    #[inline(always)] fn shared_reader(&self) -> Option<Arc<dyn ReadAt>> { self.as_ref().shared_reader() }
+    #[inline(always)] fn reads_file(&self) -> bool { self.as_ref().reads_file() }
+}
+
+// Open a file if it is in one compression format, or return None if it isn't
+type OpenCompressed = fn(&PathBuf) -> std::io::Result<Option<LogSource>>;
+
+// Open a file in any compression format we recognize by its magic number, or None if it's in none of them.
+// Once a magic number matches we report that decoder's errors instead of trying other formats.
+fn open_compressed(input_file: &PathBuf) -> std::io::Result<Option<LogSource>> {
+    let formats: [OpenCompressed; 5] = [
+        |path| Ok(ZstdLogFile::open(path)?.map(LogBase::to_src)),
+        |path| Ok(GzipLogFile::open(path)?.map(LogBase::to_src)),
+        |path| Ok(XzLogFile::open(path)?.map(LogBase::to_src)),
+        |path| Ok(Bzip2LogFile::open(path)?.map(LogBase::to_src)),
+        |path| Ok(Lz4LogFile::open(path)?.map(LogBase::to_src)),
+    ];
+    for open in formats {
+        if let Some(file) = open(input_file)? {
+            return Ok(Some(file));
+        }
+    }
+    Ok(None)
 }
 
 pub fn new_text_file(input_file: Option<&PathBuf>) -> std::io::Result<LogSource> {
+    let open = std::env::var("LESSOPEN").ok();
+    let close = std::env::var("LESSCLOSE").ok();
+    open_text_file(input_file, open.as_deref(), close.as_deref())
+}
+
+// Open a file through the LESSOPEN and LESSCLOSE preprocessor commands, if we have them
+fn open_text_file(input_file: Option<&PathBuf>, open: Option<&str>, close: Option<&str>) -> std::io::Result<LogSource> {
     if let Some(input_file) = input_file {
-            // Is it a file?
-        let metadata = input_file.metadata()?;
-        if metadata.is_file() {
-            if let Ok(file) = ZstdLogFile::from_path(input_file) {
-                // FIXME: If the first magic number succeeded but some later error occurred during scan, treat the
-                //        file as a compressed file anyway.
-                Ok(file.to_src())
-            } else if let Ok(file) = GzipLogFile::from_path(input_file) {
-                // FIXME: If the first magic number succeeded but some later error occurred during scan, treat the
-                //        file as a compressed file anyway.
-                Ok(file.to_src())
-            } else {
-                let file = File::open(input_file).unwrap();
-                let file = BufReader::new(file);
-                let file = TextLogFile::new(file)?;
-                Ok(file.to_src())
+        // Let the user's preprocessor show something else for this file first, as less does.  Their lesspipe may
+        // list the contents of an archive instead of showing the stream we would decompress.
+        if let Some(open) = open
+            && let Some(file) = PreprocessedFile::new(input_file, open, close)? {
+            return Ok(file.to_src());
+        }
+
+        // Is it a file?
+        if input_file.metadata()?.is_file() {
+            // Read the compressed formats we know ourselves
+            if let Some(file) = open_compressed(input_file)? {
+                return Ok(file);
             }
+            let file = TextLogFile::from_path(input_file)?;
+            Ok(file.to_src())
         } else {
             // Must be a stream.  We can't seek in streams; assert that seek fails to make sure.
             let mut file = File::open(input_file)?;
@@ -148,3 +206,37 @@ pub fn new_mock_file(fill: &str, size: usize, chunk_size: usize) -> LogSource {
     let file = MockLogFile::new(fill.to_string(), size, chunk_size);
     Box::new(file)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::Stream;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Read, Write};
+
+    fn read_all(mut file: LogSource) -> String {
+        file.wait_for_end();
+        let mut text = String::new();
+        file.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_lessopen_before_compressed() {
+        let path = std::env::temp_dir().join(format!("grok-{}-archive.gz", std::process::id()));
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(b"decompressed\n").unwrap();
+        encoder.finish().unwrap();
+
+        // The preprocessor shows what it likes for a compressed file, like a listing of an archive
+        let file = open_text_file(Some(&path), Some("|echo listing of %s"), None).unwrap();
+        assert_eq!(read_all(file), format!("listing of {}\n", path.display()));
+
+        // We decompress it ourselves when there's no preprocessor or it has nothing to show
+        let file = open_text_file(Some(&path), None, None).unwrap();
+        assert_eq!(read_all(file), "decompressed\n");
+        let file = open_text_file(Some(&path), Some("|true %s"), None).unwrap();
+        assert_eq!(read_all(file), "decompressed\n");
+        std::fs::remove_file(path).unwrap();
+    }
+}
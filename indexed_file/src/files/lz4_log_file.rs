@@ -0,0 +1,339 @@
+// Reader of compressed lz4 files
+use lz4_flex::block::{decompress_into, decompress_into_with_dict};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::fs::File;
+
+use crate::files::compressed_file::{Breadcrumb, CompressedFile, Decoder};
+
+pub type Lz4LogFile = CompressedFile<Lz4Decoder<BufReader<File>>>;
+
+const LZ4_MAGIC: u32 = 0x184d2204;
+
+// Skippable frames have magic numbers 0x184D2A50 to 0x184D2A5F
+const SKIPPABLE_MAGIC: u32 = 0x184d2a50;
+const SKIPPABLE_MASK: u32 = 0xfffffff0;
+
+// Frame descriptor flags
+const FLAG_INDEPENDENT: u8 = 0x20;
+const FLAG_BLOCK_CHECKSUM: u8 = 0x10;
+const FLAG_CONTENT_SIZE: u8 = 0x08;
+const FLAG_CONTENT_CHECKSUM: u8 = 0x04;
+const FLAG_DICT_ID: u8 = 0x01;
+
+// Blocks that depend on earlier ones can refer back this far
+const WINDOW_SIZE: usize = 64 * 1024;
+
+// High bit of the block size means the block is stored uncompressed
+const UNCOMPRESSED_BLOCK: u32 = 0x80000000;
+
+fn read_u32<R: Read>(file: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    file.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// The parts of an lz4 frame descriptor we need to read its blocks
+#[derive(Clone, Copy)]
+struct FrameInfo {
+    flags: u8,
+    max_block_size: usize,
+}
+
+/// Where we are in an lz4 frame. Blocks that depend on earlier ones also need the last 64KB of output.
+#[derive(Clone)]
+pub struct Lz4Context {
+    frame: FrameInfo,
+    window: Vec<u8>,
+}
+
+/**
+ * Decoder for lz4 frame files, using frames and blocks as breadcrumbs.
+ *
+ * lz4 frames may record their decompressed size, so we can find them without decoding them.  Blocks in a frame
+ * are also a place to begin decoding, if we know the frame flags and any earlier data they depend on.  So on the
+ * first pass through the file we save that context periodically, too.
+ */
+pub struct Lz4Decoder<R> {
+    /// The source (compressed) file reader
+    file: R,
+
+    /// The size of the compressed file in bytes
+    source_bytes: u64,
+
+    /// Offset in the compressed file of the next byte to read
+    physical: u64,
+
+    /// The frame we are decoding, or None at the end of the frame
+    context: Option<Lz4Context>,
+}
+
+impl<R: Read + Seek> Lz4Decoder<R> {
+    fn read_u32(&mut self) -> std::io::Result<u32> {
+        self.physical += 4;
+        read_u32(&mut self.file)
+    }
+
+    fn skip(&mut self, count: u64) -> std::io::Result<()> {
+        self.physical += count;
+        self.file.seek(SeekFrom::Start(self.physical))?;
+        Ok(())
+    }
+
+    // Read the next frame descriptor, skipping skippable frames.  Returns None at the end of the data.
+    fn read_frame_header(&mut self) -> std::io::Result<Option<(FrameInfo, Option<u64>)>> {
+        loop {
+            if self.physical + 7 > self.source_bytes {
+                return Ok(None);
+            }
+            match self.read_u32()? {
+                LZ4_MAGIC => break,
+                magic if magic & SKIPPABLE_MASK == SKIPPABLE_MAGIC => {
+                    let size = self.read_u32()?;
+                    self.skip(size as u64)?;
+                },
+                // Anything after the last frame is ignored
+                _ => return Ok(None),
+            }
+        }
+
+        let mut descriptor = [0; 2];
+        self.file.read_exact(&mut descriptor)?;
+        self.physical += 2;
+        let [flags, block_descriptor] = descriptor;
+        if flags >> 6 != 1 {
+            return Err(std::io::Error::other("Unsupported lz4 frame version"));
+        }
+        if flags & FLAG_DICT_ID != 0 {
+            return Err(std::io::Error::other("Unsupported lz4 frame with a dictionary"));
+        }
+        let max_block_size = match (block_descriptor >> 4) & 7 {
+            4 => 64 * 1024,
+            5 => 256 * 1024,
+            6 => 1024 * 1024,
+            7 => 4 * 1024 * 1024,
+            _ => return Err(std::io::Error::other("Invalid lz4 block size")),
+        };
+
+        let content_size = if flags & FLAG_CONTENT_SIZE != 0 {
+            let mut buf = [0; 8];
+            self.file.read_exact(&mut buf)?;
+            self.physical += 8;
+            Some(u64::from_le_bytes(buf))
+        } else {
+            None
+        };
+
+        // Header checksum
+        self.skip(1)?;
+        Ok(Some((FrameInfo { flags, max_block_size }, content_size)))
+    }
+
+    // Skip over the blocks of a frame without decoding them
+    fn skip_blocks(&mut self, frame: FrameInfo) -> std::io::Result<()> {
+        loop {
+            let size = self.read_u32()? & !UNCOMPRESSED_BLOCK;
+            if size == 0 {
+                break;
+            }
+            let checksum = if frame.flags & FLAG_BLOCK_CHECKSUM != 0 { 4 } else { 0 };
+            self.skip(size as u64 + checksum)?;
+        }
+        if frame.flags & FLAG_CONTENT_CHECKSUM != 0 {
+            self.skip(4)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Decoder for Lz4Decoder<R> {
+    type Reader = R;
+    type Context = Lz4Context;
+
+    fn is_recognized(file: &mut R) -> bool {
+        // Check the magic number
+        read_u32(file).is_ok_and(|magic| magic == LZ4_MAGIC)
+    }
+
+    fn new(mut file: R) -> std::io::Result<Self> {
+        let source_bytes = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file,
+            source_bytes,
+            physical: 0,
+            context: None,
+        })
+    }
+
+    // Scan the frame headers in the file and record their positions and sizes, if known
+    fn scan_frames(&mut self) -> std::io::Result<Vec<Breadcrumb<Lz4Context>>> {
+        let mut frames = Vec::new();
+        let mut logical = 0;
+        self.skip(0)?;
+        loop {
+            let start = self.physical;
+            match self.read_frame_header()? {
+                None => break,
+                Some((frame, Some(len))) => {
+                    if len > 0 {
+                        frames.push(Breadcrumb::new(start, logical, len));
+                        logical += len;
+                    }
+                    self.skip_blocks(frame)?;
+                },
+                Some((_, None)) => {
+                    // No point continuing the scan because we don't know the uncompressed size
+                    frames.push(Breadcrumb::new(start, logical, 0));
+                    break;
+                },
+            }
+        }
+        Ok(frames)
+    }
+
+    fn begin(&mut self, physical: u64, context: Option<&Lz4Context>) -> std::io::Result<bool> {
+        self.physical = physical;
+        self.skip(0)?;
+        self.context = match context {
+            Some(context) => Some(context.clone()),
+            None => self.read_frame_header()?.map(|(frame, _)| Lz4Context { frame, window: Vec::new() }),
+        };
+        Ok(self.context.is_some())
+    }
+
+    fn decode(&mut self) -> std::io::Result<Vec<u8>> {
+        let Some(context) = &self.context else { return Ok(Vec::new()) };
+        let frame = context.frame;
+
+        let size = self.read_u32()?;
+        if size == 0 {
+            // End of the frame
+            if frame.flags & FLAG_CONTENT_CHECKSUM != 0 {
+                self.skip(4)?;
+            }
+            self.context = None;
+            return Ok(Vec::new());
+        }
+
+        let compressed_size = (size & !UNCOMPRESSED_BLOCK) as usize;
+        if compressed_size > frame.max_block_size {
+            return Err(std::io::Error::other("Invalid lz4 block size"));
+        }
+        let mut block = vec![0; compressed_size];
+        self.file.read_exact(&mut block)?;
+        self.physical += compressed_size as u64;
+        if frame.flags & FLAG_BLOCK_CHECKSUM != 0 {
+            self.skip(4)?;
+        }
+
+        let context = self.context.as_mut().unwrap();
+        let data = if size & UNCOMPRESSED_BLOCK != 0 {
+            block
+        } else {
+            let mut data = vec![0; frame.max_block_size];
+            let count = if context.window.is_empty() {
+                decompress_into(&block, &mut data)
+            } else {
+                decompress_into_with_dict(&block, &mut data, &context.window)
+            }.map_err(|e| std::io::Error::other(format!("Error in the lz4 decoder: {:?}", e)))?;
+            data.truncate(count);
+            data
+        };
+
+        // Keep the data later blocks in this frame may refer to
+        if frame.flags & FLAG_INDEPENDENT == 0 {
+            context.window.extend_from_slice(&data[data.len().saturating_sub(WINDOW_SIZE)..]);
+            let excess = context.window.len().saturating_sub(WINDOW_SIZE);
+            context.window.drain(..excess);
+        }
+        Ok(data)
+    }
+
+    fn physical(&mut self) -> std::io::Result<u64> {
+        Ok(self.physical)
+    }
+
+    fn checkpoint(&self) -> Option<Lz4Context> {
+        self.context.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::Stream;
+    use lz4_flex::block::{compress, compress_with_dict};
+    use std::io::{Cursor, Write};
+
+    type Lz4Cursor = CompressedFile<Lz4Decoder<Cursor<Vec<u8>>>>;
+
+    fn make_text(lines: usize, seed: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..lines {
+            writeln!(text, "{:08} line {} of the test {:x}", i, seed, i * 7919 % 1000003).unwrap();
+        }
+        text
+    }
+
+    // Write an lz4 frame with 64KB blocks.  The frame checksums are not checked, so we don't write real ones.
+    fn lz4(data: &[u8], independent: bool, content_size: bool) -> Vec<u8> {
+        let mut frame = LZ4_MAGIC.to_le_bytes().to_vec();
+        let flags = 0x40 | if independent { FLAG_INDEPENDENT } else { 0 } | if content_size { FLAG_CONTENT_SIZE } else { 0 };
+        frame.extend([flags | FLAG_BLOCK_CHECKSUM, 0x40]);
+        if content_size {
+            frame.extend((data.len() as u64).to_le_bytes());
+        }
+        frame.push(0);
+
+        for (i, chunk) in data.chunks(WINDOW_SIZE).enumerate() {
+            let block = if independent || i == 0 {
+                compress(chunk)
+            } else {
+                compress_with_dict(chunk, &data[(i - 1) * WINDOW_SIZE..i * WINDOW_SIZE])
+            };
+            frame.extend((block.len() as u32).to_le_bytes());
+            frame.extend(block);
+            frame.extend([0; 4]);
+        }
+        frame.extend([0; 4]);
+        frame
+    }
+
+    fn check_reads(file: &mut Lz4Cursor, text: &[u8]) {
+        for pos in [text.len() - 10, 0, 70000, 65535, 12345, 500000, text.len() / 2] {
+            file.seek(SeekFrom::Start(pos as u64)).unwrap();
+            let mut buf = vec![0; 1000];
+            let count = file.read(&mut buf).unwrap();
+            assert_eq!(&buf[..count], &text[pos..(pos + 1000).min(text.len())], "read at {}", pos);
+        }
+    }
+
+    #[test]
+    fn test_lz4_frames() {
+        let parts = [make_text(20000, 1), make_text(10, 2), make_text(20000, 3)];
+        let mut compressed = lz4(&parts[0], true, true);
+        compressed.extend(lz4(&parts[1], false, true));
+        compressed.extend(lz4(&parts[2], true, true));
+        let text = parts.concat();
+
+        // All the frame sizes are known from the headers
+        let mut file = Lz4Cursor::new(Cursor::new(compressed)).unwrap();
+        assert_eq!(file.len(), text.len());
+        assert_eq!(file.frames.len(), 3);
+        check_reads(&mut file, &text);
+    }
+
+    #[test]
+    fn test_lz4_dependent_blocks() {
+        let text = make_text(50000, 4);
+        let mut file = Lz4Cursor::new(Cursor::new(lz4(&text, false, false))).unwrap();
+        file.spacing = 100000;
+        assert!(file.len() < text.len());
+
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+        assert_eq!(file.len(), text.len());
+        assert!(file.frames.len() > 5);
+        check_reads(&mut file, &text);
+    }
+}
@@ -48,7 +48,7 @@ impl Read for MockLogFile {
             let offset = self.pos as usize % self.filler.len();
             let end = (offset + len).min(self.len());
             assert!(end < self.buffer.len());
-            buf.copy_from_slice(self.buffer[offset..end].as_bytes());
+            buf.copy_from_slice(&self.buffer.as_bytes()[offset..end]);
             Ok(end-offset)
         }
     }
@@ -137,7 +137,7 @@ mod tests {
         let size = 16 * 1024;
         let fill = "this is a test\n";
         let mut file = new_mock_file(fill, size, 100);
-        assert_eq!(old_read(&mut file, 0, 10), Some(fill[..10].as_bytes().to_vec()));
+        assert_eq!(old_read(&mut file, 0, 10), Some(fill.as_bytes()[..10].to_vec()));
     }
 
     #[test]
@@ -146,7 +146,7 @@ mod tests {
         let fill = "this is a test\n";
         let mut file = new_mock_file(fill, size, 100);
         let offset = fill.len() * 10;
-        assert_eq!(old_read(&mut file, offset, 10), Some(fill[..10].as_bytes().to_vec()));
+        assert_eq!(old_read(&mut file, offset, 10), Some(fill.as_bytes()[..10].to_vec()));
     }
 
     #[test]
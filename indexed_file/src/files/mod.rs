@@ -3,9 +3,14 @@ mod mock_log_file;
 mod text_log_file;
 mod text_log_stream;
 mod gzip_log_file;
-mod zstd_log_file;
+pub(crate) mod zstd_log_file;
+mod xz_log_file;
+mod bzip2_log_file;
+mod lz4_log_file;
 mod log_file;
+mod preprocessed_file;
 mod cached_stream_reader;
+mod stream_decoder;
 mod compressed_file;
 
 pub use cursor_log_file::CursorLogFile;
@@ -14,11 +19,17 @@ pub use log_file::{LogSource, LogBase};
 pub use log_file::new_text_file;
 pub use log_file::new_mock_file;
 pub use log_file::LogFile;
+pub use log_file::ReadAt;
 pub use mock_log_file::MockLogFile;
 pub use text_log_file::TextLogFile;
 pub use text_log_stream::TextLogStream;
 pub use cached_stream_reader::CachedStreamReader;
 pub use cached_stream_reader::Stream;
-pub use compressed_file::CompressedFile;
+pub use cached_stream_reader::set_stream_memory_limit;
+pub use compressed_file::{CompressedFile, Decoder};
 pub use zstd_log_file::ZstdLogFile;
 pub use gzip_log_file::GzipLogFile;
+pub use xz_log_file::XzLogFile;
+pub use bzip2_log_file::Bzip2LogFile;
+pub use lz4_log_file::Lz4LogFile;
+pub use preprocessed_file::PreprocessedFile;
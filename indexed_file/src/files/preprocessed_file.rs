@@ -0,0 +1,191 @@
+// Reader of files through an input preprocessor, like `less` does with LESSOPEN and LESSCLOSE
+//
+// LESSOPEN holds a command to run on the file we open, with %s replaced by the file name.
+//  - "|cmd %s" means the command writes the contents we should show to its output, which we read as a stream.
+//    If it writes nothing, we show the original file instead.  "||cmd %s" shows the output even when it's empty.
+//  - "cmd %s" means the command writes the name of a replacement file to show instead, or nothing to show the
+//    original file.
+//
+// LESSCLOSE holds a command to run when we are done with the file, with the first %s replaced by the original file
+// name and the second by the replacement file name, or "-" for a pipe.  It's meant to remove the replacement file.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use crate::files::{CachedStreamReader, LogBase, LogSource, Stream, TextLogFile};
+
+pub struct PreprocessedFile {
+    /// The preprocessor output or the replacement file
+    file: LogSource,
+
+    /// The preprocessor writing to our pipe, if it's a pipe
+    child: Option<Child>,
+
+    /// The LESSCLOSE command to run when we're done, with the file names filled in
+    close: Option<String>,
+}
+
+// Quote a file name for the shell
+fn quote(name: &str) -> String {
+    format!("'{}'", name.replace('\'', r"'\''"))
+}
+
+// Replace each %s in a command with the next of the given names
+fn expand(command: &str, names: &[&str]) -> String {
+    let mut parts = command.split("%s");
+    let mut expanded = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        expanded += &quote(names.get(i).unwrap_or(&""));
+        expanded += part;
+    }
+    expanded
+}
+
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command).stdin(Stdio::null());
+    shell
+}
+
+impl PreprocessedFile {
+    /// Open a file through the preprocessor commands from LESSOPEN and LESSCLOSE.
+    /// Returns None if the preprocessor has nothing to show for this file.
+    pub fn new(input_file: &Path, open: &str, close: Option<&str>) -> std::io::Result<Option<Self>> {
+        let name = input_file.to_string_lossy();
+        let close = close.filter(|close| !close.is_empty());
+        if let Some(command) = open.strip_prefix('|') {
+            let (command, keep_empty) = match command.strip_prefix('|') {
+                Some(command) => (command, true),
+                None => (command, false),
+            };
+            // "|-" also preprocesses stdin, which we never do
+            let command = command.strip_prefix('-').unwrap_or(command);
+
+            let mut child = shell(&expand(command, &[&name])).stdout(Stdio::piped()).spawn()?;
+            let mut output = BufReader::new(child.stdout.take().unwrap());
+            if !keep_empty && output.fill_buf()?.is_empty() {
+                child.wait()?;
+                return Ok(None);
+            }
+            Ok(Some(Self {
+                file: CachedStreamReader::from_reader(output)?.to_src(),
+                child: Some(child),
+                close: close.map(|close| expand(close, &[&name, "-"])),
+            }))
+        } else if !open.is_empty() {
+            let output = shell(&expand(open, &[&name])).output()?;
+            let replacement = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if replacement.is_empty() {
+                return Ok(None);
+            }
+            let file = TextLogFile::new(BufReader::new(File::open(PathBuf::from(&replacement))?))?;
+            Ok(Some(Self {
+                file: file.to_src(),
+                child: None,
+                close: close.map(|close| expand(close, &[&name, &replacement])),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Drop for PreprocessedFile {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            // We may not have read all of its output
+            if let Ok(None) = child.try_wait() {
+                let _ = child.kill();
+            }
+            let _ = child.wait();
+        }
+        if let Some(close) = &self.close
+                && let Err(e) = shell(close).status() {
+            log::warn!("Failed to run LESSCLOSE command {}: {}", close, e);
+        }
+    }
+}
+
+impl Stream for PreprocessedFile {
+    #[inline(always)] fn len(&self) -> usize { self.file.len() }
+    #[inline(always)] fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize { self.file.poll(timeout) }
+    #[inline(always)] fn is_open(&self) -> bool { self.file.is_open() }
+    #[inline(always)] fn truncations(&self) -> usize { self.file.truncations() }
+}
+
+impl BufRead for PreprocessedFile {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.file.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.file.consume(amt);
+    }
+}
+
+impl Read for PreprocessedFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for PreprocessedFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("grok-{}-{}", std::process::id(), name));
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    fn read_all(mut file: PreprocessedFile) -> String {
+        file.wait_for_end();
+        let mut text = String::new();
+        file.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_lessopen_pipe() {
+        let input = temp_file("pipe it's", "hello\nworld\n");
+        let file = PreprocessedFile::new(&input, "|tr a-z A-Z < %s", None).unwrap().unwrap();
+        assert_eq!(read_all(file), "HELLO\nWORLD\n");
+
+        // Empty output means "show the original file", unless we asked for it with ||
+        assert!(PreprocessedFile::new(&input, "|true %s", None).unwrap().is_none());
+        let file = PreprocessedFile::new(&input, "||true %s", None).unwrap().unwrap();
+        assert_eq!(read_all(file), "");
+        std::fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_lessopen_replacement_file() {
+        let input = temp_file("original", "original\n");
+        let replacement = temp_file("replacement", "replacement\n");
+        let closed = std::env::temp_dir().join(format!("grok-{}-closed", std::process::id()));
+        let open = format!("echo {} # %s", replacement.display());
+        let close = format!("echo %s %s > {}", closed.display());
+
+        let file = PreprocessedFile::new(&input, &open, Some(&close)).unwrap().unwrap();
+        assert_eq!(read_all(file), "replacement\n");
+
+        // LESSCLOSE ran when we dropped the file
+        let names = std::fs::read_to_string(&closed).unwrap();
+        assert_eq!(names.trim(), format!("{} {}", input.display(), replacement.display()));
+
+        assert!(PreprocessedFile::new(&input, "true %s", None).unwrap().is_none());
+        for path in [input, replacement, closed] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// Decoders for compressed data arriving on a pipe or stdin
+//
+// Compressed files are read by CompressedFile, which seeks around in them to decode only the parts we look at.  We
+// can't seek in a pipe, so we decode the whole stream as it arrives instead, and CachedStreamReader caches the decoded
+// data like any other stream.
+
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::ops::RangeInclusive;
+
+use ruzstd::frame::ReadFrameHeaderError;
+use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Zstd,
+    Gzip,
+    Xz,
+    Bzip2,
+}
+
+// Magic numbers at the start of each format, as the range of values allowed for each byte
+const MAGIC: [(Format, &[RangeInclusive<u8>]); 5] = [
+    (Format::Zstd, &[0x28..=0x28, 0xb5..=0xb5, 0x2f..=0x2f, 0xfd..=0xfd]),
+    // A skippable frame, which seekable zstd files may begin with
+    (Format::Zstd, &[0x50..=0x5f, 0x2a..=0x2a, 0x4d..=0x4d, 0x18..=0x18]),
+    (Format::Gzip, &[0x1f..=0x1f, 0x8b..=0x8b]),
+    (Format::Xz, &[0xfd..=0xfd, 0x37..=0x37, 0x7a..=0x7a, 0x58..=0x58, 0x5a..=0x5a, 0x00..=0x00]),
+    (Format::Bzip2, &[b'B'..=b'B', b'Z'..=b'Z', b'h'..=b'h', b'1'..=b'9']),
+];
+
+// Length of the longest magic number
+const MAGIC_LEN: usize = 6;
+
+// The format whose magic number begins the header.  Err if the header is too short to know yet.
+fn sniff(header: &[u8], eof: bool) -> Result<Option<Format>, ()> {
+    let mut undecided = false;
+    for (format, magic) in MAGIC {
+        if magic.iter().zip(header).all(|(range, byte)| range.contains(byte)) {
+            if header.len() >= magic.len() {
+                return Ok(Some(format));
+            }
+            undecided = true;
+        }
+    }
+    if undecided && !eof { Err(()) } else { Ok(None) }
+}
+
+/// Decode the input if it begins with the magic number of a compression format we know, or else pass it through.
+/// We only wait for as many bytes as it takes to rule out every format, so short lines of text arrive promptly.
+pub(crate) fn decode_stream(mut input: Box<dyn BufRead>) -> std::io::Result<Box<dyn BufRead>> {
+    let mut header = Vec::new();
+    let format = loop {
+        let buf = input.fill_buf()?;
+        let peek = [&header, &buf[..buf.len().min(MAGIC_LEN)]].concat();
+        match sniff(&peek, buf.is_empty()) {
+            Ok(format) => break format,
+            Err(()) => {
+                // Too short to tell.  Keep it and wait for more.
+                let count = buf.len();
+                header.extend_from_slice(buf);
+                input.consume(count);
+            }
+        }
+    };
+
+    // Put back the bytes we kept
+    let input = Box::new(Cursor::new(header).chain(input));
+    if let Some(format) = format {
+        log::info!("Decompressing {:?} stream", format);
+    }
+    Ok(match format {
+        None => input,
+        Some(Format::Zstd) => Box::new(BufReader::new(ZstdStream::new(input))),
+        Some(Format::Gzip) => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(input))),
+        Some(Format::Xz) => Box::new(BufReader::new(lzma_rust2::XzReader::new(input, true))),
+        Some(Format::Bzip2) => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(input))),
+    })
+}
+
+/// Reader of all the zstd frames in a stream, one after another
+struct ZstdStream<R> {
+    input: R,
+    decoder: FrameDecoder,
+
+    /// True while we are decoding a frame
+    in_frame: bool,
+}
+
+impl<R: BufRead> ZstdStream<R> {
+    fn new(input: R) -> Self {
+        Self {
+            input,
+            decoder: FrameDecoder::new(),
+            in_frame: false,
+        }
+    }
+}
+
+fn zstd_error<E: std::fmt::Debug>(e: E) -> std::io::Error {
+    std::io::Error::other(format!("Error in the zstd decoder: {:?}", e))
+}
+
+impl<R: BufRead> Read for ZstdStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if self.in_frame {
+                while self.decoder.can_collect() < buf.len() && !self.decoder.is_finished() {
+                    let needed = buf.len() - self.decoder.can_collect();
+                    self.decoder.decode_blocks(&mut self.input, BlockDecodingStrategy::UptoBytes(needed))
+                        .map_err(zstd_error)?;
+                }
+                let count = self.decoder.read(buf)?;
+                if count > 0 || buf.is_empty() {
+                    return Ok(count);
+                }
+                self.in_frame = false;
+            }
+
+            // Begin the next frame, if there is one
+            if self.input.fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            match self.decoder.reset(&mut self.input) {
+                Ok(()) => self.in_frame = true,
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame(_magic_num, skip_size))) => {
+                    std::io::copy(&mut (&mut self.input).take(skip_size as u64), &mut std::io::sink())?;
+                }
+                Err(e) => return Err(zstd_error(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn decode(data: Vec<u8>) -> String {
+        let mut text = String::new();
+        decode_stream(Box::new(Cursor::new(data))).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x1f\x8b\x08", false), Ok(Some(Format::Gzip)));
+        assert_eq!(sniff(b"BZh9", false), Ok(Some(Format::Bzip2)));
+        assert_eq!(sniff(b"\xfd7zXZ\x00", false), Ok(Some(Format::Xz)));
+        assert_eq!(sniff(b"\x28\xb5\x2f\xfd", false), Ok(Some(Format::Zstd)));
+        assert_eq!(sniff(b"hello", false), Ok(None));
+        assert_eq!(sniff(b"BZ", false), Err(()));
+        assert_eq!(sniff(b"BZ", true), Ok(None));
+        assert_eq!(sniff(b"", true), Ok(None));
+    }
+
+    #[test]
+    fn test_decode_streams() {
+        let text = (0..5000).map(|i| format!("line {}\n", i)).collect::<String>();
+        assert_eq!(decode(text.clone().into_bytes()), text);
+        assert_eq!(decode(b"BZ".to_vec()), "BZ");
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(text.as_bytes()).unwrap();
+        assert_eq!(decode(gzip.finish().unwrap()), text);
+
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bzip2.write_all(text.as_bytes()).unwrap();
+        let bzip2 = bzip2.finish().unwrap();
+        assert_eq!(decode(bzip2.clone()), text);
+
+        // The magic number arrives a byte at a time
+        let mut decoded = String::new();
+        let input = BufReader::with_capacity(1, Cursor::new(bzip2));
+        decode_stream(Box::new(input)).unwrap().read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+
+        let mut xz = lzma_rust2::XzWriter::new(Vec::new(), lzma_rust2::XzOptions::default()).unwrap();
+        xz.write_all(text.as_bytes()).unwrap();
+        assert_eq!(decode(xz.finish().unwrap()), text);
+    }
+
+    // A zstd frame holding the data in one raw block
+    fn zstd_frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd, 0, (17 - 10) << 3];
+        frame.extend(&(1 | (data.len() as u32) << 3).to_le_bytes()[..3]);
+        frame.extend(data);
+        frame
+    }
+
+    #[test]
+    fn test_decode_zstd_frames() {
+        // Frames with a skippable frame between them, like a seek table
+        let mut data = zstd_frame(b"first frame\n");
+        data.extend([0x5e, 0x2a, 0x4d, 0x18, 3, 0, 0, 0, 1, 2, 3]);
+        data.extend(zstd_frame(b"second frame\n"));
+        assert_eq!(decode(data), "first frame\nsecond frame\n");
+    }
+}
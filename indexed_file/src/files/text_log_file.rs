@@ -1,20 +1,208 @@
 // Reader of regular text files
 
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek};
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 
-use crate::files::Stream;
+use crate::files::{ReadAt, Stream};
 
-pub struct TextLogFile {
+// Identify the file behind a path or handle, so we notice when a log is rotated
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Bytes we remember from the start of a file, to notice when it is truncated and written past its old size again
+const HEAD_BYTES: usize = 256;
+
+// Read the first bytes of a file without moving our position in it
+#[cfg(unix)]
+fn read_head(file: &File) -> Option<Vec<u8>> {
+    use std::os::unix::fs::FileExt;
+    let mut head = vec![0; HEAD_BYTES];
+    let mut filled = 0;
+    while filled < head.len() {
+        match file.read_at(&mut head[filled..], filled as u64).ok()? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    head.truncate(filled);
+    Some(head)
+}
+
+#[cfg(not(unix))]
+fn read_head(_file: &File) -> Option<Vec<u8>> {
+    None
+}
+
+// Files of a log opened again for other threads, with the offset where each one starts in the log
+#[cfg(unix)]
+struct SharedSegments(Vec<(File, usize)>);
+
+#[cfg(unix)]
+impl ReadAt for SharedSegments {
+    fn read_at(&self, buf: &mut [u8], offset: usize) -> std::io::Result<usize> {
+        use std::os::unix::fs::FileExt;
+        let index = self.0.partition_point(|(_, start)| *start <= offset) - 1;
+        let (file, start) = &self.0[index];
+        // Don't read past the start of the next file into whatever was written to this one after it was rotated
+        let end = self.0.get(index + 1).map_or(usize::MAX, |(_, next)| *next);
+        let len = buf.len().min(end - offset);
+        file.read_at(&mut buf[..len], (offset - start) as u64)
+    }
+}
+
+/// One of the files we read as a single log.  When a log is rotated we keep reading the old file and continue in
+/// the new one after it.
+struct Segment {
     file: BufReader<File>,
+
+    /// Offset of the start of this file in the log
+    start: usize,
+}
+
+pub struct TextLogFile {
+    /// Where we look for a new file when the log is rotated, if we know
+    path: Option<PathBuf>,
+
+    /// The files we read, in order.  Only the last one can still grow.
+    segments: Vec<Segment>,
+
+    /// The segment we are reading
+    current: usize,
+
     len: usize,
     pos: u64,
+
+    /// Number of times the file was truncated
+    truncations: usize,
+
+    /// The first bytes of the last file and when it was modified, the last time we looked
+    head: Vec<u8>,
+    modified: Option<SystemTime>,
 }
 
 impl TextLogFile {
     pub fn new(file: BufReader<File>) -> std::io::Result<TextLogFile> {
-        let len = file.get_ref().metadata()?.len() as usize;
-        Ok(TextLogFile { file, len, pos: 0})
+        let metadata = file.get_ref().metadata()?;
+        let head = read_head(file.get_ref()).unwrap_or_default();
+        Ok(TextLogFile {
+            path: None,
+            segments: vec![Segment { file, start: 0 }],
+            current: 0,
+            len: metadata.len() as usize,
+            pos: 0,
+            truncations: 0,
+            head,
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    /// Open a file we can follow when it is rotated
+    pub fn from_path(path: &Path) -> std::io::Result<TextLogFile> {
+        let file = Self::new(BufReader::new(File::open(path)?))?;
+        Ok(TextLogFile { path: Some(path.to_path_buf()), ..file })
+    }
+
+    // Start reading from the beginning again because the data we read is gone.  Only the last file is left; any
+    // we read before it was rotated are no longer part of the log.
+    fn truncate(&mut self, len: usize) -> std::io::Result<()> {
+        log::info!("File was truncated from {} to {} bytes", self.len, len);
+        let mut last = self.segments.pop().unwrap();
+        last.start = 0;
+        last.file.seek(SeekFrom::Start(0))?;
+        self.segments = vec![last];
+        self.head.clear();
+        self.len = len;
+        self.truncations += 1;
+        self.current = 0;
+        self.pos = 0;
+        Ok(())
+    }
+
+    // Open the file now at our path if it is not the one we are reading
+    fn rotated(&self) -> Option<File> {
+        let path = self.path.as_ref()?;
+        let last = self.segments.last()?;
+        let current = file_id(&last.file.get_ref().metadata().ok()?)?;
+        if file_id(&path.metadata().ok()?)? == current {
+            return None;
+        }
+        File::open(path).ok()
+    }
+
+    // Check the files for new data, truncation or rotation
+    fn update(&mut self) -> std::io::Result<()> {
+        let last = self.segments.last().unwrap();
+        let metadata = last.file.get_ref().metadata()?;
+        let size = metadata.len() as usize;
+        let modified = metadata.modified().ok();
+
+        // A file truncated and quickly written past its old size looks like it grew, but it starts differently now
+        let head = if last.start + size != self.len || modified != self.modified {
+            read_head(last.file.get_ref())
+        } else {
+            None
+        };
+        let rewritten = head.as_ref().is_some_and(|head| {
+            let known = head.len().min(self.head.len());
+            head[..known] != self.head[..known]
+        });
+
+        if last.start + size < self.len || rewritten {
+            self.truncate(size)?;
+        } else {
+            self.len = last.start + size;
+        }
+        if let Some(head) = head {
+            self.head = head;
+        }
+        self.modified = modified;
+
+        if let Some(file) = self.rotated() {
+            // The writer may add to the old file until it opens the new one, so we keep reading the old one until
+            // the new one has some data.  Then the new file continues the log after everything in the old one.
+            let len = file.metadata()?.len() as usize;
+            if len > 0 {
+                log::info!("File was rotated at {} bytes", self.len);
+                let start = self.len;
+                self.len += len;
+                self.segments.push(Segment { file: BufReader::new(file), start });
+
+                // Check the new file from its start next time
+                self.head.clear();
+                self.modified = None;
+            }
+        }
+        Ok(())
+    }
+
+    // Our files for other threads to read.  They read by offset, so they don't disturb our own position in them.
+    #[cfg(unix)]
+    pub(crate) fn segments_reader(&self) -> Option<Arc<dyn ReadAt>> {
+        let files = self.segments.iter()
+            .map(|segment| Some((segment.file.get_ref().try_clone().ok()?, segment.start)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Arc::new(SharedSegments(files)))
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn segments_reader(&self) -> Option<Arc<dyn ReadAt>> {
+        None
+    }
+
+    // Offset of the end of a segment.  The last one ends wherever the file ends.
+    fn segment_end(&self, index: usize) -> u64 {
+        self.segments.get(index + 1).map_or(u64::MAX, |next| next.start as u64)
     }
 }
 
@@ -23,43 +211,73 @@ impl Stream for TextLogFile {
     fn len(&self) -> usize {
         self.len
     }
-    // Poll for new data
-    #[inline(always)]
+
+    // Poll for new data.  Files can grow, shrink or be replaced while we read them, so check them again.
     fn poll(&mut self, _timeout: Option<std::time::Instant>) -> usize {
-        // FIXME: poll for new lines
+        if let Err(e) = self.update() {
+            log::warn!("Unable to check the file for changes: {}", e);
+        }
         self.len
     }
 
+    // A file may always grow, so we never wait for it to close.  Follow mode polls for growth instead.
     fn is_open(&self) -> bool { false }
+
+    fn truncations(&self) -> usize {
+        self.truncations
+    }
 }
 
 impl BufRead for TextLogFile {
     fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
-        self.file.fill_buf()
+        // Move on to the next file at the end of this one
+        while self.pos >= self.segment_end(self.current) {
+            self.current += 1;
+            self.segments[self.current].file.seek(SeekFrom::Start(0))?;
+        }
+        let remaining = self.segment_end(self.current) - self.pos;
+        let buf = self.segments[self.current].file.fill_buf()?;
+        Ok(&buf[..buf.len().min(remaining.try_into().unwrap_or(usize::MAX))])
     }
 
     fn consume(&mut self, amt: usize) {
         self.pos += amt as u64;
-        self.file.consume(amt);
+        self.segments[self.current].file.consume(amt);
     }
 }
 
 impl Read for TextLogFile {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let bytes = self.file.read(buf)?;
-        // self.pos += bytes as u64;
+        let data = self.fill_buf()?;
+        let bytes = data.len().min(buf.len());
+        buf[..bytes].copy_from_slice(&data[..bytes]);
+        self.consume(bytes);
         Ok(bytes)
     }
 }
 
 impl Seek for TextLogFile {
-    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-        if let std::io::SeekFrom::Start(n) = pos {
-            if self.pos == n {
-                return Ok(n)
-            }
-            self.pos = n;
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+            SeekFrom::End(n) => (self.len as u64).checked_add_signed(n),
+        }.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        if self.pos == target {
+            return Ok(target)
+        }
+
+        let index = self.segments.partition_point(|segment| segment.start as u64 <= target) - 1;
+        let segment = &mut self.segments[index];
+        let offset = target - segment.start as u64;
+        if index == self.current {
+            // Keep the buffer if the target is in it
+            segment.file.seek_relative(target as i64 - self.pos as i64)?;
+        } else {
+            segment.file.seek(SeekFrom::Start(offset))?;
         }
-        self.file.seek(pos)
+        self.current = index;
+        self.pos = target;
+        Ok(target)
     }
 }
@@ -0,0 +1,315 @@
+// Reader of compressed xz files
+use lzma_rust2::Lzma2Reader;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::fs::File;
+
+use crate::files::compressed_file::{Breadcrumb, CompressedFile, Decoder};
+
+pub type XzLogFile = CompressedFile<XzDecoder<BufReader<File>>>;
+
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0];
+const FOOTER_MAGIC: [u8; 2] = [b'Y', b'Z'];
+
+// Size of stream headers and footers
+const STREAM_HEADER_SIZE: u64 = 12;
+
+// The only filter we support. BCJ and delta filters are meant for executables and other binaries, not logs.
+const FILTER_LZMA2: u64 = 0x21;
+
+fn unsupported(what: &str) -> std::io::Error {
+    std::io::Error::other(format!("Unsupported xz file: {}", what))
+}
+
+// Blocks are padded to a multiple of four bytes
+fn padded(size: u64) -> u64 {
+    (size + 3) & !3
+}
+
+// Read a variable-length integer from an xz header or index
+fn read_varint(buf: &[u8], pos: &mut usize) -> std::io::Result<u64> {
+    let mut value = 0;
+    for i in 0..9 {
+        let byte = *buf.get(*pos).ok_or_else(|| unsupported("truncated header"))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(unsupported("invalid integer"))
+}
+
+struct XzBlock {
+    // Offset of the block header in the compressed file
+    physical: u64,
+
+    // Size of the whole block in the compressed file, including its padding and check
+    size: u64,
+
+    // Size of the decompressed data
+    len: u64,
+}
+
+enum Reader<R> {
+    Idle(R),
+    Block(Box<Lzma2Reader<R>>),
+    Empty,
+}
+
+/**
+ * Decoder for xz files, using xz blocks as breadcrumbs.
+ *
+ * Every xz stream ends with an index of the compressed and decompressed sizes of all its blocks, so we can find
+ * every block in the file before we decode any of them.  `xz -T` and pixz write many blocks, so their files can
+ * be read anywhere quickly.  Plain `xz` writes the whole file as one block, which we can only read from the start.
+ */
+pub struct XzDecoder<R> {
+    /// The source (compressed) file reader, or the decoder reading it
+    reader: Reader<R>,
+
+    /// The size of the compressed file in bytes
+    source_bytes: u64,
+
+    /// All the blocks in the file, in order
+    blocks: Vec<XzBlock>,
+
+    /// The block we are decoding
+    block: usize,
+}
+
+impl<R: Read + Seek> XzDecoder<R> {
+    fn file(&mut self) -> &mut R {
+        if let Reader::Block(_) = self.reader {
+            let Reader::Block(block) = std::mem::replace(&mut self.reader, Reader::Empty) else { unreachable!() };
+            self.reader = Reader::Idle(block.into_inner());
+        }
+        match &mut self.reader {
+            Reader::Idle(file) => file,
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_at(&mut self, physical: u64, len: u64) -> std::io::Result<Vec<u8>> {
+        let file = self.file();
+        file.seek(SeekFrom::Start(physical))?;
+        let mut buf = vec![0; len as usize];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // Read the index of one stream ending at `end`, and return the offset of the start of the stream
+    fn read_stream(&mut self, end: u64, blocks: &mut Vec<XzBlock>) -> std::io::Result<u64> {
+        if end < STREAM_HEADER_SIZE * 2 {
+            return Err(unsupported("truncated stream"));
+        }
+        let footer = self.read_at(end - STREAM_HEADER_SIZE, STREAM_HEADER_SIZE)?;
+        if footer[10..] != FOOTER_MAGIC {
+            return Err(unsupported("missing stream footer"));
+        }
+        let backward_size = (u32::from_le_bytes(footer[4..8].try_into().unwrap()) as u64 + 1) * 4;
+        let index_start = (end - STREAM_HEADER_SIZE).checked_sub(backward_size)
+            .ok_or_else(|| unsupported("invalid index size"))?;
+        let index = self.read_at(index_start, backward_size)?;
+        if index[0] != 0 {
+            return Err(unsupported("missing index"));
+        }
+
+        let mut pos = 1;
+        let count = read_varint(&index, &mut pos)?;
+        let mut sizes = Vec::new();
+        for _ in 0..count {
+            let unpadded = read_varint(&index, &mut pos)?;
+            let len = read_varint(&index, &mut pos)?;
+            sizes.push((padded(unpadded), len));
+        }
+
+        let blocks_size: u64 = sizes.iter().map(|(size, _)| size).sum();
+        let start = index_start.checked_sub(blocks_size + STREAM_HEADER_SIZE)
+            .ok_or_else(|| unsupported("invalid index"))?;
+        if self.read_at(start, XZ_MAGIC.len() as u64)? != XZ_MAGIC {
+            return Err(unsupported("missing stream header"));
+        }
+
+        // Blocks from later streams are already in the list, so put ours in front of them
+        let mut physical = start + STREAM_HEADER_SIZE;
+        let stream_blocks = sizes.into_iter().map(|(size, len)| {
+            let block = XzBlock { physical, size, len };
+            physical += size;
+            block
+        });
+        blocks.splice(0..0, stream_blocks);
+        Ok(start)
+    }
+
+    // Read the block header and return the LZMA2 dictionary size
+    fn read_block_header(&mut self, physical: u64) -> std::io::Result<u32> {
+        let size = self.read_at(physical, 1)?[0];
+        if size == 0 {
+            return Err(unsupported("missing block header"));
+        }
+        let header = self.read_at(physical, (size as u64 + 1) * 4)?;
+
+        let flags = header[1];
+        let mut pos = 2;
+        if flags & 0x40 != 0 {
+            read_varint(&header, &mut pos)?;   // compressed size
+        }
+        if flags & 0x80 != 0 {
+            read_varint(&header, &mut pos)?;   // uncompressed size
+        }
+
+        let filters = (flags & 3) + 1;
+        let filter = read_varint(&header, &mut pos)?;
+        let props_size = read_varint(&header, &mut pos)?;
+        if filters != 1 || filter != FILTER_LZMA2 || props_size != 1 {
+            return Err(unsupported("filters other than LZMA2"));
+        }
+
+        let props = *header.get(pos).ok_or_else(|| unsupported("truncated header"))?;
+        match props {
+            0..40 => Ok((2 | (props as u32 & 1)) << (props / 2 + 11)),
+            40 => Ok(u32::MAX),
+            _ => Err(unsupported("invalid dictionary size")),
+        }
+    }
+}
+
+impl<R: Read + Seek> Decoder for XzDecoder<R> {
+    type Reader = R;
+    type Context = ();
+
+    fn is_recognized(file: &mut R) -> bool {
+        // Check the magic number
+        let mut buf = [0; 6];
+        file.read_exact(&mut buf).is_ok() && buf == XZ_MAGIC
+    }
+
+    fn new(mut file: R) -> std::io::Result<Self> {
+        let source_bytes = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            reader: Reader::Idle(file),
+            source_bytes,
+            blocks: Vec::new(),
+            block: 0,
+        })
+    }
+
+    // Read the indexes of all the streams in the file, from the last to the first
+    fn scan_frames(&mut self) -> std::io::Result<Vec<Breadcrumb<()>>> {
+        let mut blocks = Vec::new();
+        let mut end = self.source_bytes;
+        while end > 0 {
+            // Skip stream padding
+            if end >= 4 && self.read_at(end - 4, 4)? == [0; 4] {
+                end -= 4;
+                continue;
+            }
+            end = self.read_stream(end, &mut blocks)?;
+        }
+
+        let mut frames = Vec::new();
+        let mut logical = 0;
+        for block in blocks.iter().filter(|block| block.len > 0) {
+            frames.push(Breadcrumb::new(block.physical, logical, block.len));
+            logical += block.len;
+        }
+        self.blocks = blocks;
+        Ok(frames)
+    }
+
+    fn begin(&mut self, physical: u64, _context: Option<&()>) -> std::io::Result<bool> {
+        // Start the first block at or after this point
+        let Some(block) = self.blocks.iter().position(|block| block.physical >= physical) else {
+            self.file();
+            return Ok(false);
+        };
+        let dict_size = self.read_block_header(self.blocks[block].physical)?;
+
+        // The compressed data follows the header, which leaves the file where we want it
+        let Reader::Idle(file) = std::mem::replace(&mut self.reader, Reader::Empty) else { unreachable!() };
+        self.reader = Reader::Block(Box::new(Lzma2Reader::new(file, dict_size, None)));
+        self.block = block;
+        Ok(true)
+    }
+
+    fn decode(&mut self) -> std::io::Result<Vec<u8>> {
+        const CHUNK_SIZE: usize = 256 * 1024;
+        let Reader::Block(reader) = &mut self.reader else { return Ok(Vec::new()) };
+        let mut buf = vec![0; CHUNK_SIZE];
+        let count = reader.read(&mut buf)?;
+        buf.truncate(count);
+        if count == 0 {
+            // Done with this block
+            self.file();
+        }
+        Ok(buf)
+    }
+
+    fn physical(&mut self) -> std::io::Result<u64> {
+        // We only need this at the end of blocks
+        let block = &self.blocks[self.block];
+        Ok(block.physical + block.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::Stream;
+    use lzma_rust2::{XzOptions, XzWriter};
+    use std::io::{Cursor, Write};
+    use std::num::NonZeroU64;
+
+    type XzCursor = CompressedFile<XzDecoder<Cursor<Vec<u8>>>>;
+
+    fn make_text(lines: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..lines {
+            writeln!(text, "{:08} line of the test {:x}", i, i * 7919 % 1000003).unwrap();
+        }
+        text
+    }
+
+    fn xz(data: &[u8], block_size: Option<u64>) -> Vec<u8> {
+        let mut options = XzOptions::with_preset(0);
+        options.set_block_size(block_size.and_then(NonZeroU64::new));
+        let mut writer = XzWriter::new(Vec::new(), options).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_xz_blocks() {
+        let text = make_text(100000);
+        let mut file = XzCursor::new(Cursor::new(xz(&text, Some(300000)))).unwrap();
+
+        // All the blocks are known from the index
+        assert_eq!(file.len(), text.len());
+        assert!(file.frames.len() > 5);
+
+        for pos in [text.len() - 100, 0, 1234567, 300000, 299999, 17] {
+            file.seek(SeekFrom::Start(pos as u64)).unwrap();
+            let mut buf = vec![0; 1000];
+            let count = file.read(&mut buf).unwrap();
+            assert_eq!(&buf[..count], &text[pos..(pos + 1000).min(text.len())], "read at {}", pos);
+        }
+    }
+
+    #[test]
+    fn test_xz_streams() {
+        let parts = [make_text(1000), make_text(10), make_text(3000)];
+        let mut compressed = Vec::new();
+        for part in &parts {
+            compressed.extend(xz(part, None));
+            // Stream padding
+            compressed.extend([0; 8]);
+        }
+        let text = parts.concat();
+        let mut file = XzCursor::new(Cursor::new(compressed)).unwrap();
+        assert_eq!(file.frames.len(), 3);
+
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+    }
+}
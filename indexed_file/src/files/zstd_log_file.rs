@@ -1,22 +1,544 @@
 // Reader of compressed zstd files
 
-use std::path::PathBuf;
+use ruzstd;
 use std::io::BufReader;
-use crate::files::CompressedFile;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
+use std::ops::Range;
+use std::thread;
 
+use ruzstd::frame::ReadFrameHeaderError;
+use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoderError};
+use ruzstd::decoding::block_decoder;
+use ruzstd::frame::read_frame_header;
 
-pub type ZstdLogFile = CompressedFile<BufReader<File>>;
+use crate::files::compressed_file::{Breadcrumb, CompressedFile, Decoder};
+use crate::worker_pool::WorkerPool;
 
-impl ZstdLogFile {
-    pub fn from_path(filename: &PathBuf) -> std::io::Result<ZstdLogFile> {
-        let file = File::open(filename)?;
-        if !CompressedFile::is_recognized(&file) {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "Unrecognized file type".to_string()))
-        } else {
-            let file = BufReader::new(file);
-            let zf = CompressedFile::new(file)?;
-            Ok(zf)
+pub type ZstdLogFile = CompressedFile<ZstdDecoder<BufReader<File>>>;
+
+// The seek table of the seekable zstd format is in a skippable frame with this magic number at the end of the file
+const SEEK_TABLE_MAGIC: u32 = 0x184d2a5e;
+
+// The seek table ends with a footer holding the number of frames, a descriptor byte and this magic number
+const SEEKABLE_MAGIC: u32 = 0x8f92eab1;
+const SEEK_TABLE_FOOTER_SIZE: u64 = 9;
+const SEEK_TABLE_CHECKSUM_FLAG: u8 = 0x80;
+
+// Worker threads decode whole frames in memory, so we decode bigger frames ourselves as we read them
+const MAX_PARALLEL_FRAME: u64 = 16 * 1024 * 1024;
+
+// Limit on the decompressed bytes the workers decode ahead of the reader
+const READ_AHEAD_BYTES: u64 = 256 * 1024 * 1024;
+
+fn zstd_error<E: std::fmt::Debug>(e: E) -> std::io::Error {
+    std::io::Error::other(format!("Error in the zstd decoder: {:?}", e))
+}
+
+fn unsupported(what: &str) -> std::io::Error {
+    std::io::Error::other(format!("Unsupported zstd file: {}", what))
+}
+
+// Decode one whole frame from memory
+fn decode_frame(mut data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoder = ruzstd::FrameDecoder::new();
+    decoder.reset(&mut data).map_err(zstd_error)?;
+    decoder.decode_blocks(&mut data, BlockDecodingStrategy::All).map_err(zstd_error)?;
+    Ok(decoder.collect().unwrap_or_default())
+}
+
+type FrameResult = (usize, std::io::Result<Vec<u8>>);
+
+/// Worker threads that decode frames given to them by index, and the frames they have decoded
+struct FramePool {
+    workers: WorkerPool<FrameResult>,
+
+    /// Frames given to the workers that we haven't received yet
+    pending: HashSet<usize>,
+
+    /// Decoded frames we haven't used yet
+    ready: BTreeMap<usize, std::io::Result<Vec<u8>>>,
+}
+
+impl FramePool {
+    fn new(threads: usize) -> Self {
+        Self {
+            workers: WorkerPool::with_threads(threads),
+            pending: HashSet::new(),
+            ready: BTreeMap::new(),
+        }
+    }
+
+    fn is_queued(&self, index: usize) -> bool {
+        self.pending.contains(&index) || self.ready.contains_key(&index)
+    }
+
+    fn submit(&mut self, index: usize, data: Vec<u8>) -> std::io::Result<()> {
+        if !self.workers.run(move || (index, decode_frame(&data))) {
+            return Err(std::io::Error::other("zstd worker threads are gone"));
+        }
+        self.pending.insert(index);
+        Ok(())
+    }
+
+    // Forget decoded frames we no longer expect to read
+    fn retain(&mut self, wanted: Range<usize>) {
+        self.ready.retain(|index, _| wanted.contains(index));
+    }
+
+    // Wait for the workers to decode a frame we submitted
+    fn wait(&mut self, index: usize) -> std::io::Result<Vec<u8>> {
+        loop {
+            if let Some(result) = self.ready.remove(&index) {
+                return result;
+            }
+            let (done, result) = self.workers.wait().ok_or_else(|| std::io::Error::other("zstd worker threads are gone"))?;
+            self.pending.remove(&done);
+            self.ready.insert(done, result);
+        }
+    }
+}
+
+/// A frame we found before decoding any
+struct ZstdFrame {
+    // Offset of the frame in the compressed file
+    physical: u64,
+
+    // Size of the whole frame in the compressed file
+    size: u64,
+
+    // Size of the decompressed data
+    len: u64,
+}
+
+/// Where the data we decode next comes from
+enum Source {
+    /// Our frame decoder, reading the file
+    File,
+
+    /// The worker pool, decoding the frame at this index. True when we already returned its data.
+    Pool(usize, bool),
+}
+
+/// Decoder for zstd files, using zstd frames as breadcrumbs.
+///
+/// Frames are useful because they can be decoded independently from each other. This means we can position
+/// the input file to the start of any frame and decompress from there. Thus we can treat a compressed file
+/// made of multiple frames as one logical decompressed file supporting random-access reads if we know the
+/// compressed and decompressed sizes of all the frames.
+///
+/// In practice this is only useful if we have a file compressed with multiple frames. Ideally each frame
+/// also stores its decompressed size in the frame header.  However, this (decomp size) is optional. If it
+/// is not available, the only way to find it is to decompress the frames to calculate it, which is slow.
+///
+/// When we read through frames we found up front, we also decode the frames after the one being read on worker
+/// threads, so reading the whole file of a multi-frame archive keeps all the cores busy.
+pub struct ZstdDecoder<R> {
+    /// The source (compressed) file reader
+    file: R,
+
+    /// The size of the compressed file in bytes
+    source_bytes: u64,
+
+    /// The zstd frame reader and decoder
+    decoder: ruzstd::FrameDecoder,
+
+    /// The frames we found before decoding any, in order
+    frames: Vec<ZstdFrame>,
+
+    /// Number of frames to decode at once
+    pub(crate) threads: usize,
+
+    /// Worker threads for decoding frames ahead of the reader, started when we first need them
+    pool: Option<FramePool>,
+
+    /// Where the data of the current frame comes from
+    source: Source,
+}
+
+impl<R: Read + Seek> ZstdDecoder<R> {
+    fn read_u32(&mut self) -> std::io::Result<u32> {
+        let mut buf = [0; 4];
+        self.file.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    // Read the seek table at the end of a file in the seekable zstd format, if there is one.
+    // It gives us the position and size of every frame in the file without having to visit them.
+    fn read_seek_table(&mut self) -> std::io::Result<Option<Vec<Breadcrumb<()>>>> {
+        if self.source_bytes < SEEK_TABLE_FOOTER_SIZE + 8 {
+            return Ok(None);
+        }
+        self.file.seek(SeekFrom::Start(self.source_bytes - SEEK_TABLE_FOOTER_SIZE))?;
+        let count = self.read_u32()? as u64;
+        let mut descriptor = [0];
+        self.file.read_exact(&mut descriptor)?;
+        if self.read_u32()? != SEEKABLE_MAGIC {
+            return Ok(None);
+        }
+
+        let entry_size = if descriptor[0] & SEEK_TABLE_CHECKSUM_FLAG != 0 { 12 } else { 8 };
+        let table_size = count * entry_size + SEEK_TABLE_FOOTER_SIZE;
+        let Some(table_start) = self.source_bytes.checked_sub(table_size + 8) else { return Ok(None) };
+        self.file.seek(SeekFrom::Start(table_start))?;
+        if self.read_u32()? != SEEK_TABLE_MAGIC || self.read_u32()? as u64 != table_size {
+            return Ok(None);
+        }
+
+        let mut entries = vec![0; (count * entry_size) as usize];
+        self.file.read_exact(&mut entries)?;
+        let mut frames = Vec::new();
+        self.frames.clear();
+        let (mut physical, mut logical) = (0, 0);
+        for entry in entries.chunks(entry_size as usize) {
+            let compressed = u32::from_le_bytes(entry[0..4].try_into().unwrap()) as u64;
+            let decompressed = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as u64;
+            if decompressed > 0 {
+                frames.push(Breadcrumb::new(physical, logical, decompressed));
+                self.frames.push(ZstdFrame { physical, size: compressed, len: decompressed });
+            }
+            physical += compressed;
+            logical += decompressed;
+        }
+        if physical != table_start {
+            log::warn!("zstd seek table does not match the file; ignoring it");
+            self.frames.clear();
+            return Ok(None);
+        }
+        Ok(Some(frames))
+    }
+
+    // Find a frame we can decode on the worker threads
+    fn parallel_frame(&self, physical: u64) -> Option<usize> {
+        if self.threads < 2 || self.frames.len() < 2 {
+            return None;
+        }
+        let index = self.frames.binary_search_by_key(&physical, |frame| frame.physical).ok()?;
+        (self.frames[index].len <= MAX_PARALLEL_FRAME).then_some(index)
+    }
+
+    // Give the workers the frames from `index` on that they don't have yet
+    fn read_ahead(&mut self, index: usize) -> std::io::Result<()> {
+        let mut end = index;
+        let mut bytes = 0;
+        while let Some(frame) = self.frames.get(end)
+                && end < index + self.threads * 2
+                && frame.len <= MAX_PARALLEL_FRAME
+                && (end == index || bytes + frame.len <= READ_AHEAD_BYTES) {
+            bytes += frame.len;
+            end += 1;
+        }
+
+        let pool = self.pool.get_or_insert_with(|| FramePool::new(self.threads));
+        pool.retain(index..end);
+        for (i, frame) in self.frames.iter().enumerate().take(end).skip(index) {
+            if !pool.is_queued(i) {
+                self.file.seek(SeekFrom::Start(frame.physical))?;
+                let mut data = vec![0; frame.size as usize];
+                self.file.read_exact(&mut data)?;
+                pool.submit(i, data)?;
+            }
         }
+        Ok(())
+    }
+
+    fn skip_frame(&mut self) -> std::io::Result<(Option<u64>, u64)> {
+        match read_frame_header(&mut self.file) {
+            Err(ReadFrameHeaderError::SkipFrame(_magic_num, skip_size,)) => {
+                self.file.seek(SeekFrom::Current(skip_size as i64))?;
+                // Skipped a frame with no uncompressible bytes
+                // FIXME: Magic number "4" is the size of the frame header we parsed. read_frame_header should tell us that.
+                Ok((Some(0), 4u64 + skip_size as u64))
+            }
+            Ok((frame, bytes_read)) => {
+                // Started a new frame. Skip all the blocks.
+                let mut bytes_read = bytes_read as u64;
+                let mut block_dec = block_decoder::new();
+                loop {
+                    let (block_header, block_header_size) = block_dec
+                        .read_block_header(&mut self.file)
+                        .map_err(|e| zstd_error(FrameDecoderError::FailedToReadBlockHeader(e)))?;
+
+                    // block_header.decompressed_size is usually filled only after decoding the block  :-(
+                    bytes_read += block_header_size as u64;
+                    self.file.seek(SeekFrom::Current(block_header.content_size as i64))?;
+                    bytes_read += block_header.content_size as u64;
+                    if block_header.last_block {
+                        break;
+                    }
+                }
+                if frame.header.descriptor.content_checksum_flag() {
+                    self.file.seek(SeekFrom::Current(4))?;
+                    bytes_read += 4;
+                }
+                // Return the uncompressed size or None if we don't know
+                let uncompressed_bytes = frame.header.frame_content_size().ok();
+                Ok((uncompressed_bytes, bytes_read))
+            },
+            Err(other) => {
+                // Some error.  Quit early.
+                Err(std::io::Error::other(format!("File is not a valid zstd file: {:?}", other)))
+            },
+        }
+    }
+}
+
+impl<R: Read + Seek> Decoder for ZstdDecoder<R> {
+    type Reader = R;
+    type Context = ();
+
+    fn is_recognized(file: &mut R) -> bool {
+        match read_frame_header(file) {
+            Ok((frame, _bytes_read)) => {
+                frame.check_valid().is_ok()
+            },
+            _ => false,
+        }
+    }
+
+    fn new(mut file: R) -> std::io::Result<Self> {
+        let source_bytes = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            file,
+            source_bytes,
+            decoder: ruzstd::FrameDecoder::new(),
+            frames: Vec::new(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            pool: None,
+            source: Source::File,
+        })
+    }
+
+    // Scan all the zstd frame headers in the file and record their positions and sizes, if known
+    fn scan_frames(&mut self) -> std::io::Result<Vec<Breadcrumb<()>>> {
+        if let Some(frames) = self.read_seek_table()? {
+            return Ok(frames);
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+
+        let mut frames = Vec::new();
+        let mut pos = 0;
+
+        let mut fpos = 0;
+        while fpos < self.source_bytes {
+            // Starting a new frame.  Record details.
+            let (uncompressed_bytes, frame_bytes) = self.skip_frame()?;
+            if fpos + frame_bytes > self.source_bytes {
+                return Err(unsupported("truncated frame"));
+            }
+            match uncompressed_bytes {
+                None => {
+                    // No point continuing the scan because we don't know the uncompressed size
+                    // Leave an empty marker for the last physical frame position
+                    frames.push(Breadcrumb::new(fpos, pos, 0));
+                    break
+                },
+                Some(0) => { /* Skippable; no action */ },
+                Some(size) => {
+                    // eprintln!("Frame @ {fpos} holds {pos} to {}", pos+size);
+                    frames.push(Breadcrumb::new(fpos, pos, size));
+                    self.frames.push(ZstdFrame { physical: fpos, size: frame_bytes, len: size });
+                    pos += size;
+                }
+            }
+            fpos += frame_bytes;
+            if fpos != self.file.stream_position()? {
+                return Err(unsupported("frame size does not match its blocks"));
+            }
+        }
+        Ok(frames)
+    }
+
+    // Parse a frame header and automatically skip over Skippable Frames
+    fn begin(&mut self, physical: u64, _context: Option<&()>) -> std::io::Result<bool> {
+        if let Some(index) = self.parallel_frame(physical) {
+            self.read_ahead(index)?;
+            self.source = Source::Pool(index, false);
+            return Ok(true);
+        }
+
+        self.source = Source::File;
+        self.file.seek(SeekFrom::Start(physical))?;
+        while self.file.stream_position()? < self.source_bytes {
+            match self.decoder.reset(&mut self.file) {
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame(
+                    _magic_num,
+                    skip_size,
+                ))) => {
+                    self.file.seek(SeekFrom::Current(skip_size as i64))?;
+                    continue;
+                }
+                Ok(_) => {
+                    return Ok(true)
+                },
+                Err(e) => {
+                    return Err(zstd_error(e));
+                },
+            }
+        }
+        Ok(false)
+    }
+
+    fn decode(&mut self) -> std::io::Result<Vec<u8>> {
+        if let Source::Pool(index, done) = self.source {
+            // The workers decode the whole frame at once
+            if done {
+                return Ok(Vec::new());
+            }
+            self.source = Source::Pool(index, true);
+            return self.pool.as_mut().unwrap().wait(index);
+        }
+
+        loop {
+            if self.decoder.can_collect() > 0 {
+                // FIXME: We have to copy bytes twice here: Once from the Decoder buffer to ours, and once again
+                // to our reader.  We could skip the first copy if we had access to Decoder::buffer::as_slices(), but
+                // Decoder::buffer is private.  Shucks.  For now, we must copy.
+                return Ok(self.decoder.collect().unwrap_or_default());
+            } else if self.decoder.is_finished() {
+                // Reached end of frame
+                return Ok(Vec::new());
+            }
+
+            // Decode more bytes
+            self.decoder.decode_blocks(&mut self.file, BlockDecodingStrategy::UptoBlocks(1))
+                .map_err(zstd_error)?;
+        }
+    }
+
+    fn physical(&mut self) -> std::io::Result<u64> {
+        match self.source {
+            Source::Pool(index, _) => Ok(self.frames[index].physical + self.frames[index].size),
+            Source::File => self.file.stream_position(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::files::Stream;
+    use std::io::{Cursor, Write};
+
+    type ZstdCursor = CompressedFile<ZstdDecoder<Cursor<Vec<u8>>>>;
+
+    pub(crate) fn make_text(lines: usize) -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..lines {
+            writeln!(text, "{:08} line of the test {:x}", i, i * 7919 % 1000003).unwrap();
+        }
+        text
+    }
+
+    // Write a zstd frame of raw blocks with no content size, so only a seek table can tell us how big it is
+    pub(crate) fn zstd_frame(data: &[u8]) -> Vec<u8> {
+        const BLOCK_SIZE: usize = 128 * 1024;
+        let mut frame = 0xfd2fb528u32.to_le_bytes().to_vec();
+        // No flags, and a 128KB window
+        frame.extend([0, (17 - 10) << 3]);
+        let count = data.len().div_ceil(BLOCK_SIZE);
+        for (i, block) in data.chunks(BLOCK_SIZE).enumerate() {
+            let last = (i + 1 == count) as u32;
+            frame.extend(&(last | (block.len() as u32) << 3).to_le_bytes()[..3]);
+            frame.extend(block);
+        }
+        frame
+    }
+
+    fn seekable(parts: &[Vec<u8>], checksums: bool) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut table = Vec::new();
+        for part in parts {
+            let frame = zstd_frame(part);
+            table.extend((frame.len() as u32).to_le_bytes());
+            table.extend((part.len() as u32).to_le_bytes());
+            if checksums {
+                table.extend([0; 4]);
+            }
+            file.extend(frame);
+        }
+        table.extend((parts.len() as u32).to_le_bytes());
+        table.push(if checksums { SEEK_TABLE_CHECKSUM_FLAG } else { 0 });
+        table.extend(SEEKABLE_MAGIC.to_le_bytes());
+
+        file.extend(SEEK_TABLE_MAGIC.to_le_bytes());
+        file.extend((table.len() as u32).to_le_bytes());
+        file.extend(table);
+        file
+    }
+
+    #[test]
+    fn test_zstd_seek_table() {
+        let text = make_text(50000);
+        let parts = text.chunks(100000).map(|part| part.to_vec()).collect::<Vec<_>>();
+        for checksums in [false, true] {
+            let mut file = ZstdCursor::new(Cursor::new(seekable(&parts, checksums))).unwrap();
+
+            // Every frame is known before we decode any
+            assert_eq!(file.len(), text.len());
+            assert_eq!(file.frames.len(), parts.len());
+
+            for pos in [text.len() - 10, 0, 1234567, 100000, 99999, 17] {
+                file.seek(SeekFrom::Start(pos as u64)).unwrap();
+                let mut buf = vec![0; 1000];
+                let count = file.read(&mut buf).unwrap();
+                assert_eq!(&buf[..count], &text[pos..(pos + 1000).min(text.len())], "read at {}", pos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_zstd_parallel_frames() {
+        let text = make_text(100000);
+        let parts = text.chunks(50000).map(|part| part.to_vec()).collect::<Vec<_>>();
+        let mut file = ZstdCursor::new(Cursor::new(seekable(&parts, false))).unwrap();
+        file.decoder.threads = 4;
+
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+        assert!(file.decoder.pool.is_some());
+
+        // Jump around the read-ahead window, and read across the frames after each jump
+        for pos in [100, text.len() - 10, 1234567, 50000, 49999, 2000000, 17] {
+            file.seek(SeekFrom::Start(pos as u64)).unwrap();
+            let mut buf = vec![0; 300000];
+            file.read_exact(&mut buf[..(text.len() - pos).min(300000)]).unwrap();
+            let end = (pos + 300000).min(text.len());
+            assert_eq!(&buf[..end - pos], &text[pos..end], "read at {}", pos);
+        }
+    }
+
+    #[test]
+    fn test_zstd_truncated() {
+        let text = make_text(20000);
+        let mut compressed = zstd_frame(&text);
+
+        // Cut off in a block, in a block header, and in the frame header
+        for len in [compressed.len() - 10, 128 * 1024 + 7, 5] {
+            compressed.truncate(len);
+            let file = ZstdCursor::new(Cursor::new(compressed.clone()));
+            assert!(file.is_err(), "truncated to {}", len);
+        }
+    }
+
+    #[test]
+    fn test_zstd_unknown_sizes() {
+        let text = make_text(20000);
+        let parts = text.chunks(100000).map(|part| part.to_vec()).collect::<Vec<_>>();
+        let compressed = parts.iter().flat_map(|part| zstd_frame(part)).collect::<Vec<_>>();
+        let mut file = ZstdCursor::new(Cursor::new(compressed)).unwrap();
+
+        // Without a seek table we learn the frame sizes as we go
+        assert_eq!(file.frames.len(), 1);
+        let mut decoded = Vec::new();
+        file.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+        assert_eq!(file.len(), text.len());
+        assert_eq!(file.frames.len(), parts.len());
     }
 }
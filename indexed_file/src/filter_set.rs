@@ -0,0 +1,296 @@
+use crate::{index_filter::SearchType, indexer::{indexed_log::IndexStats, waypoint::Position, GetLine, IndexedLog}, log_filter::LogFilter, LogLine};
+
+/// One filter in a FilterSet
+struct FilterEntry {
+    /// The expression the user gave us, including any leading '!'
+    pattern: String,
+
+    /// Show matching lines (true) or hide them (false)
+    include: bool,
+
+    enabled: bool,
+    filter: LogFilter,
+}
+
+/**
+ * A set of filters applied together to a log.
+ *
+ * A line is shown if it matches any enabled include filter, or if there are no include filters, and it matches none of
+ * the enabled exclude filters.  Each filter keeps its own index of the source log, so it can be disabled and enabled
+ * again without rescanning the file.
+ *
+ * When only one filter is enabled we use it directly and hand out its Positions.  Otherwise we walk the filters
+ * together and hand out virtual Positions: forward hits point at the end of the line and backward hits point at its
+ * start. So advancing from a hit leaves the Position where it is.
+ */
+#[derive(Default)]
+pub(crate) struct FilterSet {
+    filters: Vec<FilterEntry>,
+}
+
+impl FilterSet {
+    /// Add a filter expression.  Expressions starting with '!' hide the lines they match.
+    /// If we already have this expression, just enable it again.
+    pub fn add(&mut self, pattern: &str, bytes_total: usize) -> Result<(), regex::Error> {
+        if let Some(entry) = self.filters.iter_mut().find(|entry| entry.pattern == pattern) {
+            entry.enabled = true;
+            return Ok(());
+        }
+
+        let search = SearchType::new(pattern)?;
+        let include = !matches!(search, SearchType::Neg(_));
+        self.filters.push(FilterEntry {
+            pattern: pattern.to_string(),
+            include,
+            enabled: true,
+            filter: LogFilter::new(search, bytes_total),
+        });
+        Ok(())
+    }
+
+    /// Replace the expression of one filter, keeping its place and whether it is enabled
+    pub fn replace(&mut self, index: usize, pattern: &str, bytes_total: usize) -> Result<(), regex::Error> {
+        let search = SearchType::new(pattern)?;
+        if let Some(entry) = self.filters.get_mut(index) && entry.pattern != pattern {
+            entry.pattern = pattern.to_string();
+            entry.include = !matches!(search, SearchType::Neg(_));
+            entry.filter = LogFilter::new(search, bytes_total);
+        }
+        Ok(())
+    }
+
+    /// Move a filter to another place in the list.  The order does not change which lines are shown.
+    pub fn move_to(&mut self, from: usize, to: usize) {
+        if from < self.filters.len() && to < self.filters.len() {
+            let entry = self.filters.remove(from);
+            self.filters.insert(to, entry);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.filters.len() {
+            self.filters.remove(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Enable or disable a filter without forgetting what it has indexed
+    pub fn enable(&mut self, index: usize, enabled: bool) {
+        if let Some(entry) = self.filters.get_mut(index) {
+            entry.enabled = enabled;
+        }
+    }
+
+    /// The filter expressions and whether each is enabled
+    pub fn iter(&self) -> impl Iterator<Item = (&str, bool)> + '_ {
+        self.filters.iter().map(|entry| (entry.pattern.as_str(), entry.enabled))
+    }
+
+    /// Index statistics for one filter.  Counts lines shown by the filter so far.
+    pub fn stats(&self, index: usize) -> Option<&IndexStats> {
+        self.filters.get(index).and_then(|entry| entry.filter.info().next())
+    }
+
+    /// True if any filter is enabled
+    pub fn is_active(&self) -> bool {
+        self.filters.iter().any(|entry| entry.enabled)
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &FilterEntry> + '_ {
+        self.filters.iter().filter(|entry| entry.enabled)
+    }
+
+    /// The filter to use directly if it is the only one enabled
+    fn single(&mut self) -> Option<&mut LogFilter> {
+        let mut enabled = self.filters.iter_mut().filter(|entry| entry.enabled);
+        match (enabled.next(), enabled.next()) {
+            (Some(entry), None) => Some(&mut entry.filter),
+            _ => None,
+        }
+    }
+
+    /// The enabled filters, with their place in the list and whether each one shows the lines it matches
+    pub(crate) fn enabled_filters(&self) -> impl Iterator<Item = (usize, &LogFilter, bool)> + '_ {
+        self.filters.iter().enumerate()
+            .filter(|(_, entry)| entry.enabled)
+            .map(|(index, entry)| (index, &entry.filter, entry.include))
+    }
+
+    pub(crate) fn filter_mut(&mut self, index: usize) -> Option<&mut LogFilter> {
+        self.filters.get_mut(index).map(|entry| &mut entry.filter)
+    }
+
+    fn has_includes(&self) -> bool {
+        self.enabled().any(|entry| entry.include)
+    }
+
+    /// Find the next line that passes the filters
+    pub fn find_next<LOG: IndexedLog + ?Sized>(&mut self, log: &mut LOG, pos: &Position) -> GetLine {
+        if let Some(filter) = self.single() {
+            return filter.find_next(log, pos);
+        }
+        if pos.is_invalid() {
+            return GetLine::Miss(Position::invalid());
+        }
+
+        let has_includes = self.has_includes();
+        let mut offset = pos.least_offset();
+        loop {
+            let from = Position::from(offset);
+
+            // Find the first line shown by any include filter
+            let line = if has_includes {
+                let mut found: Option<LogLine> = None;
+                for entry in self.filters.iter_mut().filter(|entry| entry.enabled && entry.include) {
+                    match entry.filter.find_next(log, &from) {
+                        GetLine::Hit(_, line) => {
+                            if found.as_ref().is_none_or(|found| line.offset < found.offset) {
+                                found = Some(line);
+                            }
+                        },
+                        GetLine::Miss(_) => {},
+                        GetLine::Timeout(_) => return GetLine::Timeout(from),
+                    }
+                }
+                match found {
+                    Some(line) => line,
+                    None => return GetLine::Miss(Position::invalid()),
+                }
+            } else {
+                match log.next(&from) {
+                    GetLine::Hit(_, line) => line,
+                    GetLine::Miss(_) => return GetLine::Miss(Position::invalid()),
+                    GetLine::Timeout(_) => return GetLine::Timeout(from),
+                }
+            };
+
+            // Skip ahead to the first line every exclude filter lets through
+            let at = Position::from(line.offset);
+            let mut next = line.offset;
+            for entry in self.filters.iter_mut().filter(|entry| entry.enabled && !entry.include) {
+                match entry.filter.find_next(log, &at) {
+                    GetLine::Hit(_, shown) => next = next.max(shown.offset),
+                    GetLine::Miss(_) => return GetLine::Miss(Position::invalid()),
+                    GetLine::Timeout(_) => return GetLine::Timeout(from),
+                }
+            }
+
+            if next == line.offset {
+                let end = log.line_end(&line);
+                return GetLine::Hit(Position::from(end), line);
+            }
+            offset = next;
+        }
+    }
+
+    /// Find the previous line that passes the filters
+    pub fn find_next_back<LOG: IndexedLog + ?Sized>(&mut self, log: &mut LOG, pos: &Position) -> GetLine {
+        if let Some(filter) = self.single() {
+            return filter.find_next_back(log, pos);
+        }
+        if pos.is_invalid() {
+            return GetLine::Miss(Position::invalid());
+        }
+
+        let has_includes = self.has_includes();
+        let mut offset = pos.most_offset();
+        loop {
+            let from = Position::from(offset);
+
+            // Find the last line shown by any include filter
+            let line = if has_includes {
+                let mut found: Option<LogLine> = None;
+                for entry in self.filters.iter_mut().filter(|entry| entry.enabled && entry.include) {
+                    match entry.filter.find_next_back(log, &from) {
+                        // A filter whose first line is at offset may give us that line; it is not before offset
+                        GetLine::Hit(_, line) if line.offset >= offset => {},
+                        GetLine::Hit(_, line) => {
+                            if found.as_ref().is_none_or(|found| line.offset > found.offset) {
+                                found = Some(line);
+                            }
+                        },
+                        GetLine::Miss(_) => {},
+                        GetLine::Timeout(_) => return GetLine::Timeout(from),
+                    }
+                }
+                match found {
+                    Some(line) => line,
+                    None => return GetLine::Miss(Position::invalid()),
+                }
+            } else {
+                match log.next_back(&from) {
+                    GetLine::Hit(_, line) => line,
+                    GetLine::Miss(_) => return GetLine::Miss(Position::invalid()),
+                    GetLine::Timeout(_) => return GetLine::Timeout(from),
+                }
+            };
+
+            // Skip back to the last line every exclude filter lets through
+            let end = log.line_end(&line);
+            let at = Position::from(end);
+            let mut prev = end;
+            for entry in self.filters.iter_mut().filter(|entry| entry.enabled && !entry.include) {
+                match entry.filter.find_next_back(log, &at) {
+                    GetLine::Hit(_, shown) if shown.offset < end => prev = prev.min(log.line_end(&shown)),
+                    GetLine::Hit(..) | GetLine::Miss(_) => return GetLine::Miss(Position::invalid()),
+                    GetLine::Timeout(_) => return GetLine::Timeout(from),
+                }
+            }
+
+            if prev == end {
+                return GetLine::Hit(Position::from(line.offset), line);
+            }
+            offset = prev;
+        }
+    }
+
+    pub fn advance(&mut self, pos: &Position) -> Position {
+        match self.single() {
+            Some(filter) => filter.advance(pos),
+            None => pos.clone(),
+        }
+    }
+
+    pub fn advance_back(&mut self, pos: &Position) -> Position {
+        match self.single() {
+            Some(filter) => filter.advance_back(pos),
+            None => pos.clone(),
+        }
+    }
+
+    /// Fill the gaps in each enabled filter in turn.  Returns where we stopped if we timed out.
+    pub fn resolve_gaps<LOG: IndexedLog + ?Sized>(&mut self, log: &mut LOG, pos: &Position) -> Position {
+        for entry in self.filters.iter_mut().filter(|entry| entry.enabled && entry.filter.has_gaps()) {
+            let stopped = entry.filter.resolve_gaps(log, pos);
+            if !stopped.is_invalid() {
+                return stopped;
+            }
+        }
+        Position::invalid()
+    }
+
+    pub fn info(&self) -> impl Iterator<Item = &IndexStats> + '_ {
+        self.enabled().flat_map(|entry| entry.filter.info())
+    }
+
+    /// Forget what we found because the source was truncated
+    pub fn reset(&mut self) {
+        for entry in self.filters.iter_mut() {
+            entry.filter.reset();
+        }
+    }
+
+    // We don't participate in poll(), so poll()-callers should update us by calling this
+    pub fn update_len(&mut self, len: usize) {
+        for entry in self.filters.iter_mut() {
+            entry.filter.update_len(len);
+        }
+    }
+
+    pub fn has_gaps(&self) -> bool {
+        self.enabled().any(|entry| entry.filter.has_gaps())
+    }
+}
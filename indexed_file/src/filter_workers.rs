@@ -0,0 +1,96 @@
+// Worker threads that test the lines in chunks of a log against our filters and searches, so a big file is filtered
+// in parallel while the UI keeps drawing.  The workers read the file themselves; we merge what they find into the
+// indexes of the filters and searches on our own thread.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::combined_filter::{CombinedFilter, Matches};
+use crate::files::ReadAt;
+use crate::worker_pool::WorkerPool;
+use crate::indexer::line_scanner::scan_lines;
+
+// Read as much of the buffer as the data allows
+fn read_all_at(reader: &dyn ReadAt, buf: &mut [u8], offset: usize) -> std::io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let count = reader.read_at(&mut buf[filled..], offset + filled)?;
+        if count == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        filled += count;
+    }
+    Ok(())
+}
+
+/// Test the lines that begin in a range of the log
+pub(crate) fn test_chunk(reader: &dyn ReadAt, range: &Range<usize>, filter: &CombinedFilter) -> std::io::Result<Matches> {
+    let lines = scan_lines(reader, range)?;
+    let (Some(&start), Some(&end)) = (lines.first(), lines.last()) else {
+        return Ok(Matches::new(filter.targets().len(), range.start));
+    };
+    let mut matches = Matches::new(filter.targets().len(), start);
+
+    let mut data = vec![0; end - start];
+    read_all_at(reader, &mut data, start)?;
+    for line in lines.windows(2) {
+        let range = line[0]..line[1];
+        let text = String::from_utf8_lossy(&data[range.start - start..range.end - start]);
+        filter.test(text.strip_suffix('\n').unwrap_or(&text), &range, &mut matches);
+    }
+    Ok(matches)
+}
+
+/// Workers filling the indexes of a set of filters and searches
+pub(crate) struct FilterWorkers {
+    pub pool: WorkerPool<std::io::Result<Matches>>,
+    filter: Arc<CombinedFilter>,
+}
+
+impl FilterWorkers {
+    pub fn new(filter: Arc<CombinedFilter>) -> Self {
+        Self {
+            pool: WorkerPool::new(),
+            filter,
+        }
+    }
+
+    /// True if these workers test with this filter, and not one we had before it changed
+    pub fn is_testing(&self, filter: &Arc<CombinedFilter>) -> bool {
+        Arc::ptr_eq(&self.filter, filter)
+    }
+
+    pub fn submit(&mut self, reader: Arc<dyn ReadAt>, range: Range<usize>) {
+        let filter = self.filter.clone();
+        self.pool.submit(range, move |range| test_chunk(reader.as_ref(), &range, &filter));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combined_filter::Target;
+    use crate::index_filter::{IndexFilter, LineMatcher, SearchType};
+
+    fn matcher(pattern: &str) -> LineMatcher {
+        IndexFilter::new(SearchType::new(pattern).unwrap(), 0, true).matcher().clone()
+    }
+
+    #[test]
+    fn test_chunk_filters_and_searches() {
+        let data = b"apple pie\nbanana split\napple crumble\ncherry pie\n".to_vec();
+        let (apple, crumble, pie) = (matcher("apple|cherry"), matcher("!crumble"), matcher("pie"));
+        let filter = CombinedFilter::new(
+            &[(Target::Filter(0), &apple, true), (Target::Filter(2), &crumble, false)],
+            &[(Target::Search(0), &pie)],
+        ).unwrap();
+        let matches = test_chunk(&data, &(0..data.len()), &filter).unwrap();
+        assert_eq!(matches.span, 0..data.len());
+        assert_eq!(matches.lines[2], vec![0..10, 37..48]);
+
+        // Only the lines that begin in the chunk
+        let matches = test_chunk(&data, &(5..24), &filter).unwrap();
+        assert_eq!(matches.span, 10..37);
+        assert_eq!(matches.lines, vec![vec![23..37], vec![10..23], vec![]]);
+    }
+}
@@ -11,7 +11,7 @@ use crate::indexer::waypoint::Position;
  * eventually indexes all lines that match the search criteria.
  */
 
- #[derive(Debug)]
+ #[derive(Clone, Debug)]
 pub enum SearchType {
     Regex(Regex),
     Neg(Regex),
@@ -44,11 +44,36 @@ impl SearchType {
     }
 }
 
-pub struct IndexFilter {
+/// The test an IndexFilter applies to each line.  It can be handed to worker threads.
+#[derive(Clone)]
+pub(crate) struct LineMatcher {
     f: SearchType,
 
     /// Filter in (true) or out (false)
     include: bool,
+}
+
+impl LineMatcher {
+    /// Test a line without its line ending
+    #[inline]
+    pub fn is_match(&self, line: &str) -> bool {
+        is_match_type(line, &self.f) ^ (!self.include)
+    }
+
+    /// The pattern to test lines with in a RegexSet instead, and whether we want the lines that don't match it.
+    /// Without a pattern, every line matches.
+    pub fn set_pattern(&self) -> (Option<String>, bool) {
+        match &self.f {
+            SearchType::Regex(re) => (Some(re.as_str().to_string()), !self.include),
+            SearchType::Neg(re) => (Some(re.as_str().to_string()), self.include),
+            SearchType::Raw(s) => (Some(regex::escape(s)), !self.include),
+            SearchType::None => (None, !self.include),
+        }
+    }
+}
+
+pub struct IndexFilter {
+    matcher: LineMatcher,
 
     /// Memoized index of matching lines
     pub(crate) index: SaneIndex,
@@ -80,8 +105,7 @@ impl IndexFilter {
     pub fn new(f: SearchType, bytes_total: usize, include: bool) -> Self {
         let name = format!("{}", f);
         IndexFilter {
-            f,
-            include,
+            matcher: LineMatcher { f, include },
             index: SaneIndex::new(name, bytes_total),
         }
     }
@@ -90,14 +114,13 @@ impl IndexFilter {
         self.index.reset()
     }
 
-    #[inline]
-    fn is_match(&self, line: &str) -> bool {
-        is_match_type(line, &self.f) ^ (!self.include)
+    pub(crate) fn matcher(&self) -> &LineMatcher {
+        &self.matcher
     }
 
     // Evaluate a new line for inclusion in the index
     pub fn eval(&mut self, line: &LogLine) -> bool {
-        self.is_match(trim_newline(line.line.as_str()))
+        self.matcher.is_match(trim_newline(line.line.as_str()))
     }
 
     // Resolve the gap at Position by inserting a new waypoint at the range given
@@ -0,0 +1,381 @@
+// Save line indexes on disk so we don't have to scan big files again every time we open them
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use directories::ProjectDirs;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use fnv::FnvHasher;
+
+use super::sane_index::SaneIndex;
+use super::waypoint::Waypoint;
+
+const MAGIC: &[u8; 8] = b"FELONIX1";
+
+// Bytes hashed at the start of the file, and before the end of the part we indexed
+const FINGERPRINT_BYTES: u64 = 64 * 1024;
+
+// Small files are quick to scan; don't clutter the cache with them
+const MIN_CACHE_SIZE: u64 = 1024 * 1024;
+
+/// What we know about the file an index was built from
+#[derive(Debug, PartialEq)]
+struct CacheKey {
+    path: String,
+    size: u64,
+    mtime: u128,
+
+    /// Hash of the first bytes of the file
+    head: u64,
+
+    /// Hash of the last bytes before `size`
+    tail: u64,
+}
+
+impl CacheKey {
+    fn new(path: &Path, size: u64, mtime: u128) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        Ok(Self {
+            path: path.display().to_string(),
+            size,
+            mtime,
+            head: fingerprint(&mut file, 0, size.min(FINGERPRINT_BYTES))?,
+            tail: fingerprint(&mut file, size.saturating_sub(FINGERPRINT_BYTES), size)?,
+        })
+    }
+
+    /// True if the file we were made from is unchanged in `current`, or if it has only had lines appended since
+    fn matches(&self, current: &CacheKey) -> bool {
+        if self.path != current.path || self.head != current.head {
+            false
+        } else if self.size == current.size {
+            self.mtime == current.mtime
+        } else if self.size < current.size {
+            // The file grew. Check the end of the part we indexed is still there.
+            let Ok(mut file) = File::open(&current.path) else { return false };
+            fingerprint(&mut file, self.size.saturating_sub(FINGERPRINT_BYTES), self.size).is_ok_and(|tail| tail == self.tail)
+        } else {
+            false
+        }
+    }
+}
+
+// Hash the bytes in [start, end) of the file
+fn fingerprint(file: &mut File, start: u64, end: u64) -> std::io::Result<u64> {
+    let mut buf = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.take(end - start).read_to_end(&mut buf)?;
+    let mut hasher = FnvHasher::default();
+    hasher.write(&buf);
+    Ok(hasher.finish())
+}
+
+/**
+ * A line index saved on disk for one file.
+ *
+ * The cache file holds the SaneIndex waypoints and the CacheKey of the file they describe.  We reuse an index if the
+ * file is unchanged, or if it only grew; the appended tail is still an unmapped region in the index, so only that part
+ * gets scanned.
+ */
+pub(crate) struct IndexCache {
+    /// Where the cache file lives
+    cache_path: PathBuf,
+    key: CacheKey,
+
+    /// Lines in the index when we loaded it, so we know if there is anything new to save
+    lines_loaded: usize,
+}
+
+impl IndexCache {
+    /// Find the cache for a file in our cache directory.  Returns None if the file is not one we cache.
+    pub fn open(path: &Path) -> Option<Self> {
+        let proj_dirs = ProjectDirs::from("com", "Phord Software", "Felon")?;
+        Self::open_in(path, proj_dirs.cache_dir())
+    }
+
+    /// Find the cache for a file in the given directory
+    pub fn open_in(path: &Path, cache_dir: &Path) -> Option<Self> {
+        let path = path.canonicalize().ok()?;
+        let metadata = path.metadata().ok()?;
+        if !metadata.is_file() || metadata.len() < MIN_CACHE_SIZE {
+            return None;
+        }
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        let key = CacheKey::new(&path, metadata.len(), mtime).ok()?;
+
+        let mut hasher = FnvHasher::default();
+        hasher.write(key.path.as_bytes());
+        let cache_path = cache_dir.join(format!("index-{:016x}", hasher.finish()));
+
+        Some(Self { cache_path, key, lines_loaded: 0 })
+    }
+
+    /// Load the saved index if it still describes our file
+    pub fn load(&mut self, bytes_total: usize) -> Option<SaneIndex> {
+        let file = File::open(&self.cache_path).ok()?;
+        let mut reader = BufReader::new(GzDecoder::new(BufReader::new(file)));
+        let (key, mut index) = match read_index(&mut reader) {
+            Ok(found) => found,
+            Err(e) => {
+                log::warn!("Ignoring bad index cache {:?}: {}", self.cache_path, e);
+                return None;
+            }
+        };
+        if !key.matches(&self.key) {
+            log::trace!("Index cache {:?} is stale", self.cache_path);
+            return None;
+        }
+
+        log::trace!("Loaded {} lines from index cache {:?}", index.stats.lines_indexed, self.cache_path);
+        index.stats.bytes_total = bytes_total;
+        self.lines_loaded = index.stats.lines_indexed;
+        Some(index)
+    }
+
+    /// Save the index if we learned anything since we loaded it
+    pub fn save(&self, index: &SaneIndex) -> std::io::Result<()> {
+        if index.stats.lines_indexed <= self.lines_loaded {
+            return Ok(());
+        }
+        if let Some(dir) = self.cache_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Write to a temporary file and move it into place so readers never see half a cache
+        let temp = self.cache_path.with_extension("tmp");
+        let mut writer = BufWriter::new(GzEncoder::new(File::create(&temp)?, Compression::fast()));
+        write_index(&mut writer, &self.key, index)?;
+        writer.into_inner().map_err(|e| e.into_error())?.finish()?;
+        std::fs::rename(&temp, &self.cache_path)?;
+        log::trace!("Saved {} lines to index cache {:?}", index.stats.lines_indexed, self.cache_path);
+        Ok(())
+    }
+}
+
+// The cache file is a sequence of LEB128 varints.  Mapped rows store the lengths of their lines instead of the offsets,
+// which usually takes a byte or two per line.
+fn write_varint<W: Write>(w: &mut W, mut value: u128) -> std::io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(r: &mut R) -> std::io::Result<u128> {
+    let mut value = 0u128;
+    for shift in (0..128).step_by(7) {
+        let mut byte = [0u8];
+        r.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u128) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+fn read_usize<R: Read>(r: &mut R) -> std::io::Result<usize> {
+    usize::try_from(read_varint(r)?).map_err(|_| invalid("value out of range"))
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+const ROW_UNMAPPED: u128 = 0;
+const ROW_MAPPED: u128 = 1;
+
+fn write_index<W: Write>(w: &mut W, key: &CacheKey, index: &SaneIndex) -> std::io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_varint(w, key.path.len() as u128)?;
+    w.write_all(key.path.as_bytes())?;
+    for value in [key.size as u128, key.mtime, key.head as u128, key.tail as u128] {
+        write_varint(w, value)?;
+    }
+    write_varint(w, index.stats.bytes_indexed as u128)?;
+    write_varint(w, index.stats.lines_indexed as u128)?;
+
+    write_varint(w, index.index.len() as u128)?;
+    for row in index.index.iter() {
+        match row.front() {
+            Some(Waypoint::Unmapped(range)) => {
+                write_varint(w, ROW_UNMAPPED)?;
+                write_varint(w, range.start as u128)?;
+                write_varint(w, range.end as u128)?;
+            },
+            Some(Waypoint::Mapped(range)) => {
+                write_varint(w, ROW_MAPPED)?;
+                write_varint(w, row.len() as u128)?;
+                write_varint(w, range.start as u128)?;
+                for waypoint in row.iter() {
+                    let Waypoint::Mapped(range) = waypoint else { return Err(invalid("unmapped waypoint in mapped row")) };
+                    write_varint(w, (range.end - range.start) as u128)?;
+                }
+            },
+            None => {
+                // Empty rows are skipped when we read them back
+                write_varint(w, ROW_MAPPED)?;
+                write_varint(w, 0)?;
+                write_varint(w, 0)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+fn read_index<R: Read>(r: &mut R) -> std::io::Result<(CacheKey, SaneIndex)> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not an index cache"));
+    }
+    let mut path = vec![0u8; read_usize(r)?];
+    r.read_exact(&mut path)?;
+    let key = CacheKey {
+        path: String::from_utf8(path).map_err(|_| invalid("bad path"))?,
+        size: read_varint(r)? as u64,
+        mtime: read_varint(r)?,
+        head: read_varint(r)? as u64,
+        tail: read_varint(r)? as u64,
+    };
+
+    let mut index = SaneIndex::default();
+    index.stats.bytes_indexed = read_usize(r)?;
+    index.stats.lines_indexed = read_usize(r)?;
+
+    let rows = read_usize(r)?;
+    index.index = Vec::with_capacity(rows);
+    for _ in 0..rows {
+        let row = match read_varint(r)? {
+            ROW_UNMAPPED => VecDeque::from([Waypoint::Unmapped(read_usize(r)?..read_usize(r)?)]),
+            ROW_MAPPED => {
+                let count = read_usize(r)?;
+                let mut offset = read_usize(r)?;
+                let mut row = VecDeque::with_capacity(count);
+                for _ in 0..count {
+                    let end = offset.checked_add(read_usize(r)?).ok_or_else(|| invalid("line out of range"))?;
+                    row.push_back(Waypoint::Mapped(offset..end));
+                    offset = end;
+                }
+                row
+            },
+            _ => return Err(invalid("bad row type")),
+        };
+        if !row.is_empty() {
+            index.index.push(row);
+        }
+    }
+    if index.index.is_empty() {
+        return Err(invalid("empty index"));
+    }
+    Ok((key, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IndexedLog, Log};
+
+    // A file big enough to be cached, and a cache dir of its own
+    fn make_test_file(name: &str, lines: usize) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("felon-index-cache-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        let mut file = BufWriter::new(File::create(&path).unwrap());
+        for i in 0..lines {
+            writeln!(file, "{:08} This is line number {} of the test file", i, i).unwrap();
+        }
+        (path, dir.join("cache"))
+    }
+
+    fn append_lines(path: &Path, from: usize, lines: usize) {
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        for i in from..from + lines {
+            writeln!(file, "{:08} This is line number {} of the test file", i, i).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_index_cache_roundtrip() {
+        let (path, cache_dir) = make_test_file("roundtrip", 50000);
+        {
+            let mut log = Log::open_cached_in(Some(&path), &cache_dir).unwrap();
+            assert_eq!(log.iter_lines().count(), 50000);
+        }
+
+        // The second time we don't have to scan anything
+        let log = Log::open_cached_in(Some(&path), &cache_dir).unwrap();
+        assert!(!log.has_gaps());
+        assert_eq!(log.info().next().unwrap().lines_indexed, 50000);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_index_cache_appended() {
+        let (path, cache_dir) = make_test_file("appended", 50000);
+        {
+            let mut log = Log::open_cached_in(Some(&path), &cache_dir).unwrap();
+            assert_eq!(log.iter_lines().count(), 50000);
+        }
+
+        // Lines appended to the file are scanned; the rest comes from the cache
+        append_lines(&path, 50000, 100);
+        let mut log = Log::open_cached_in(Some(&path), &cache_dir).unwrap();
+        assert!(log.has_gaps());
+        assert_eq!(log.info().next().unwrap().lines_indexed, 50000);
+        let lines = log.iter_lines().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 50100);
+        assert!(lines[50099].starts_with("00050099 "));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_index_cache_changed() {
+        let (path, cache_dir) = make_test_file("changed", 50000);
+        {
+            let mut log = Log::open_cached_in(Some(&path), &cache_dir).unwrap();
+            assert_eq!(log.iter_lines().count(), 50000);
+        }
+
+        // A different file at the same path doesn't use the old index
+        make_test_file("changed", 40000);
+        let mut log = Log::open_cached_in(Some(&path), &cache_dir).unwrap();
+        assert_eq!(log.info().next().unwrap().lines_indexed, 0);
+        assert_eq!(log.iter_lines().count(), 40000);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_index_cache_preprocessed() {
+        use crate::files::{LogBase, PreprocessedFile, Stream};
+
+        let (path, cache_dir) = make_test_file("preprocessed", 50000);
+        let open = || {
+            let mut file = PreprocessedFile::new(&path, "|cat %s", None).unwrap().unwrap();
+            file.wait_for_end();
+            Log::with_cache(Log::from(file.to_src()), IndexCache::open_in(&path, &cache_dir))
+        };
+        {
+            let mut log = open();
+            assert_eq!(log.iter_lines().count(), 50000);
+        }
+
+        // The preprocessor's output may change while the file doesn't, so we don't keep an index of it
+        assert!(!cache_dir.exists());
+        let log = open();
+        assert_eq!(log.info().next().unwrap().lines_indexed, 0);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -21,7 +21,7 @@ impl GetLine {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct IndexStats {
     pub name: String,
     pub bytes_indexed: usize,
@@ -44,6 +44,27 @@ impl IndexStats {
     }
 }
 
+/// The number of a line in the log, counting from 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineNumber {
+    /// Every line before it is indexed
+    Exact(usize),
+    /// Some lines before it are not indexed yet, so we guessed how many there are from the lines we did index
+    Estimate(usize),
+    /// We haven't indexed enough of the log to guess
+    Unknown,
+}
+
+impl std::fmt::Display for LineNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineNumber::Exact(number) => write!(f, "{}", number),
+            LineNumber::Estimate(number) => write!(f, "~{}", number),
+            LineNumber::Unknown => write!(f, "~"),
+        }
+    }
+}
+
 pub trait IndexedLog: Stream {
     /// Return a Position to read from given offset.
     /// Always returns a generic virtual position that can be used on any index.
@@ -55,6 +76,10 @@ pub trait IndexedLog: Stream {
     // TODO: Move this into Log
     fn read_line(&mut self, offset: usize) -> Option<LogLine>;
 
+    /// Offset just past the end of a line we read, as it is in the file.  The LogLine may be longer than the bytes it
+    /// came from if they had invalid utf-8, so don't use its length to find the next line.
+    fn line_end(&mut self, line: &LogLine) -> usize;
+
     /// Read the next/prev line from the file
     /// returns
     ///    GetLine::Hit:     found line and its indexed position
@@ -90,7 +115,7 @@ pub trait IndexedLog: Stream {
     where Self: Sized ;
 
     // Autowrap
-    fn with_timeout(&mut self, ms: u64) -> TimeoutWrapper<Self> where Self: std::marker::Sized {
+    fn with_timeout(&mut self, ms: u64) -> TimeoutWrapper<'_, Self> where Self: std::marker::Sized {
         TimeoutWrapper::new(self, ms as usize)
     }
 
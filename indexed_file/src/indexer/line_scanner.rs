@@ -0,0 +1,72 @@
+// Find the lines in chunks of a log on worker threads, so we can index a big file in parallel without stalling the UI
+
+use std::ops::Range;
+
+use crate::files::ReadAt;
+
+// Bytes the workers read at a time
+const READ_SIZE: usize = 1024 * 1024;
+
+pub(crate) type ScanResult = std::io::Result<Vec<usize>>;
+
+/// Find the lines that begin in a range of the log, like LogFile::find_lines.  Returns the start of each line and the
+/// end of the last one, so we read past the end of the range to find where its last line ends.  Lines that began
+/// before the range belong to whoever scans the range before it.
+pub(crate) fn scan_lines(reader: &dyn ReadAt, range: &Range<usize>) -> ScanResult {
+    let mut lines = Vec::new();
+    if range.start == 0 {
+        // There's always a line beginning at zero
+        lines.push(0);
+    }
+
+    // Begin one byte early to see whether a line begins right at the start
+    let mut offset = range.start.saturating_sub(1);
+    let mut buf = vec![0; READ_SIZE.min(range.len() + 1)];
+    loop {
+        let count = reader.read_at(&mut buf, offset)?;
+        if count == 0 {
+            // The last line has no LF
+            if lines.last().is_some_and(|&last| last < offset) {
+                lines.push(offset);
+            }
+            return Ok(lines);
+        }
+        for eol in memchr::memchr_iter(b'\n', &buf[..count]) {
+            let line = offset + eol + 1;
+            if line >= range.start {
+                lines.push(line);
+            }
+            if line >= range.end {
+                return Ok(lines);
+            }
+        }
+        offset += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl ReadAt for Vec<u8> {
+        fn read_at(&self, buf: &mut [u8], offset: usize) -> std::io::Result<usize> {
+            let data = &self[offset.min(self.len())..];
+            let count = data.len().min(buf.len());
+            buf[..count].copy_from_slice(&data[..count]);
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn test_scan_lines() {
+        let data = b"one\ntwo\nthree\n\nfour".to_vec();
+        assert_eq!(scan_lines(&data, &(0..data.len())).unwrap(), vec![0, 4, 8, 14, 15, 19]);
+        assert_eq!(scan_lines(&data, &(0..5)).unwrap(), vec![0, 4, 8]);
+
+        // Lines beginning in the range, and the end of the last one
+        assert_eq!(scan_lines(&data, &(4..9)).unwrap(), vec![4, 8, 14]);
+        assert_eq!(scan_lines(&data, &(5..9)).unwrap(), vec![8, 14]);
+        assert_eq!(scan_lines(&data, &(15..19)).unwrap(), vec![15, 19]);
+        assert_eq!(scan_lines(&data, &(16..19)).unwrap(), Vec::<usize>::new());
+    }
+}
@@ -4,7 +4,10 @@ pub mod indexed_log;
 pub mod sane_indexer;
 pub(crate) mod waypoint;
 pub(crate) mod timeout;
+pub(crate) mod index_cache;
+pub(crate) mod line_scanner;
 
 pub use indexed_log::IndexedLog;
 pub use indexed_log::GetLine;
+pub use waypoint::Position;
 pub use timeout::TimeoutWrapper;
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use super::{indexed_log::IndexStats, waypoint::{Position, VirtualPosition, Waypoint}};
+use super::{indexed_log::{IndexStats, LineNumber}, waypoint::{Position, VirtualPosition, Waypoint}};
 
 const IMAX:usize = usize::MAX;
 type Range = std::ops::Range<usize>;
@@ -110,6 +110,15 @@ impl SaneIndex {
         Position::Virtual(VirtualPosition::End)
     }
 
+    /// Find the first gap that ends after the offset
+    pub(crate) fn gap_after(&self, offset: usize) -> Position {
+        let mut gap = self.seek_gap(&Position::from(offset));
+        while gap.is_unmapped() && gap.region().end <= offset {
+            gap = self.seek_gap(&gap.advance(self));
+        }
+        gap
+    }
+
     /// Find the index holding the given offset, or where it would be inserted if none found.
     pub(crate) fn search(&self, offset: usize) -> IndexIndex {
         if self.index.is_empty() {
@@ -141,15 +150,13 @@ impl SaneIndex {
             },
         };
 
-        if let Some(prev) = self.index_prev(ndx) {
-            if self.value(prev).contains(offset) {
-                return prev;
-            }
+        if let Some(prev) = self.index_prev(ndx)
+            && self.value(prev).contains(offset) {
+            return prev;
         }
-        if self.index_valid(ndx) && offset > self.value(ndx).cmp_offset() {
-            if let Some(next) = self.index_next(ndx) {
-                return next;
-            }
+        if self.index_valid(ndx) && offset > self.value(ndx).cmp_offset()
+            && let Some(next) = self.index_next(ndx) {
+            return next;
         }
         ndx
     }
@@ -236,10 +243,9 @@ impl SaneIndex {
             if let Some(next) = self.index_next(ndx) {
                 ndx = next;
             }
-        } else if let Some(prev) = self.index_prev(ndx) {
-            if self.value(prev).contains(gap.start) {
-                ndx = prev;
-            }
+        } else if let Some(prev) = self.index_prev(ndx)
+            && self.value(prev).contains(gap.start) {
+            ndx = prev;
         }
         assert!(self.index_valid(ndx));
 
@@ -345,8 +351,100 @@ impl SaneIndex {
         Position::Existing((row, col), waypoint_pos)
     }
 
+    /// Insert the lines between each pair of line offsets, skipping any we already mapped
+    pub(crate) fn insert_lines(&mut self, lines: &[usize]) {
+        let mut pos = Position::invalid();
+        for line in lines.windows(2) {
+            let range = line[0]..line[1];
+            if !pos.is_unmapped() || !pos.region().contains(&range.start) {
+                pos = Position::from(range.start).resolve(self);
+            }
+            if pos.is_unmapped() && pos.region().contains(&range.start) {
+                pos = self.insert_one(&pos, &range).advance(self);
+            }
+        }
+    }
+
+    /// Map a range of the file where we tested every line: insert the given lines and erase the rest, except where we
+    /// already mapped it
+    pub(crate) fn fill(&mut self, span: &Range, lines: &[Range]) {
+        let mut offset = span.start;
+        for line in lines.iter().chain(std::iter::once(&(span.end..span.end))) {
+            self.erase_unmapped(&(offset..line.start));
+            if !line.is_empty() {
+                let pos = Position::from(line.start).resolve(self);
+                if pos.is_unmapped() && pos.region().contains(&line.start) {
+                    self.insert_one(&pos, line);
+                }
+            }
+            offset = line.end;
+        }
+    }
+
+    // Erase the parts of a range we haven't mapped yet
+    fn erase_unmapped(&mut self, range: &Range) {
+        let mut offset = range.start;
+        loop {
+            let gap = self.gap_after(offset);
+            if !gap.is_unmapped() || gap.region().start >= range.end {
+                break;
+            }
+            let erase = gap.region().start.max(offset)..gap.region().end.min(range.end);
+            offset = erase.end;
+            self.erase_gap(&gap, &erase);
+        }
+    }
+
+    /// Number of the line holding offset.  Each mapped row holds a run of lines with no gaps between them, so we count
+    /// the rows before the offset.  If there are gaps before it, we guess how many lines they hold from the lines we
+    /// mapped so far.
+    pub(crate) fn line_number(&self, offset: usize) -> LineNumber {
+        let mut lines = 0;
+        let mut unmapped = 0;
+        for row in &self.index {
+            let first = &row[0];
+            if first.cmp_offset() > offset {
+                break;
+            }
+            if first.is_mapped() {
+                lines += row.partition_point(|waypoint| waypoint.cmp_offset() <= offset);
+            } else {
+                unmapped += first.end_offset().min(offset + 1) - first.cmp_offset();
+            }
+        }
+
+        if unmapped == 0 {
+            LineNumber::Exact(lines)
+        } else if self.stats.bytes_indexed > 0 && self.stats.lines_indexed > 0 {
+            let density = self.stats.lines_indexed as f64 / self.stats.bytes_indexed as f64;
+            LineNumber::Estimate(lines + (unmapped as f64 * density).ceil() as usize)
+        } else {
+            LineNumber::Unknown
+        }
+    }
+
+    /// Offset of the line with the given number, counting from 1, if every line before it is mapped.  Otherwise returns
+    /// the number of lines before the first gap and the offset where the last of them ends.
+    pub(crate) fn line_offset(&self, number: usize) -> Result<usize, (usize, usize)> {
+        let number = number.max(1);
+        let mut lines = 0;
+        let mut end = 0;
+        for row in &self.index {
+            let last = row.back().unwrap();
+            if !last.is_mapped() {
+                break;
+            }
+            if lines + row.len() >= number {
+                return Ok(row[number - lines - 1].cmp_offset());
+            }
+            lines += row.len();
+            end = last.end_offset();
+        }
+        Err((lines, end))
+    }
+
     #[cfg(test)]
-    pub(crate) fn iter(&self) -> SaneIter {
+    pub(crate) fn iter(&self) -> SaneIter<'_> {
         SaneIter::new(self)
     }
 }
@@ -415,3 +513,51 @@ fn sane_index_basic_rev() {
     index.insert(&(14..30));
     assert_eq!(index.iter().collect::<Vec<_>>(), vec![Waypoint::Mapped(13..14), Waypoint::Mapped(14..30), Waypoint::Unmapped(30..52), Waypoint::Unmapped(67..IMAX)]);
 }
+
+#[test]
+fn sane_index_insert_lines() {
+    let mut index = SaneIndex::default();
+    // Chunks scanned out of order, overlapping lines we already found
+    index.insert_lines(&[30, 51, 52, 67]);
+    index.insert(&(13..14));
+    index.insert_lines(&[0, 13, 14, 30, 51]);
+    assert_eq!(index.iter().collect::<Vec<_>>(), vec![Waypoint::Mapped(0..13), Waypoint::Mapped(13..14), Waypoint::Mapped(14..30), Waypoint::Mapped(30..51), Waypoint::Mapped(51..52), Waypoint::Mapped(52..67), Waypoint::Unmapped(67..IMAX)]);
+    assert_eq!(index.stats.lines_indexed, 6);
+}
+
+#[test]
+fn sane_index_fill() {
+    let mut index = SaneIndex::default();
+    index.insert(&(14..30));
+    // Lines 0..13, 30..51 and 52..67 matched in 0..67
+    index.fill(&(0..67), &[0..13, 30..51, 52..67]);
+    assert_eq!(index.iter().collect::<Vec<_>>(), vec![Waypoint::Mapped(0..13), Waypoint::Mapped(14..30), Waypoint::Mapped(30..51), Waypoint::Mapped(52..67), Waypoint::Unmapped(67..IMAX)]);
+    assert_eq!(index.stats.bytes_indexed, 67);
+}
+
+#[test]
+fn sane_index_line_numbers() {
+    let mut index = SaneIndex::default();
+    index.insert(&(0..13));
+    index.insert(&(13..14));
+    index.insert(&(52..67));
+    assert_eq!(index.line_number(0), LineNumber::Exact(1));
+    assert_eq!(index.line_number(13), LineNumber::Exact(2));
+    assert_eq!(index.line_offset(2), Ok(13));
+
+    // Lines in and after the gap are estimated from the 29 bytes we mapped in three lines
+    assert_eq!(index.line_number(20), LineNumber::Estimate(3));
+    assert_eq!(index.line_number(52), LineNumber::Estimate(7));
+    assert_eq!(index.line_offset(3), Err((2, 14)));
+
+    index.insert(&(14..30));
+    index.insert(&(30..51));
+    index.insert(&(51..52));
+    assert_eq!(index.line_number(29), LineNumber::Exact(3));
+    assert_eq!(index.line_number(52), LineNumber::Exact(6));
+    assert_eq!(index.line_number(66), LineNumber::Exact(6));
+    assert_eq!(index.line_offset(6), Ok(52));
+    assert_eq!(index.line_offset(7), Err((6, 67)));
+
+    assert_eq!(SaneIndex::default().line_number(10), LineNumber::Unknown);
+}
@@ -5,12 +5,15 @@ extern crate lru;
 use std::fmt;
 use std::time::Duration;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 use lru::LruCache;
 
-use crate::files::{LogFile, Stream};
+use crate::files::{LogFile, ReadAt, Stream};
 use crate::LogLine;
 
 use super::indexed_log::{IndexStats, IndexedLog};
+use crate::worker_pool::WorkerPool;
+use super::line_scanner::{scan_lines, ScanResult};
 use super::sane_index::SaneIndex;
 use super::timeout::Timeout;
 use super::waypoint::Position;
@@ -21,7 +24,14 @@ pub struct SaneIndexer<LOG> {
     source: LOG,
     index: SaneIndex,
     timeout: Timeout,
-    line_cache: LruCache<usize, LogLine>,
+    // Lines we read lately, with the bytes each one takes in the file
+    line_cache: LruCache<usize, (LogLine, usize)>,
+
+    // Truncations of the source we already know about
+    truncations: usize,
+
+    // Workers filling big gaps in the index, once we have some
+    scanner: Option<WorkerPool<ScanResult>>,
 }
 
 impl<LOG: LogFile> fmt::Debug for SaneIndexer<LOG> {
@@ -33,6 +43,9 @@ impl<LOG: LogFile> fmt::Debug for SaneIndexer<LOG> {
 
 const CHUNK_SIZE:usize = 64 * 1024;
 
+// Size of the chunks we give to the workers to scan.  We only start the workers for more than a couple of these.
+const SCAN_CHUNK_SIZE:usize = 4 * 1024 * 1024;
+
 impl<LOG: LogFile> SaneIndexer<LOG> {
 
     pub fn new(file: LOG) -> SaneIndexer<LOG> {
@@ -44,7 +57,50 @@ impl<LOG: LogFile> SaneIndexer<LOG> {
             index,
             timeout: Timeout::Inactive(false),
             line_cache: LruCache::new(NonZeroUsize::new(1000).unwrap()),
+            truncations: 0,
+            scanner: None,
+        }
+    }
+
+    pub(crate) fn shared_reader(&self) -> Option<Arc<dyn ReadAt>> {
+        self.source.shared_reader()
+    }
+
+    /// True if our source reads its file directly, so we can cache its index
+    pub(crate) fn reads_file(&self) -> bool {
+        self.source.reads_file()
+    }
+
+    /// Read the bytes of the line at offset as they are in the file
+    pub(crate) fn read_bytes(&mut self, offset: usize) -> Option<Vec<u8>> {
+        self.source.read_bytes_at(offset).ok().filter(|bytes| !bytes.is_empty())
+    }
+
+    // Read the line at offset and the number of bytes it takes in the file.  Invalid utf-8 is replaced in the line, so
+    // its length may not match.
+    pub(crate) fn read_line_len(&mut self, offset: usize) -> Option<(LogLine, usize)> {
+        if let Some(line) = self.line_cache.get(&offset) {
+            return Some(line.clone());
         }
+        let bytes = self.source.read_bytes_at(offset).unwrap();
+        if !bytes.is_empty() {
+            let line = (LogLine::new(String::from_utf8_lossy(&bytes).into_owned(), offset), bytes.len());
+            self.line_cache.put(offset, line.clone());
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn index(&self) -> &SaneIndex {
+        &self.index
+    }
+
+    /// Replace our index with one we saved before
+    pub(crate) fn set_index(&mut self, mut index: SaneIndex) {
+        index.stats.name = std::mem::take(&mut self.index.stats.name);
+        index.stats.bytes_total = self.len();
+        self.index = index;
     }
 
     /// read and memoize a line containing a given offset from a BufRead
@@ -58,17 +114,20 @@ impl<LOG: LogFile> SaneIndexer<LOG> {
         } else if offset >= self.len() {
             GetLine::Miss(Position::invalid())
         } else {
-            let next = self.read_line(offset);
+            let next = self.read_line_len(offset);
 
             let mut pos = pos.resolve(&self.index);
             if pos.is_unmapped() {
-                if let Some(ref line) = next {
-                    pos = self.index.insert_one(&pos, &(line.offset..line.offset + line.line.len()));
+                if let Some((ref line, len)) = next {
+                    pos = self.index.insert_one(&pos, &(line.offset..line.offset + len));
                 } else {
-                    panic!("Read error? offset={}", offset);
+                    // The source ended before the length it estimated, like a compressed file with no sizes in it.
+                    // It knows its real length now.
+                    self.index.stats.bytes_total = self.source.len();
+                    return GetLine::Miss(Position::invalid());
                 }
             }
-            GetLine::Hit(pos, next.unwrap_or_default())
+            GetLine::Hit(pos, next.map(|(line, _)| line).unwrap_or_default())
         }
     }
 
@@ -103,6 +162,49 @@ impl<LOG: LogFile> SaneIndexer<LOG> {
     }
 
 
+    /// Fill the gaps in the index on worker threads, scanning chunks of the log in parallel and merging the lines they
+    /// find as they finish.  Returns the position to continue from if we timed out, or None when there's nothing left
+    /// for the workers to do, or if the log can't be read by other threads.  Whatever they missed is left for us.
+    fn scan_gaps(&mut self, pos: &Position) -> Option<Position> {
+        let reader = self.source.shared_reader()?;
+        let mut scanner = match self.scanner.take() {
+            Some(scanner) => scanner,
+            None if self.len().saturating_sub(self.index.stats.bytes_indexed) > 2 * SCAN_CHUNK_SIZE => WorkerPool::new(),
+            None => return None,
+        };
+
+        let result = loop {
+            // Keep the workers busy with the next chunks of the gaps
+            while !scanner.is_busy() {
+                let gap = self.index.gap_after(scanner.next);
+                if !gap.is_unmapped() {
+                    break;
+                }
+                let start = gap.region().start.max(scanner.next);
+                let end = gap.region().end.min(self.len()).min(start + SCAN_CHUNK_SIZE);
+                if start >= end {
+                    break;
+                }
+                let reader = reader.clone();
+                scanner.submit(start..end, move |range| scan_lines(reader.as_ref(), &range));
+            }
+
+            if scanner.is_idle() {
+                break None;
+            }
+            match scanner.receive(Duration::from_millis(5)) {
+                Some(Ok(lines)) => self.index.insert_lines(&lines),
+                Some(Err(e)) => log::warn!("Error scanning the file for lines: {}", e),
+                None => {},
+            }
+            if self.check_timeout() {
+                break Some(self.index.seek_gap(pos));
+            }
+        };
+        self.scanner = Some(scanner);
+        result
+    }
+
     /// Scan a chunk of space bounded by pos before the offset position to find the start of our target line
     /// Return the last line found before offset in the region.
     /// Note: offset is inclusive
@@ -153,10 +255,19 @@ impl<LOG: LogFile> Stream for SaneIndexer<LOG> {
 
     fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize {
         self.index.stats.bytes_total = self.source.poll(timeout);
+        if self.source.truncations() != self.truncations {
+            // The lines we found are gone
+            self.truncations = self.source.truncations();
+            self.index.reset();
+            self.line_cache.clear();
+            self.scanner = None;
+        }
         self.index.stats.bytes_total
     }
 
     fn is_open(&self) -> bool { self.source.is_open() }
+
+    fn truncations(&self) -> usize { self.source.truncations() }
 }
 
 impl<LOG: LogFile> IndexedLog for SaneIndexer<LOG> {
@@ -178,21 +289,20 @@ impl<LOG: LogFile> IndexedLog for SaneIndexer<LOG> {
 
     /// Read the line starting from offset to EOL
     fn read_line(&mut self, offset: usize) -> Option<LogLine> {
-        // Find the line containing offset, if any
-        if let Some(line) = self.line_cache.get(&offset) {
-            return Some(line.clone());
-        }
-        let line = self.source.read_line_at(offset).unwrap();
-        if !line.is_empty() {
-            let line = LogLine::new(line, offset);
-            self.line_cache.put(offset, line.clone());
-            Some(line)
-        } else {
-            None
+        self.read_line_len(offset).map(|(line, _)| line)
+    }
+
+    fn line_end(&mut self, line: &LogLine) -> usize {
+        match self.read_line_len(line.offset) {
+            Some((_, len)) => line.offset + len,
+            None => line.offset + line.line.len(),
         }
     }
 
     fn resolve_gaps(&mut self, pos: &Position) -> Position {
+        if let Some(pos) = self.scan_gaps(pos) {
+            return pos;
+        }
         let mut pos = self.index.seek_gap(pos);
         while pos.is_unmapped() {
             // Resolve unmapped region
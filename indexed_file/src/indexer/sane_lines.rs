@@ -1,4 +1,4 @@
-/// SaneLines combines a SaneIndex with a LogFile to provide an iterator over lines in a log file.
+// SaneLines combines a SaneIndex with a LogFile to provide an iterator over lines in a log file.
 
 use crate::LogLine;
 
@@ -136,3 +136,30 @@ fn sane_index_rev_line_zero() {
     assert!(log.next_back().is_some());
     assert!(log.next_back().is_none());
 }
+
+#[test]
+fn sane_index_line_numbers() {
+    use crate::files::CursorLogFile;
+    use crate::indexer::indexed_log::LineNumber;
+    let file = b"Hello, world\n\nThis is a test.\nThis is only a test.\n\nEnd of message\n";
+    let cursor = CursorLogFile::new(file.to_vec());
+    let mut log = Log::from(cursor);
+
+    // Nothing is indexed yet
+    assert_eq!(log.line_count(), LineNumber::Unknown);
+
+    // Finding a line reads the ones before it
+    let found = |get| match get {
+        GetLine::Hit(_, line) => Some(line.offset),
+        _ => None,
+    };
+    assert_eq!(found(log.find_line(5)), Some(51));
+    assert_eq!(log.line_number(51), LineNumber::Exact(5));
+    assert_eq!(log.line_number(60), LineNumber::Estimate(6));
+    assert_eq!(found(log.find_line(1)), Some(0));
+
+    // Looking past the end reads the rest
+    assert_eq!(found(log.find_line(7)), None);
+    assert_eq!(log.line_number(60), LineNumber::Exact(6));
+    assert_eq!(log.line_count(), LineNumber::Exact(6));
+}
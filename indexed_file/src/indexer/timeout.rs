@@ -18,10 +18,9 @@ impl Timeout {
 
     // Checks if timer expired and set timed-out latch if so
     pub(crate) fn is_timed_out(&mut self) -> bool {
-        if let Timeout::Future(t) = self {
-            if Instant::now() > *t {
-                *self = Timeout::TimedOut;
-            }
+        if let Timeout::Future(t) = self
+            && Instant::now() > *t {
+            *self = Timeout::TimedOut;
         }
         self.timed_out()
     }
@@ -82,6 +81,10 @@ impl<LOG: IndexedLog> Stream for TimeoutWrapper<'_, LOG>  {
     fn is_open(&self) -> bool {
         self.inner.is_open()
     }
+
+    fn truncations(&self) -> usize {
+        self.inner.truncations()
+    }
 }
 
 impl<LOG: IndexedLog> IndexedLog for TimeoutWrapper<'_, LOG> {
@@ -93,6 +96,10 @@ impl<LOG: IndexedLog> IndexedLog for TimeoutWrapper<'_, LOG> {
         self.inner.read_line(offset)
     }
 
+    fn line_end(&mut self, line: &crate::LogLine) -> usize {
+        self.inner.line_end(line)
+    }
+
     fn next_back(&mut self, pos: &super::waypoint::Position) -> super::GetLine {
         self.inner.next_back(pos)
     }
@@ -169,10 +169,9 @@ impl Position {
                 if let Some(offset) = virt.offset() {
                     let mut i = index.search(offset);
                     // offset is exclusive when seeking backwards.  If we found a waypoint at offset, we need to step back one.
-                    if !index.index_valid(i) || offset <= index.value(i).cmp_offset() {
-                        if let Some(ndx) = index.index_prev(i) {
-                            i = ndx;
-                        }
+                    if (!index.index_valid(i) || offset <= index.value(i).cmp_offset())
+                        && let Some(ndx) = index.index_prev(i) {
+                        i = ndx;
                     }
                     if index.index_valid(i) {
                         Position::Existing(i, index.value(i).clone())
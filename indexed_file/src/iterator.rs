@@ -7,7 +7,7 @@ use std::ops::Bound;
 pub struct LogLine {
     pub line: String,
     pub offset: usize,
-    // pub number: Option<usize>,   // TODO: Relative line number in file;  Future<usize>?
+    // Line numbers come from Log::line_number, since we may not know them until the lines before are indexed
 }
 
 impl LogLine {
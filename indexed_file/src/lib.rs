@@ -6,9 +6,16 @@ pub mod log;
 pub mod log_stack;
 
 pub(crate) mod iterator;
-pub(crate) mod time_stamper;
+pub(crate) mod time_filter;
+pub(crate) mod bookmark_filter;
+pub(crate) mod combined_filter;
+pub(crate) mod filter_set;
+pub(crate) mod filter_workers;
+pub(crate) mod worker_pool;
+pub mod time_stamper;
 
 pub use iterator::LogLine;
+pub use time_stamper::TimeStamper;
 pub use log_stack::LogStack;
 pub use crate::log::Log;
 
@@ -19,7 +26,7 @@ pub use iterator::{LineIndexerDataIterator, LineIndexerIterator};
 #[cfg(test)]
 mod tests {
     use crate::{IndexedLog, Log};
-    use crate::files::{new_text_file, LogSource, TextLogFile};
+    use crate::files::{new_text_file, LogSource, Stream, TextLogFile};
     use std::path::PathBuf;
 
     fn open_log_file(filename: &str) -> std::io::Result<LogSource> {
@@ -46,17 +53,15 @@ mod tests {
         println!("{:?}", path);
         let file = new_text_file(Some(&path));
         assert!(file.is_ok());
+        std::fs::remove_file(path).unwrap();
     }
 
     use std::io::{Write, BufReader};
-    use rand::prelude::*;
     use std::fs::File;
     use std::io::{self, BufRead};
 
     fn make_test_file(name: &str, words: usize, lines: usize) -> (PathBuf, usize) {
-        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.push("resources/test");
-        path.push(name);
+        let path = std::env::temp_dir().join(format!("grok-{}-{}", std::process::id(), name));
 
         // write some data to the file
         let mut file = std::fs::File::create(path.clone()).unwrap();
@@ -66,7 +71,7 @@ mod tests {
             let mut s = String::new();
             s.push_str("--40 byte header skipped on every line--");
             for _ in 0..words {
-                let x: u8 = random();
+                let x: u8 = rand::random();
                 let word = match x % 3 {
                     0 => "foo",
                     1 => "bar",
@@ -104,7 +109,7 @@ mod tests {
         // Walk the file and compare each line offset to the expected offset
         let mut offset = 0;
         let mut linecount = 0;
-        let scan = File::open(test_file).unwrap();
+        let scan = File::open(&test_file).unwrap();
         let mut scanlines = io::BufReader::new(scan).lines();
         for start in file.iter_offsets() {
             linecount += 1;
@@ -120,6 +125,7 @@ mod tests {
         // assert no more lines in file
         assert_eq!(scanlines.count(), 0);
         assert_eq!(file.file.info().next().unwrap().lines_indexed, linecount);
+        std::fs::remove_file(test_file).unwrap();
     }
 
     #[test]
@@ -146,7 +152,7 @@ mod tests {
         assert_eq!(lines, io::BufReader::new(scan).lines().count());
         assert_eq!(lines, file.iter_offsets().count());
 
-        let scan = File::open(test_file).unwrap();
+        let scan = File::open(&test_file).unwrap();
         let mut scanlines = io::BufReader::new(scan).lines();
         for start in file.iter_offsets() {
             linecount += 1;
@@ -161,29 +167,197 @@ mod tests {
         // assert no more lines in file
         assert_eq!(scanlines.count(), 0);
         assert_eq!(file.file.info().next().unwrap().lines_indexed, linecount);
+        std::fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn file_index_big_file_in_parallel() {
+        let path = std::env::temp_dir().join(format!("grok-{}-big", std::process::id()));
+        let mut writer = io::BufWriter::new(File::create(&path).unwrap());
+        let mut offsets = vec![];
+        let mut offset = 0;
+        while offset < 12 * 1024 * 1024 {
+            offsets.push(offset);
+            let line = format!("{} {}\n", offsets.len(), "x".repeat(offsets.len() % 300));
+            offset += line.len();
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        drop(writer);
+
+        let mut file = open_log_file_lines(path.clone());
+        assert_eq!(file.iter_offsets().rev().take(10).count(), 10);
+
+        // The workers fill the rest around the lines we read already
+        let pos = file.resolve_gaps(&crate::indexer::waypoint::Position::from(0));
+        assert!(pos.is_invalid());
+        assert!(!file.has_gaps());
+        assert_eq!(file.file.info().next().unwrap().lines_indexed, offsets.len());
+        assert_eq!(file.iter_offsets().collect::<Vec<_>>(), offsets);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn make_zstd_file(name: &str, lines: usize) -> (PathBuf, Vec<u8>) {
+        use crate::files::zstd_log_file::tests::{make_text, zstd_frame};
+        let path = std::env::temp_dir().join(format!("grok-{}-{}.zst", std::process::id(), name));
+        let text = make_text(lines);
+        std::fs::write(&path, zstd_frame(&text)).unwrap();
+        (path, text)
     }
 
     #[test]
     fn file_found_zstd() {
-        let path = PathBuf::from("/home/phord/git/mine/igrok/test.zst");
-        println!("{:?}", path);
+        let (path, _) = make_zstd_file("found", 100);
         let file = new_text_file(Some(&path));
         assert!(file.is_ok());
+        std::fs::remove_file(path).unwrap();
     }
 
     #[test]
     fn file_iter_zstd() {
-        let path = PathBuf::from("/home/phord/git/mine/igrok/test.zst");
-        // let path = PathBuf::from("/home/phord/git/mine/igrok/README.md");
-        println!("{:?}", path);
+        let (path, text) = make_zstd_file("iter", 10000);
         let file = new_text_file(Some(&path));
         assert!(file.is_ok());
         let mut file = Log::from( file.unwrap() );
-        for line in file.iter_lines() {
-            println!("{}  {line}", line.offset);
-        }
+        let lines = file.iter_lines().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines.concat().as_bytes(), text);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_corrupt_compressed() {
+        // A file with the xz magic number is an xz file, even if xz can't read it
+        let path = std::env::temp_dir().join(format!("grok-{}-corrupt.xz", std::process::id()));
+        std::fs::write(&path, b"\xfd7zXZ\x00 not really xz\n").unwrap();
+        assert!(new_text_file(Some(&path)).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_follow_growing_file() {
+        let path = std::env::temp_dir().join(format!("grok-{}-growing", std::process::id()));
+        let mut writer = File::create(&path).unwrap();
+        writeln!(writer, "first line\nsecond line").unwrap();
+
+        let mut file = open_log_file_lines(path.clone());
+        assert_eq!(file.iter_lines().count(), 2);
+
+        // The iterator polls the file and finds the new lines
+        writeln!(writer, "third line").unwrap();
+        assert_eq!(file.iter_lines().count(), 3);
+        assert_eq!(file.iter_lines().last().unwrap().line, "third line\n");
+        std::fs::remove_file(path).unwrap();
     }
 
+    #[test]
+    fn file_follow_search_new_lines() {
+        let path = std::env::temp_dir().join(format!("grok-{}-search-growing", std::process::id()));
+        let mut writer = File::create(&path).unwrap();
+        write!(writer, "deploy complete\nstarting").unwrap();
+
+        let mut file = crate::LogStack::new(open_log_file_lines(path.clone()));
+        file.search_regex("deploy complete").unwrap();
+        let end = file.len();
+        assert_eq!(file.search_next(1, end), None);
+
+        // Only the lines that arrived since we looked match, including the one we saw only part of
+        writeln!(writer, " deploy complete\nwaiting\ndeploy complete").unwrap();
+        file.poll(None);
+        assert_eq!(file.search_next(1, end), Some(16));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_follow_truncated_file() {
+        let path = std::env::temp_dir().join(format!("grok-{}-truncated", std::process::id()));
+        std::fs::write(&path, "match one\nskip two\nmatch three\n").unwrap();
+
+        let mut file = crate::LogStack::new(open_log_file_lines(path.clone()));
+        file.filter_regex("match").unwrap();
+        assert_eq!(crate::LineIndexerDataIterator::new(&mut file).count(), 2);
+
+        // Truncate in place and write new lines, which the filter finds from scratch
+        std::fs::write(&path, "match new\n").unwrap();
+        let lines = crate::LineIndexerDataIterator::new(&mut file).map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["match new\n"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_follow_truncated_and_rewritten_longer() {
+        let path = std::env::temp_dir().join(format!("grok-{}-truncated-longer", std::process::id()));
+        std::fs::write(&path, "old one\nold two\n").unwrap();
+
+        let mut file = Log::from(TextLogFile::from_path(&path).unwrap());
+        assert_eq!(file.iter_lines().count(), 2);
+
+        // Truncated and written past the old size before we looked again
+        std::fs::write(&path, "new one\nnew two\nnew three\n").unwrap();
+        let lines = file.iter_lines().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["new one\n", "new two\n", "new three\n"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_follow_rotated_file() {
+        let path = std::env::temp_dir().join(format!("grok-{}-rotated", std::process::id()));
+        let rotated = path.with_extension("1");
+        std::fs::write(&path, "old one\nold two\n").unwrap();
+
+        let mut file = Log::from(TextLogFile::from_path(&path).unwrap());
+        assert_eq!(file.iter_lines().count(), 2);
+
+        // The new file continues the log after the old one
+        std::fs::rename(&path, &rotated).unwrap();
+        std::fs::write(&path, "new one\n").unwrap();
+        let lines = file.iter_lines().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["old one\n", "old two\n", "new one\n"]);
+
+        // Read backwards across the files, too
+        let lines = file.iter_lines().rev().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["new one\n", "old two\n", "old one\n"]);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(rotated).unwrap();
+    }
+
+    #[test]
+    fn file_follow_rotated_file_late_writes() {
+        let path = std::env::temp_dir().join(format!("grok-{}-rotated-late", std::process::id()));
+        let rotated = path.with_extension("1");
+        std::fs::write(&path, "old one\n").unwrap();
+
+        let mut file = Log::from(TextLogFile::from_path(&path).unwrap());
+        assert_eq!(file.iter_lines().count(), 1);
+
+        // The writer still adds to the old file before it opens the new one
+        std::fs::rename(&path, &rotated).unwrap();
+        File::create(&path).unwrap();
+        assert_eq!(file.iter_lines().count(), 1);
+        std::fs::OpenOptions::new().append(true).open(&rotated).unwrap().write_all(b"old two\n").unwrap();
+        std::fs::write(&path, "new one\n").unwrap();
+        let lines = file.iter_lines().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["old one\n", "old two\n", "new one\n"]);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(rotated).unwrap();
+    }
+
+    #[test]
+    fn file_follow_truncated_after_rotation() {
+        let path = std::env::temp_dir().join(format!("grok-{}-rotated-truncated", std::process::id()));
+        let rotated = path.with_extension("1");
+        std::fs::write(&path, "old one\n").unwrap();
+
+        let mut file = Log::from(TextLogFile::from_path(&path).unwrap());
+        std::fs::rename(&path, &rotated).unwrap();
+        std::fs::write(&path, "new one\n").unwrap();
+        assert_eq!(file.iter_lines().count(), 2);
+
+        // Only the new file is left after it is truncated
+        std::fs::write(&path, "fresh\n").unwrap();
+        let lines = file.iter_lines().map(|line| line.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["fresh\n"]);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(rotated).unwrap();
+    }
 
 /*
     use std::process::{Command, Stdio, Child, ChildStdin};
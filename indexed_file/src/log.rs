@@ -1,21 +1,28 @@
-use crate::indexer::indexed_log::IndexStats;
+use crate::indexer::index_cache::IndexCache;
+use crate::indexer::indexed_log::{IndexStats, LineNumber};
 use crate::indexer::sane_indexer::SaneIndexer;
 use crate::indexer::waypoint::Position;
 use crate::time_stamper::TimeStamper;
 use crate::LogLine;
+use chrono::{NaiveDateTime, TimeDelta};
 use std::path::PathBuf;
+use std::sync::Arc;
+#[cfg(test)]
+use std::path::Path;
 use crate::indexer::{GetLine, IndexedLog};
 
-use crate::files::{new_text_file, LogBase, LogSource, Stream};
+use crate::files::{new_text_file, LogBase, LogSource, ReadAt, Stream};
 
 /**
  * Log is an adapter interface used to instantiate a SaneIndexer from different kinds of LogSources.
  */
 pub struct Log {
     pub(crate) file: SaneIndexer<LogSource>,
-    #[allow(dead_code)]
     pub(crate) format: TimeStamper,
     cached_len: usize,
+
+    /// Where we save our line index when we are done, if we have a file to save it for
+    cache: Option<IndexCache>,
 }
 
 impl<LOG: LogBase + 'static> From<LOG> for Log {
@@ -35,6 +42,7 @@ impl From<LogSource> for Log {
             file: src,
             format: TimeStamper::default(),
             cached_len,
+            cache: None,
         }
     }
 }
@@ -47,6 +55,7 @@ impl Log {
             file: src,
             format: TimeStamper::default(),
             cached_len,
+            cache: None,
         }
     }
 
@@ -59,6 +68,7 @@ impl Log {
             file: src,
             format: TimeStamper::default(),
             cached_len,
+            cache: None,
         }
     }
 
@@ -70,11 +80,166 @@ impl Log {
             file: SaneIndexer::new(src),
             format: TimeStamper::default(),
             cached_len,
+            cache: None,
         };
         Ok(log)
     }
+
+    /// Open a file and reuse the line index we saved the last time we opened it, if the file has not changed since
+    /// or has only grown.  The index is saved again when the Log is dropped.
+    pub fn open_cached(file: Option<&PathBuf>) -> std::io::Result<Self> {
+        let cache = file.and_then(|path| IndexCache::open(path));
+        Ok(Self::with_cache(Self::open(file)?, cache))
+    }
+
+    /// Open a file using an index cache in the given directory
+    #[cfg(test)]
+    pub(crate) fn open_cached_in(file: Option<&PathBuf>, cache_dir: &Path) -> std::io::Result<Self> {
+        let cache = file.and_then(|path| IndexCache::open_in(path, cache_dir));
+        Ok(Self::with_cache(Self::open(file)?, cache))
+    }
+
+    // Use the cache for the log's file, unless we read the log from somewhere other than the file itself.  The cache
+    // only describes the file, and a LESSOPEN preprocessor may show us something different each time.
+    pub(crate) fn with_cache(mut log: Self, cache: Option<IndexCache>) -> Self {
+        if let Some(mut cache) = cache.filter(|_| log.file.reads_file()) {
+            if let Some(index) = cache.load(log.len()) {
+                log.file.set_index(index);
+            }
+            log.cache = Some(cache);
+        }
+        log
+    }
+}
+
+impl Drop for Log {
+    fn drop(&mut self) {
+        // The index of a truncated file doesn't describe the file we opened
+        if let Some(cache) = &self.cache
+            && self.file.truncations() == 0
+            && let Err(e) = cache.save(self.file.index()) {
+            log::warn!("Unable to save index cache: {}", e);
+        }
+    }
 }
 
+// Line numbers
+impl Log {
+    /// Number of the line holding offset, or an estimate if we haven't indexed every line before it yet
+    pub fn line_number(&self, offset: usize) -> LineNumber {
+        self.file.index().line_number(offset)
+    }
+
+    /// Number of lines in the log, or an estimate if we haven't indexed all of it yet
+    pub fn line_count(&self) -> LineNumber {
+        match self.len() {
+            0 => LineNumber::Exact(0),
+            len => self.line_number(len - 1),
+        }
+    }
+
+    /// Find the line with the given number, counting from 1.  We read the lines after the last one we can count
+    /// until we reach it, or until the timeout set on the log runs out.  Returns Miss if the log has fewer lines, or
+    /// Timeout if we have to come back later to read more of them.
+    pub fn find_line(&mut self, number: usize) -> GetLine {
+        loop {
+            let end = match self.file.index().line_offset(number) {
+                Ok(offset) => return self.file.next(&Position::from(offset)),
+                Err((_, end)) => end,
+            };
+            // Reading the next line maps it in the index, so we can count it
+            if let get @ (GetLine::Miss(_) | GetLine::Timeout(_)) = self.file.next(&Position::from(end)) {
+                return get;
+            }
+        }
+    }
+}
+
+// Maximum number of lines to read past an offset looking for a timestamp
+const TIME_SCAN_LINES: usize = 1000;
+
+// Timestamps
+impl Log {
+    /// A reader of the log for worker threads, if it has one
+    pub(crate) fn shared_reader(&self) -> Option<Arc<dyn ReadAt>> {
+        self.file.shared_reader()
+    }
+
+    /// The bytes of the line at offset as they are in the file, before we replace any invalid utf-8
+    pub fn read_bytes(&mut self, offset: usize) -> Option<Vec<u8>> {
+        self.file.read_bytes(offset)
+    }
+
+    /// Find the first line that starts at or after offset and has a timestamp, reading at most TIME_SCAN_LINES lines.
+    /// Returns the offset of the line and its timestamp, or Err with the offset where we stopped looking, or None if
+    /// we reached the end of the log.
+    fn time_at(&mut self, offset: usize) -> Result<(usize, NaiveDateTime), Option<usize>> {
+        let mut start = if offset == 0 {
+            0
+        } else {
+            // Skip the rest of the line holding offset-1 to land on the start of a line
+            let (line, len) = self.file.read_line_len(offset - 1).ok_or(None)?;
+            line.offset + len
+        };
+
+        // Step by the length of each line in the file; invalid utf-8 makes the line we read a different length
+        for _ in 0..TIME_SCAN_LINES {
+            let (line, len) = self.file.read_line_len(start).ok_or(None)?;
+            if let Some(time) = self.format.time(&line.line) {
+                return Ok((start, time));
+            }
+            start += len;
+        }
+        Err(Some(start))
+    }
+
+    /// Find the first line that starts at or after offset and before end and has a timestamp, however many lines
+    /// without one we have to read past
+    fn time_before(&mut self, offset: usize, end: usize) -> Option<(usize, NaiveDateTime)> {
+        let mut offset = offset;
+        loop {
+            match self.time_at(offset) {
+                Ok((start, time)) => return (start < end).then_some((start, time)),
+                Err(Some(next)) if next < end => offset = next,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Timestamp of the first timestamped line in the log
+    pub fn first_time(&mut self) -> Option<NaiveDateTime> {
+        self.time_at(0).ok().map(|(_, time)| time)
+    }
+
+    /// UTC offset of the first timestamped line in the log, if it has one
+    pub fn first_offset(&mut self) -> Option<TimeDelta> {
+        self.time_at(0).ok()?;
+        self.format.offset()
+    }
+
+    /// Find the offset of the first line with a timestamp at or after `time`.
+    /// We bisect the byte offsets of the file reading only a few lines at each step, so the file does not need to be
+    /// indexed first.  This assumes the log is mostly sorted by time.
+    /// Returns None if there is no such line, or if the log does not begin with timestamped lines.
+    pub fn find_time(&mut self, time: NaiveDateTime) -> Option<usize> {
+        // Don't read a whole log with no timestamps looking for one
+        self.first_time()?;
+
+        let mut lo = 0;
+        let mut hi = self.len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            // Lines without timestamps between mid and hi don't move the answer, however many there are
+            match self.time_before(mid, hi) {
+                // The answer is after this line
+                Some((start, stamp)) if stamp < time => lo = start + 1,
+                // The answer starts before mid, or it is the line we found, or the first one with a timestamp after hi
+                _ => hi = mid,
+            }
+        }
+        self.time_before(lo, usize::MAX).map(|(start, _)| start)
+    }
+}
 
 // TODO: Delete this except for tests once SaneIndexer something something something...
 impl Stream for Log {
@@ -91,6 +256,10 @@ impl Stream for Log {
     fn is_open(&self) -> bool {
         self.file.is_open()
     }
+
+    fn truncations(&self) -> usize {
+        self.file.truncations()
+    }
 }
 
 // Navigation
@@ -126,6 +295,11 @@ impl IndexedLog for Log {
         self.file.read_line(offset)
     }
 
+    #[inline]
+    fn line_end(&mut self, line: &LogLine) -> usize {
+        self.file.line_end(line)
+    }
+
     #[inline]
     fn set_timeout(&mut self, limit: Option<std::time::Duration>) {
         self.file.set_timeout(limit);
@@ -1,5 +1,7 @@
 
-use crate::{index_filter::{IndexFilter, SearchType}, indexer::{indexed_log::IndexStats, waypoint::Position, GetLine, IndexedLog}};
+use std::ops::Range;
+
+use crate::{index_filter::{IndexFilter, LineMatcher, SearchType}, indexer::{indexed_log::IndexStats, waypoint::Position, GetLine, IndexedLog}};
 
 /// Applies an IndexFilter to an IndexedLog to make a filtered IndexLog that can iterate lines after applying the filter.
 #[derive(Default)]
@@ -27,6 +29,10 @@ impl LogFilter {
         let mut next = next.clone();
 
         loop {
+            if !next.is_unmapped() {
+                // We erased the whole gap, e.g. at the start of the file
+                return GetLine::Miss(next);
+            }
             if log.check_timeout() {
                 return GetLine::Timeout(next)
             }
@@ -36,7 +42,7 @@ impl LogFilter {
             match get {
                 GetLine::Hit(pos, line) => {
                     self.inner_pos = log.advance_back(&pos);
-                    let range = line.offset..line.offset + line.line.len();
+                    let range = line.offset..log.line_end(&line);
                     assert!(range.start < gap.end);
                     if range.end <= gap.start {
                         return GetLine::Miss(next);
@@ -90,7 +96,7 @@ impl LogFilter {
             match get {
                 GetLine::Hit(pos, line) => {
                     self.inner_pos = log.advance(&pos);
-                    let range = line.offset..line.offset + line.line.len();
+                    let range = line.offset..log.line_end(&line);
                     if range.end <= gap.start {
                         // Inner starts by scanning the line that ends at the start of our gap. This is that line. Ignore it.
                         continue;
@@ -138,11 +144,11 @@ impl LogFilter {
 
     // Update an inner Position to navigate the log file while resolving unmapped filtered regions
     fn seek_inner(&mut self, pos: usize) {
-        // Ignore it if the caller tries to set us but we're already tracking them
+        // Ignore it if the caller tries to set us but we're already tracking them.  An inner position that merely
+        // contains pos is not good enough; reading from it would start before pos.
         if self.inner_pos.is_virtual()
                 || self.inner_pos.moved(&self.filter.index)
-                || !(self.inner_pos.region().contains(&pos)
-                        || self.inner_pos.most_offset() == pos) {
+                || self.inner_pos.least_offset() != pos {
             self.inner_pos = Position::from(pos);
         }
     }
@@ -262,4 +268,20 @@ impl LogFilter {
     pub fn has_gaps(&self) -> bool {
         self.filter.index.stats.bytes_indexed < self.filter.index.stats.bytes_total
     }
+
+    // Background filling support: workers test the lines with our matcher and we merge what they found
+    pub(crate) fn matcher(&self) -> &LineMatcher {
+        self.filter.matcher()
+    }
+
+    /// The first gap in our index that ends after the offset, beginning no earlier than the offset
+    pub(crate) fn gap_after(&self, offset: usize) -> Option<Range<usize>> {
+        let gap = self.filter.index.gap_after(offset);
+        gap.is_unmapped().then(|| gap.region().start.max(offset)..gap.region().end)
+    }
+
+    /// Map the lines a worker found matching in the span it tested
+    pub(crate) fn merge(&mut self, span: &Range<usize>, matches: &[Range<usize>]) {
+        self.filter.index.fill(span, matches);
+    }
 }
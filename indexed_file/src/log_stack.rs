@@ -1,6 +1,13 @@
+use chrono::{NaiveDateTime, TimeDelta};
 use regex::Regex;
 
-use crate::{files::Stream, index_filter::SearchType, indexer::{indexed_log::IndexStats, waypoint::Position, GetLine}, log_filter::LogFilter, IndexedLog, Log};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::bookmark_filter::BookmarkFilter;
+use crate::combined_filter::{CombinedFilter, Matches, Target};
+use crate::filter_workers::FilterWorkers;
+use crate::{files::Stream, filter_set::FilterSet, index_filter::SearchType, indexer::{indexed_log::{IndexStats, LineNumber}, waypoint::Position, GetLine}, log_filter::LogFilter, time_filter::TimeFilter, IndexedLog, Log, LogLine};
 
 // Pending operations are used to handle blocking tasks asyncronously.  Mostly these are background tasks, and the order
 // doesn't much matter.  But if we are searching for a line, we need to find it and return it asap.  So we try to complete
@@ -13,6 +20,8 @@ enum PendingOp {
     //          count, offset
     SeekForward(usize, Position),
     SeekBackward(usize, Position),
+    //       number
+    FindLine(usize),
     FillGaps(Position),
     Streaming,
     None,
@@ -20,14 +29,6 @@ enum PendingOp {
 
 
 impl PendingOp {
-    fn seek_fwd_rev(&mut self, log: &mut LogFilter, src: &mut dyn IndexedLog, pos: Position) -> Position {
-        match self {
-            PendingOp::SeekForward(..) => log.find_next(src, &pos).into_pos(),
-            PendingOp::SeekBackward(..) => log.find_next_back(src, &pos).into_pos(),
-            _ => panic!("Invalid pending op: {:?} for {:?}", self, pos),
-        }
-    }
-
     fn update(&mut self, count: usize, pos: Position) -> Self {
         match self {
             PendingOp::SeekForward(..) => PendingOp::SeekForward(count, pos),
@@ -38,8 +39,58 @@ impl PendingOp {
 }
 
 
+// Size of the chunks of the log we give to the filter workers.  We only start them for more than a couple of these.
+const FILTER_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// Bytes of lines we test in one pass before we update the indexes
+const PASS_CHUNK_SIZE: usize = 1024 * 1024;
+
 // TODO: Move this into Felon?  It implements some very felon-specific features.
 
+/// A search expression and its memoized index of matching lines
+struct NamedSearch {
+    pattern: String,
+    filter: LogFilter,
+}
+
+/// Find the nearest line matching any of the searches, forward or backward from pos.
+/// A Hit gives the Position to continue searching from after this line.
+fn nearest_match<LOG: IndexedLog + ?Sized>(searches: &mut [&mut LogFilter], src: &mut LOG, pos: &Position, forward: bool) -> GetLine {
+    let offset = if forward { pos.least_offset() } else { pos.most_offset() };
+    let mut nearest: Option<LogLine> = None;
+    for search in searches.iter_mut() {
+        let get = if forward {
+            search.find_next(src, pos)
+        } else {
+            search.find_next_back(src, pos)
+        };
+        match get {
+            // Searching backwards, a search whose first match is at offset may give us that line; it is not before offset
+            GetLine::Hit(_, line) if !forward && line.offset >= offset => {},
+            GetLine::Hit(_, line) => {
+                let nearer = match &nearest {
+                    None => true,
+                    Some(near) if forward => line.offset < near.offset,
+                    Some(near) => line.offset > near.offset,
+                };
+                if nearer {
+                    nearest = Some(line);
+                }
+            },
+            GetLine::Miss(_) => {},
+            GetLine::Timeout(_) => return GetLine::Timeout(pos.clone()),
+        }
+    }
+
+    match nearest {
+        Some(line) => {
+            let next = if forward { src.line_end(&line) } else { line.offset };
+            GetLine::Hit(Position::from(next), line)
+        },
+        None => GetLine::Miss(Position::invalid()),
+    }
+}
+
 /// A stack of logs with filters.
 /// Rust complicates our traits enough that it's impractical to rely on recursive log trees.
 /// As it turns out, that's also impractical from a usability and reasoning standpoint, too.
@@ -47,29 +98,87 @@ impl PendingOp {
 /// filters, exclude filters, bookmarks, highlights and and searches.
 pub struct LogStack {
     source: FilteredSource,
-    search: Option<LogFilter>,  // FIXME: Should hold IndexFilter here and create a LogFilter as-needed
+    searches: Vec<NamedSearch>,  // FIXME: Should hold IndexFilter here and create a LogFilter as-needed
+    search_target: Option<String>,  // The search that search_next follows, or None for all of them
     pending: PendingOp,
+    truncations: usize,  // Truncations of the source we already know about
+    workers: Option<FilterWorkers>,  // Threads filling the gaps in the filters and searches, once we have some
+    combined: Option<(Vec<String>, Option<Arc<CombinedFilter>>)>,  // Filters and searches tested together, and what they were made from
 }
 
 impl  LogStack {
     pub fn new(log: Log) -> Self {
         Self {
             source: FilteredSource::new(log),
-            search: None,
+            searches: Vec::new(),
+            search_target: None,
             pending: PendingOp::FillGaps(Position::invalid()),
+            truncations: 0,
+            workers: None,
+            combined: None,
         }
     }
 
-    /// Apply a new regex search expression to the filter. If string is empty, clears current filter.
-    /// TODO: add more filters instead of replacing the one we currently allow
+    /// Add a regex filter.  Lines matching any include filter are shown, except for lines matching an exclude filter,
+    /// which start with '!'.  If string is empty, clears all filters.
     pub fn filter_regex(&mut self, re: &str) -> Result<(), regex::Error> {
         self.source.filter_regex(re)?;
-        // FIXME: Only restart search if filter changed
-        if let Some(search) = &mut self.search {
-            search.reset();
+        self.filter_changed();
+        Ok(())
+    }
+
+    /// The filter expressions and whether each is enabled
+    pub fn filters(&self) -> impl Iterator<Item = (&str, bool)> + '_ {
+        self.source.filters.iter()
+    }
+
+    /// Enable or disable one of the filters.  A disabled filter remembers what it has indexed.
+    pub fn enable_filter(&mut self, index: usize, enabled: bool) {
+        self.source.filters.enable(index, enabled);
+        self.filter_changed();
+    }
+
+    /// Change the expression of one of the filters.  A leading '!' makes it an exclude filter.
+    pub fn edit_filter(&mut self, index: usize, re: &str) -> Result<(), regex::Error> {
+        let len = self.source.len();
+        self.source.filters.replace(index, re, len)?;
+        self.filter_changed();
+        Ok(())
+    }
+
+    /// Move one of the filters to another place in the list
+    pub fn move_filter(&mut self, from: usize, to: usize) {
+        self.source.filters.move_to(from, to);
+    }
+
+    /// Index statistics for one of the filters
+    pub fn filter_stats(&self, index: usize) -> Option<&IndexStats> {
+        self.source.filters.stats(index)
+    }
+
+    pub fn remove_filter(&mut self, index: usize) {
+        self.source.filters.remove(index);
+        self.filter_changed();
+    }
+
+    /// Hide lines outside the time window [from, to).  If both are None, clears the time window.
+    pub fn filter_time(&mut self, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>) {
+        self.source.filter_time(from, to);
+        self.filter_changed();
+    }
+
+    /// Show only the lines at the given offsets, as well as passing the other filters.  If None, shows every line again.
+    pub fn filter_bookmarks(&mut self, lines: Option<BTreeSet<usize>>) {
+        self.source.bookmarks = lines.map(BookmarkFilter::new);
+        self.filter_changed();
+    }
+
+    // The set of lines we show changed, so the search has to start over
+    fn filter_changed(&mut self) {
+        for search in self.searches.iter_mut() {
+            search.filter.reset();
         }
         self.kick_pending();
-        Ok(())
     }
 
     pub fn has_pending(&self) -> bool {
@@ -77,41 +186,72 @@ impl  LogStack {
     }
 
     fn do_search(&mut self, timeout: u64, count: usize, pos: Position) -> Option<usize> {
-        if let Some(search) = &mut self.search {
-            let src = &mut self.source.with_timeout(timeout);
-            let mut count = count;
-            let mut pos = pos;
-            loop {
-                pos = self.pending.seek_fwd_rev(search, src, pos);
-                if src.timed_out() || pos.is_invalid() {
-                    break;
-                }
-                count = count.saturating_sub(1);
-                if count == 0 {
-                    break;
-                }
+        let forward = matches!(self.pending, PendingOp::SeekForward(..));
+        let target = &self.search_target;
+        let mut searches = self.searches.iter_mut()
+            .filter(|search| target.as_ref().is_none_or(|target| *target == search.pattern))
+            .map(|search| &mut search.filter)
+            .collect::<Vec<_>>();
+        if searches.is_empty() {
+            // No search term; nothing to do here
+            self.pending = PendingOp::None;
+            return None;
+        }
+
+        let src = &mut self.source.with_timeout(timeout);
+        let mut count = count;
+        let mut pos = pos;
+        loop {
+            match nearest_match(&mut searches, src, &pos, forward) {
+                GetLine::Hit(next, line) => {
+                    count = count.saturating_sub(1);
+                    if count == 0 {
+                        // Found it
+                        log::trace!("Search found");
+                        self.pending = PendingOp::None;
+                        return Some(line.offset);
+                    }
+                    pos = next;
+                },
+                GetLine::Miss(_) => {
+                    // Not found, but nothing more to find, either.
+                    log::trace!("Search invalid");
+                    self.pending = PendingOp::None;
+                    // FIXME: Return some indication to that caller that search failed
+                    return None;
+                },
+                GetLine::Timeout(pos) => {
+                    // Didn't find it yet
+                    log::trace!("Search timed out");
+                    self.pending = self.pending.update(count, pos);
+                    return None;
+                },
             }
-            if pos.is_invalid() {
-                // Not found, but nothing more to find, either.
+        }
+    }
+
+    fn do_find_line(&mut self, timeout: u64, number: usize) -> Option<usize> {
+        let log = &mut self.source.source;
+        log.set_timeout(Some(std::time::Duration::from_millis(timeout)));
+        let get = log.find_line(number);
+        log.set_timeout(None);
+        match get {
+            GetLine::Hit(_, line) => {
+                log::trace!("Line found");
                 self.pending = PendingOp::None;
-                log::trace!("Search invalid");
-                // FIXME: Return some indication to that caller that search failed
-                None
-            } else if src.timed_out() {
+                Some(line.offset)
+            },
+            GetLine::Miss(_) => {
+                // The log has fewer lines, so go to the end of it
+                log::trace!("Line past the end");
+                self.pending = PendingOp::None;
+                Some(self.len())
+            },
+            GetLine::Timeout(_) => {
                 // Didn't find it yet
-                self.pending = self.pending.update(count, pos);
-                log::trace!("Search timed out");
+                log::trace!("Find line timed out");
                 None
-            } else {
-                // Found it
-                log::trace!("Search found");
-                self.pending = PendingOp::None;
-                pos.offset()
-            }
-        } else {
-            // No search term; nothing to do here
-            self.pending = PendingOp::None;
-            None
+            },
         }
     }
 
@@ -132,6 +272,7 @@ impl  LogStack {
         match self.pending {
             PendingOp::SeekForward(..) => "Search Forward".to_string(),
             PendingOp::SeekBackward(..) => "Search Backward".to_string(),
+            PendingOp::FindLine(..) => "Find Line".to_string(),
             PendingOp::FillGaps(..) => "Fill Gaps".to_string(),
             PendingOp::Streaming => "Streaming".to_string(),
             PendingOp::None => "None".to_string(),
@@ -145,14 +286,14 @@ impl  LogStack {
             PendingOp::SeekBackward(count, pos) =>
                 result = self.do_search(timeout, count, pos),
 
+            PendingOp::FindLine(number) =>
+                result = self.do_find_line(timeout, number),
+
             PendingOp::FillGaps(pos) =>
                 self.do_fill_gaps(timeout, pos),
 
             PendingOp::Streaming => {
-                let len = self.source.poll(Some(std::time::Instant::now() + std::time::Duration::from_millis(timeout)));
-                if let Some(search) = &mut self.search {
-                    search.update_len(len);
-                }
+                self.poll(Some(std::time::Instant::now() + std::time::Duration::from_millis(timeout)));
                 if !self.source.is_open() {
                     self.pending = PendingOp::None;
                 }
@@ -174,18 +315,59 @@ impl  LogStack {
         }
     }
 
-    /// Set a new regex search expression
-    /// TODO: allow multiple active searches
+    /// Set a new regex search expression, replacing any others we have.  If string is empty, clears all searches.
     pub fn search_regex(&mut self, re: &str) -> Result<(), regex::Error> {
-        if re.is_empty() {
-            self.search = None;
-        } else {
-            self.search = Some(LogFilter::new(SearchType::Regex(Regex::new(re)?), self.source.len()));
-            self.kick_pending();
+        let matcher = if re.is_empty() { None } else { Some(Regex::new(re)?) };
+        self.searches.clear();
+        self.search_target = None;
+        if let Some(matcher) = matcher {
+            self.push_search(re, matcher);
+        }
+        Ok(())
+    }
+
+    /// Add another regex search.  Does nothing if we already have this one.
+    pub fn add_search(&mut self, re: &str) -> Result<(), regex::Error> {
+        if !self.searches.iter().any(|search| search.pattern == re) {
+            let matcher = Regex::new(re)?;
+            self.push_search(re, matcher);
         }
         Ok(())
     }
 
+    fn push_search(&mut self, re: &str, matcher: Regex) {
+        self.searches.push(NamedSearch {
+            pattern: re.to_string(),
+            filter: LogFilter::new(SearchType::Regex(matcher), self.source.len()),
+        });
+        self.kick_pending();
+    }
+
+    pub fn remove_search(&mut self, re: &str) {
+        self.searches.retain(|search| search.pattern != re);
+        if self.search_target.as_deref() == Some(re) {
+            self.search_target = None;
+        }
+    }
+
+    /// The search expressions we have
+    pub fn searches(&self) -> impl Iterator<Item = &str> + '_ {
+        self.searches.iter().map(|search| search.pattern.as_str())
+    }
+
+    /// Index statistics for one of the searches
+    pub fn search_stats(&self, re: &str) -> Option<&IndexStats> {
+        self.searches.iter()
+            .find(|search| search.pattern == re)
+            .and_then(|search| search.filter.info().next())
+    }
+
+    /// Make search_next and search_next_back find matches for only one of our searches.  None finds matches for any of
+    /// them.
+    pub fn set_search_target(&mut self, re: Option<&str>) {
+        self.search_target = re.map(|re| re.to_string());
+    }
+
     pub fn search_next(&mut self, count: usize, offset: usize) -> Option<usize> {
         self.pending = PendingOp::SeekForward(count, Position::from(offset));
         // return a result if we have one within 10ms.  Otherwise, let caller run_pending on their own.
@@ -198,6 +380,183 @@ impl  LogStack {
         self.run_pending(10)
     }
 
+    /// Our filters and searches combined to test each line once, made again when they change.  Searches only match
+    /// lines in the time window and the bookmarks, which we don't check here, so we leave them to fill in the usual
+    /// way while we have either one.
+    fn combined_filter(&mut self) -> Option<Arc<CombinedFilter>> {
+        let with_searches = self.source.time.is_none() && self.source.bookmarks.is_none();
+        let mut key = self.source.filters.iter()
+            .map(|(pattern, enabled)| format!("{} {}", enabled, pattern))
+            .collect::<Vec<_>>();
+        if with_searches {
+            key.extend(self.searches.iter().map(|search| format!("/{}", search.pattern)));
+        }
+        if let Some((made_from, filter)) = &self.combined && *made_from == key {
+            return filter.clone();
+        }
+
+        let filters = self.source.filters.enabled_filters()
+            .map(|(index, filter, include)| (Target::Filter(index), filter.matcher(), include))
+            .collect::<Vec<_>>();
+        let searches = self.searches.iter().enumerate()
+            .filter(|_| with_searches)
+            .map(|(index, search)| (Target::Search(index), search.filter.matcher()))
+            .collect::<Vec<_>>();
+        let filter = match CombinedFilter::new(&filters, &searches) {
+            Ok(filter) if !filter.is_empty() => Some(Arc::new(filter)),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Unable to combine the filters and searches: {}", e);
+                None
+            },
+        };
+        self.combined = Some((key, filter.clone()));
+        filter
+    }
+
+    fn target_filter(&mut self, target: Target) -> Option<&mut LogFilter> {
+        match target {
+            Target::Filter(index) => self.source.filters.filter_mut(index),
+            Target::Search(index) => self.searches.get_mut(index).map(|search| &mut search.filter),
+        }
+    }
+
+    // Where the first gap after offset begins in any of the indexes
+    fn next_gap(&mut self, targets: &[Target], offset: usize) -> Option<usize> {
+        targets.iter()
+            .filter_map(|target| self.target_filter(*target)?.gap_after(offset))
+            .map(|gap| gap.start)
+            .min()
+    }
+
+    fn merge(&mut self, targets: &[Target], matches: &Matches) {
+        for (target, lines) in targets.iter().zip(&matches.lines) {
+            if let Some(filter) = self.target_filter(*target) {
+                filter.merge(&matches.span, lines);
+            }
+        }
+    }
+
+    /// Fill the gaps in the filters and searches together, reading each line of the log once and testing it against
+    /// all of them.  Returns the position to continue from if we timed out, or None when we reach the end of the log.
+    fn fill_in_one_pass(&mut self, pos: &Position) -> Option<Position> {
+        let filter = self.combined_filter()?;
+        let targets = filter.targets();
+        let mut offset = 0;
+        loop {
+            let start = self.next_gap(targets, offset)?;
+            if start >= self.len() {
+                return None;
+            }
+
+            let mut matches = Matches::new(targets.len(), start);
+            let mut next = Position::from(start);
+            let stopped = loop {
+                match self.source.source.next(&next) {
+                    GetLine::Hit(at, line) => {
+                        let range = line.offset..self.source.source.line_end(&line);
+                        filter.test(line.line.strip_suffix('\n').unwrap_or(&line.line), &range, &mut matches);
+                        next = self.source.source.advance(&at);
+                        if matches.span.len() >= PASS_CHUNK_SIZE {
+                            break None;
+                        }
+                    },
+                    GetLine::Miss(_) => break Some(None),
+                    GetLine::Timeout(_) => break Some(Some(pos.clone())),
+                }
+            };
+            self.merge(targets, &matches);
+            if let Some(stopped) = stopped {
+                return stopped;
+            }
+            offset = matches.span.end;
+        }
+    }
+
+    /// Fill the gaps in the filters and searches on worker threads, merging the lines they match as they finish.
+    /// Returns the position to continue from if we timed out, or None when the workers have nothing left to do, or if
+    /// they can't read this log.  Whatever they missed is left for the usual way.
+    fn fill_in_background(&mut self, pos: &Position) -> Option<Position> {
+        let reader = self.source.source.shared_reader()?;
+        let filter = self.combined_filter()?;
+        let mut workers = match self.workers.take() {
+            Some(workers) if workers.is_testing(&filter) => workers,
+            _ => {
+                // Only worth it for big gaps
+                let len = self.len();
+                let most = filter.targets().iter()
+                    .filter_map(|target| self.target_filter(*target)?.info().next().map(|stats| stats.bytes_indexed))
+                    .map(|indexed| len.saturating_sub(indexed))
+                    .max().unwrap_or_default();
+                if most <= 2 * FILTER_CHUNK_SIZE {
+                    return None;
+                }
+                FilterWorkers::new(filter.clone())
+            },
+        };
+        let targets = filter.targets();
+
+        let result = loop {
+            // Keep the workers busy with the next chunks that any of our indexes has a gap in
+            while !workers.pool.is_busy() {
+                let Some(start) = self.next_gap(targets, workers.pool.next) else { break };
+                let end = self.len().min(start + FILTER_CHUNK_SIZE);
+                if start >= end {
+                    break;
+                }
+                workers.submit(reader.clone(), start..end);
+            }
+
+            if workers.pool.is_idle() {
+                break None;
+            }
+            match workers.pool.receive(std::time::Duration::from_millis(5)) {
+                Some(Ok(matches)) => self.merge(targets, &matches),
+                Some(Err(e)) => log::warn!("Error filtering the file: {}", e),
+                None => {},
+            }
+            if self.check_timeout() {
+                break Some(pos.clone());
+            }
+        };
+        self.workers = Some(workers);
+        result
+    }
+
+    /// Number of the line holding offset in the source log, before any filters
+    pub fn line_number(&self, offset: usize) -> LineNumber {
+        self.source.source.line_number(offset)
+    }
+
+    /// Number of lines in the source log
+    pub fn line_count(&self) -> LineNumber {
+        self.source.source.line_count()
+    }
+
+    /// Find the offset of the line with the given number in the source log, or the end of the log if it has fewer
+    /// lines.  Like a search, we count the lines in the background if it takes a while, and return the offset from
+    /// run_pending when we find it.
+    pub fn find_line(&mut self, number: usize) -> Option<usize> {
+        self.pending = PendingOp::FindLine(number);
+        // return a result if we have one within 10ms.  Otherwise, let caller run_pending on their own.
+        self.run_pending(10)
+    }
+
+    /// Timestamp of the first timestamped line in the source log
+    pub fn first_time(&mut self) -> Option<NaiveDateTime> {
+        self.source.source.first_time()
+    }
+
+    /// UTC offset of the first timestamped line in the source log, if it has one
+    pub fn first_offset(&mut self) -> Option<TimeDelta> {
+        self.source.source.first_offset()
+    }
+
+    /// Find the offset of the first line at or after the given time in the source log
+    pub fn find_time(&mut self, time: NaiveDateTime) -> Option<usize> {
+        self.source.source.find_time(time)
+    }
+
 }
 
 impl Stream for LogStack {
@@ -207,11 +566,29 @@ impl Stream for LogStack {
 
     // Poll for new data
     fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize {
-        self.source.poll(timeout)
+        let len = self.source.poll(timeout);
+        let truncated = self.source.truncations() != self.truncations;
+        self.truncations = self.source.truncations();
+        for search in self.searches.iter_mut() {
+            if truncated {
+                search.filter.reset();
+            }
+            search.filter.update_len(len);
+        }
+        if truncated {
+            // Positions in a pending search are gone too; start over indexing the new data
+            self.workers = None;
+            self.pending = PendingOp::None;
+            self.kick_pending();
+        }
+        len
     }
     fn is_open(&self) -> bool {
         self.source.is_open()
      }
+    fn truncations(&self) -> usize {
+        self.source.truncations()
+    }
 }
 
 impl IndexedLog for LogStack {
@@ -219,6 +596,10 @@ impl IndexedLog for LogStack {
         self.source.read_line(offset)
     }
 
+    fn line_end(&mut self, line: &crate::LogLine) -> usize {
+        self.source.line_end(line)
+    }
+
     fn next(&mut self, pos: &Position) -> GetLine {
         self.source.next(pos)
     }
@@ -241,10 +622,11 @@ impl IndexedLog for LogStack {
         } else {
             pos.clone()
         };
-        if let Some(search) = &mut self.search {
-            if search.has_gaps() {
-                return search.resolve_gaps(&mut self.source, &pos)
-            }
+        if let Some(pos) = self.fill_in_background(&pos).or_else(|| self.fill_in_one_pass(&pos)) {
+            return pos;
+        }
+        if let Some(search) = self.searches.iter_mut().find(|search| search.filter.has_gaps()) {
+            return search.filter.resolve_gaps(&mut self.source, &pos)
         }
 
         self.source.resolve_gaps(&pos)
@@ -265,12 +647,12 @@ impl IndexedLog for LogStack {
     fn info(&self) -> impl Iterator<Item = &'_ IndexStats> + '_
     where Self: Sized  {
         self.source.info()
-        .chain(self.search.iter().flat_map(|f| f.info()))
+        .chain(self.searches.iter().flat_map(|search| search.filter.info()))
     }
 
     fn has_gaps(&self) -> bool {
         self.source.has_gaps()
-            || self.search.as_ref().map(|f| f.has_gaps()).unwrap_or(false)
+            || self.searches.iter().any(|search| search.filter.has_gaps())
     }
 }
 
@@ -278,23 +660,106 @@ impl IndexedLog for LogStack {
 /// This is primarily used to give us a detachable source so LogStack doesn't bump into Rust's ownership rules.
 struct FilteredSource {
     source: Log,
-    filter: Option<LogFilter>,
+    filters: FilterSet,
+    time: Option<TimeFilter>,
+    bookmarks: Option<BookmarkFilter>,
+
+    // Truncations of the source we already know about
+    truncations: usize,
 }
 
 impl FilteredSource {
     pub fn new(source: Log) -> Self {
-        Self { source, filter: None }
+        Self { source, filters: FilterSet::default(), time: None, bookmarks: None, truncations: 0 }
+    }
+
+    /// Apply a new time window to the source
+    pub fn filter_time(&mut self, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>) {
+        if from.is_none() && to.is_none() {
+            self.time = None;
+        } else {
+            self.time = Some(TimeFilter::new(from, to));
+        }
+    }
+
+    /// Byte range of the source lines we can show
+    fn window(&mut self) -> std::ops::Range<usize> {
+        match &mut self.time {
+            Some(time) => time.window(&mut self.source),
+            None => 0..usize::MAX,
+        }
+    }
+
+    /// Find the next line the filters show
+    fn next_shown(&mut self, pos: &Position) -> GetLine {
+        if self.filters.is_active() {
+            self.filters.find_next(&mut self.source, pos)
+        } else {
+            self.source.next(pos)
+        }
+    }
+
+    /// Find the previous line the filters show
+    fn next_shown_back(&mut self, pos: &Position) -> GetLine {
+        if self.filters.is_active() {
+            self.filters.find_next_back(&mut self.source, pos)
+        } else {
+            self.source.next_back(pos)
+        }
+    }
+
+    /// Find the next bookmarked line the filters show.  Like the FilterSet, we hand out virtual Positions at the end of
+    /// the line, so advancing from a hit leaves the Position where it is.
+    fn next_bookmark(&mut self, pos: &Position) -> GetLine {
+        if pos.is_invalid() {
+            return GetLine::Miss(Position::invalid());
+        }
+        let mut offset = pos.least_offset();
+        loop {
+            let Some(mark) = self.bookmarks.as_ref().and_then(|bookmarks| bookmarks.next(offset)) else {
+                return GetLine::Miss(Position::invalid());
+            };
+            match self.next_shown(&Position::from(mark)) {
+                GetLine::Hit(_, line) if line.offset <= mark => {
+                    let end = self.source.line_end(&line);
+                    return GetLine::Hit(Position::from(end), line);
+                },
+                // The filters hide the bookmarked line; look for a bookmark from the next line they show
+                GetLine::Hit(_, line) => offset = line.offset,
+                get => return get,
+            }
+        }
+    }
+
+    /// Find the previous bookmarked line the filters show.  Backward hits point at the start of the line.
+    fn next_bookmark_back(&mut self, pos: &Position) -> GetLine {
+        if pos.is_invalid() {
+            return GetLine::Miss(Position::invalid());
+        }
+        let mut offset = pos.most_offset();
+        loop {
+            let Some(mark) = self.bookmarks.as_ref().and_then(|bookmarks| bookmarks.next_back(offset)) else {
+                return GetLine::Miss(Position::invalid());
+            };
+            match self.next_shown_back(&Position::from(mark + 1)) {
+                GetLine::Hit(_, line) if self.source.line_end(&line) > mark => {
+                    return GetLine::Hit(Position::from(line.offset), line);
+                },
+                // The filters hide the bookmarked line; look for a bookmark before the previous line they show
+                GetLine::Hit(_, line) => offset = self.source.line_end(&line),
+                get => return get,
+            }
+        }
     }
 
-    /// Apply a new regex search expression to the filter
-    /// TODO: add more filters instead of replacing the one we currently allow
+    /// Add a regex filter to the set.  If string is empty, clears all filters.
     pub fn filter_regex(&mut self, re: &str) -> Result<(), regex::Error> {
         if re.is_empty() {
-            self.filter = None;
+            self.filters.clear();
+            Ok(())
         } else {
-            self.filter = Some(LogFilter::new(SearchType::new(re)?, self.source.len()));
+            self.filters.add(re, self.source.len())
         }
-        Ok(())
     }
 }
 
@@ -307,11 +772,21 @@ impl Stream for FilteredSource {
         self.source.is_open()
     }
 
+    fn truncations(&self) -> usize {
+        self.source.truncations()
+    }
+
     // Poll for new data
     fn poll(&mut self, timeout: Option<std::time::Instant>) -> usize {
         let len = self.source.poll(timeout);
-        if let Some(filter) = &mut self.filter {
-            filter.update_len(len);
+        if self.source.truncations() != self.truncations {
+            // The lines our filters found are gone
+            self.truncations = self.source.truncations();
+            self.filters.reset();
+        }
+        self.filters.update_len(len);
+        if let Some(time) = &mut self.time {
+            time.update_len(len);
         }
         len
     }
@@ -322,33 +797,70 @@ impl IndexedLog for FilteredSource {
         self.source.read_line(offset)
     }
 
+    fn line_end(&mut self, line: &crate::LogLine) -> usize {
+        self.source.line_end(line)
+    }
+
     fn next(&mut self, pos: &Position) -> GetLine {
-        if let Some(filter) = &mut self.filter {
-            filter.find_next(&mut self.source, pos)
+        // Skip ahead to the time window
+        let window = self.window();
+        let pos = if pos.least_offset() < window.start {
+            self.seek(window.start)
         } else {
-            self.source.next(pos)
+            pos.clone()
+        };
+        if pos.least_offset() >= window.end {
+            return GetLine::Miss(Position::invalid());
+        }
+
+        let get = if self.bookmarks.is_some() {
+            self.next_bookmark(&pos)
+        } else {
+            self.next_shown(&pos)
+        };
+
+        match get {
+            GetLine::Hit(_, line) if line.offset >= window.end => GetLine::Miss(Position::invalid()),
+            get => get,
         }
     }
 
     fn next_back(&mut self, pos: &Position) -> GetLine {
-        if let Some(filter) = &mut self.filter {
-            filter.find_next_back(&mut self.source, pos)
+        // Skip back to the time window
+        let window = self.window();
+        let pos = if !pos.is_invalid() && pos.most_offset() > window.end {
+            self.seek(window.end)
         } else {
-            self.source.next_back(pos)
+            pos.clone()
+        };
+
+        let get = if self.bookmarks.is_some() {
+            self.next_bookmark_back(&pos)
+        } else {
+            self.next_shown_back(&pos)
+        };
+
+        match get {
+            GetLine::Hit(_, line) if line.offset < window.start => GetLine::Miss(Position::invalid()),
+            get => get,
         }
     }
 
     fn advance(&mut self, pos: &Position) -> Position {
-        if let Some(filter) = &mut self.filter {
-            filter.advance(pos)
+        if self.bookmarks.is_some() {
+            pos.clone()
+        } else if self.filters.is_active() {
+            self.filters.advance(pos)
         } else {
             self.source.advance(pos)
         }
     }
 
     fn advance_back(&mut self, pos: &Position) -> Position {
-        if let Some(filter) = &mut self.filter {
-            filter.advance_back(pos)
+        if self.bookmarks.is_some() {
+            pos.clone()
+        } else if self.filters.is_active() {
+            self.filters.advance_back(pos)
         } else {
             self.source.advance_back(pos)
         }
@@ -360,10 +872,8 @@ impl IndexedLog for FilteredSource {
         } else {
             pos.clone()
         };
-        if let Some(filter) = &mut self.filter {
-            if filter.has_gaps() {
-                return filter.resolve_gaps(&mut self.source, &pos)
-            }
+        if self.filters.has_gaps() {
+            return self.filters.resolve_gaps(&mut self.source, &pos)
         }
 
         if self.source.has_gaps() {
@@ -388,11 +898,10 @@ impl IndexedLog for FilteredSource {
     fn info(&self) -> impl Iterator<Item = &'_ IndexStats> + '_
     where Self: Sized  {
         self.source.info()
-        .chain(self.filter.iter().flat_map(|f| f.info()))
+        .chain(self.filters.info())
     }
 
     fn has_gaps(&self) -> bool {
-        self.source.has_gaps() ||
-            self.filter.as_ref().map(|f| f.has_gaps()).unwrap_or(false)
+        self.source.has_gaps() || self.filters.has_gaps()
     }
 }
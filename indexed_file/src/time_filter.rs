@@ -0,0 +1,64 @@
+use chrono::NaiveDateTime;
+use std::ops::Range;
+
+use crate::{files::Stream, Log};
+
+/**
+ * Filter that hides lines outside a [from, to) time window.
+ *
+ * Unlike the SearchType filters, which evaluate every line, this one finds the byte offsets of the edges of the window
+ * by bisecting the log on timestamps. Lines outside the window are never read. The log is assumed to be mostly sorted
+ * by time.
+ */
+pub(crate) struct TimeFilter {
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+
+    /// Byte offsets of the lines inside the window, once we have found them
+    window: Option<Range<usize>>,
+
+    /// Log length when we found the window
+    len: usize,
+}
+
+impl TimeFilter {
+    pub fn new(from: Option<NaiveDateTime>, to: Option<NaiveDateTime>) -> Self {
+        Self {
+            from,
+            to,
+            window: None,
+            len: 0,
+        }
+    }
+
+    /// Byte range holding the lines inside the time window
+    pub fn window(&mut self, log: &mut Log) -> Range<usize> {
+        if let Some(window) = &self.window {
+            return window.clone();
+        }
+
+        let len = log.len();
+        let start = match self.from {
+            Some(from) => log.find_time(from).unwrap_or(len),
+            None => 0,
+        };
+        let end = match self.to {
+            Some(to) => log.find_time(to).unwrap_or(usize::MAX),
+            None => usize::MAX,
+        };
+        log::trace!("Time window {:?}..{:?} is {}..{}", self.from, self.to, start, end);
+
+        let window = start..end.max(start);
+        self.window = Some(window.clone());
+        self.len = len;
+        window
+    }
+
+    // We don't participate in poll(), so poll()-callers should update us by calling this
+    pub fn update_len(&mut self, len: usize) {
+        if len != self.len {
+            // The window edges may have moved if they were at the end of the log
+            self.window = None;
+        }
+    }
+}
@@ -1,115 +1,313 @@
 // A factory for timestamps for log lines in a file
-use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveDate, NaiveTime, TimeDelta, Timelike};
 use regex::Regex;
+use std::path::Path;
+use std::sync::RwLock;
+
+/*  Timestamp formats are regexes with named capture groups.  These groups are understood:
+
+        year        4-digit year.  If missing, we use the current year (see TimeStamper::set_year)
+        month       month number or 3-letter English month name
+        day         day of the month
+        clock       HH:MM:SS with optional fractional seconds after '.' or ','
+        ampm        AM or PM for a 12-hour clock
+        tz          Z, or an offset like +hh:mm or -hhmm.  If given, the time is converted to UTC.  Times the user
+                    gives without an offset are taken to be in the same zone as the log (see parse_user_time).
+        epoch       seconds since 1970, with optional fraction in 'frac'
+        epoch_ms    milliseconds since 1970
+ */
+
+const MONTHS: &str = r"Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec";
+const CLOCK: &str = r"\d{2}:\d{2}:\d{2}(?:[.,]\d+)?";
+
+// Built-in formats, in the order we try them
+fn builtin_formats() -> Vec<(&'static str, String)> {
+    vec![
+        // Apr  4 22:21:15.813
+        ("syslog", format!(r"^(?P<month>{MONTHS}) (?P<day>[ 0-9]{{2}}) (?P<clock>{CLOCK})\b")),
+
+        // <34>1 2003-10-11T22:14:15.003Z mymachine.example.com su - ID47 - ...
+        ("rfc5424", format!(r"^<\d{{1,3}}>\d{{1,2}} (?P<year>\d{{4}})-(?P<month>\d{{2}})-(?P<day>\d{{2}})T(?P<clock>{CLOCK})(?P<tz>Z|[+-]\d{{2}}:\d{{2}})")),
+
+        // 2009-11-10 23:00:00.123456 +0000 UTC    (Go time.Time.String())
+        ("go", format!(r"(?P<year>\d{{4}})-(?P<month>\d{{2}})-(?P<day>\d{{2}}) (?P<clock>{CLOCK}) (?P<tz>[+-]\d{{4}}) [A-Z]{{3,5}}\b")),
+
+        // 2009/11/10 23:00:00.123456    (Go log package)
+        ("go-log", format!(r"^(?P<year>\d{{4}})/(?P<month>\d{{2}})/(?P<day>\d{{2}}) (?P<clock>{CLOCK})\b")),
+
+        // 2024-04-07T22:21:15.813+02:00, 2024-04-07 22:21:15,813    (ISO-8601, RFC3339, log4j, Python logging)
+        ("iso8601", format!(r"(?P<year>\d{{4}})-(?P<month>\d{{2}})-(?P<day>\d{{2}})[T ](?P<clock>{CLOCK})(?P<tz>Z|[+-]\d{{2}}:?\d{{2}})?")),
+
+        // 127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326
+        ("apache", format!(r"\[(?P<day>\d{{2}})/(?P<month>{MONTHS})/(?P<year>\d{{4}}):(?P<clock>\d{{2}}:\d{{2}}:\d{{2}}) (?P<tz>[+-]\d{{4}})\]")),
+
+        // Sun Apr 07 22:21:15 UTC 2024    (java.util.Date.toString())
+        ("java-date", format!(r"(?:Mon|Tue|Wed|Thu|Fri|Sat|Sun) (?P<month>{MONTHS}) (?P<day>[ 0-9]{{2}}) (?P<clock>\d{{2}}:\d{{2}}:\d{{2}}) (?:[A-Z]{{3,5}} )?(?P<year>\d{{4}})\b")),
+
+        // Apr 07, 2024 10:21:15 PM    (java.util.logging.SimpleFormatter)
+        ("java-logging", format!(r"^(?P<month>{MONTHS}) (?P<day>\d{{1,2}}), (?P<year>\d{{4}}) (?P<clock>\d{{1,2}}:\d{{2}}:\d{{2}}) (?P<ampm>AM|PM)\b")),
+
+        // 1712528475123
+        ("epoch-ms", r"^\[?(?P<epoch_ms>\d{13})\b".to_string()),
+
+        // 1712528475.123
+        ("epoch", r"^\[?(?P<epoch>\d{10})(?:\.(?P<frac>\d+))?\b".to_string()),
+    ]
+}
+
+// User-defined formats are tried before the built-in ones
+static USER_FORMATS: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+
+/// Read user-defined timestamp formats from a config file.  Each line holds one format as `name = regex`, using
+/// the capture group names listed above.  Blank lines and lines starting with '#' are ignored.
+pub fn read_user_formats(path: &Path) -> std::io::Result<Vec<(String, String)>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut formats = Vec::new();
+    for (num, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), num + 1, msg));
+        let Some((name, pattern)) = line.split_once('=') else {
+            return Err(invalid("expected name = regex".to_string()));
+        };
+        let pattern = pattern.trim();
+        if let Err(e) = Regex::new(pattern) {
+            return Err(invalid(e.to_string()));
+        }
+        formats.push((name.trim().to_string(), pattern.to_string()));
+    }
+    Ok(formats)
+}
+
+/// Load user-defined timestamp formats from a config file for every TimeStamper made after this.  If the file has
+/// an error, the formats loaded before are kept.  Returns the number of formats loaded.
+pub fn load_user_formats(path: &Path) -> std::io::Result<usize> {
+    let formats = read_user_formats(path)?;
+    let count = formats.len();
+    *USER_FORMATS.write().unwrap() = formats;
+    Ok(count)
+}
+
+/// Parse a time typed by the user, like "2024-05-01 13:45", "2024-05-01T13:45:10.5", "2024-05-01" or "13:45".
+/// A time without a date is taken to be on `date`.  Any timestamp format we recognize in logs is accepted, too.
+/// A time without a UTC offset is wall-clock time in the zone `offset` of the log, so it is converted to UTC the
+/// same way as the log's own timestamps.
+pub fn parse_user_time(text: &str, date: Option<NaiveDate>, offset: Option<TimeDelta>) -> Option<NaiveDateTime> {
+    let local = |time: NaiveDateTime| time - offset.unwrap_or_default();
+    let text = text.trim();
+
+    const DATE_TIMES: &[&str] = &[
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+        "%Y/%m/%d %H:%M:%S%.f",
+        "%Y/%m/%d %H:%M",
+    ];
+    if let Some(time) = DATE_TIMES.iter().find_map(|fmt| NaiveDateTime::parse_from_str(text, fmt).ok()) {
+        return Some(local(time));
+    }
+
+    if let Ok(day) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return day.and_hms_opt(0, 0, 0).map(local);
+    }
+
+    if let Some(time) = ["%H:%M:%S%.f", "%H:%M"].iter().find_map(|fmt| NaiveTime::parse_from_str(text, fmt).ok()) {
+        return date.map(|date| local(date.and_time(time)));
+    }
+
+    let mut stamper = TimeStamper::default();
+    let time = stamper.time(text)?;
+    match stamper.offset() {
+        Some(_) => Some(time),
+        None => Some(local(time)),
+    }
+}
+
+// Once a format has matched this many lines we assume it is the format for the file and stop trying the others.
+// If no format matches this many lines we assume the file has no timestamps and stop looking.
+const WINNER_THRESHOLD: usize = 1000;
+
+pub struct TimeFormat {
+    pub name: String,
+    pub pattern: Regex,
+    matches: usize,
+}
 
 pub struct TimeStamper {
-    pub patterns: Vec<Regex>,
-    matches: Vec<usize>,
+    pub formats: Vec<TimeFormat>,
     unmatched: usize,
-}
+    year: i32,
 
-impl TimeStamper {
-    // TODO: Add configs
-    pub fn default() -> Self {
-        // TODO: Load from config
+    /// UTC offset of the last timestamp we parsed, if it had one
+    offset: Option<TimeDelta>,
+}
 
-        // TODO: Use RegexSet here
-        let src_patterns = vec![
-            r"^(?P<month>Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) (?P<day>[ 0-9]{2}) (?P<clock>[0-2][0-9]:[0-5][0-9]:[0-6][0-9]\.\d{3})\b",
-        ];
+impl Default for TimeStamper {
+    fn default() -> Self {
+        Self::with_formats(&USER_FORMATS.read().unwrap())
+    }
+}
 
+impl TimeStamper {
+    /// A stamper which tries the given user formats before the built-in ones
+    pub fn with_formats(user_formats: &[(String, String)]) -> Self {
         let mut s = Self {
-            patterns: Vec::default(),
-            matches: Vec::default(),
+            formats: Vec::default(),
             unmatched: 0,
+            year: chrono::Local::now().year(),
+            offset: None,
         };
 
-        for p in src_patterns {
-            s.push(p);
+        for (name, pattern) in user_formats {
+            s.push_named(name, pattern);
+        }
+        for (name, pattern) in builtin_formats() {
+            s.push_named(name, &pattern);
         }
         s
     }
 
     pub fn push(&mut self, matcher: &str) {
+        self.push_named("custom", matcher);
+    }
+
+    pub fn push_named(&mut self, name: &str, matcher: &str) {
         match Regex::new(matcher) {
             Ok(re) => {
-                self.patterns.push(re);
-                self.matches.push(0);
+                self.formats.push(TimeFormat { name: name.to_string(), pattern: re, matches: 0 });
                 self.unmatched = 0;
             },
             e => eprintln!("Error parsing timestamp pattern: {:?}", e),
         }
     }
 
-    #[allow(dead_code)]
-    fn parse_time(line: &str, re: &Regex) -> Option<NaiveDateTime> {
+    /// Set the year to use for formats which do not include one
+    pub fn set_year(&mut self, year: i32) {
+        self.year = year;
+    }
 
-        let months = "Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec";
+    /// The UTC offset of the last timestamp we parsed, or None if it had no offset
+    pub fn offset(&self) -> Option<TimeDelta> {
+        self.offset
+    }
 
-        if let Some(caps) = re.captures(line) {
-            let month:u32 = match caps.name("month") {
-                Some(x) => {
-                    let m = x.as_str().trim();
-                    if let Ok(imonth) = m.parse::<u32>() {
-                        imonth
-                    } else {
-                        // Find month name string and convert to 1..12
-                        months.find(m).unwrap_or(0) as u32 / 4 + 1
-                    }
-                },
-                _ => return None,
-            };
+    /// The format we settled on for this file, if any
+    pub fn winner(&self) -> Option<&str> {
+        match self.formats.as_slice() {
+            [format] if format.matches >= WINNER_THRESHOLD => Some(&format.name),
+            _ => None,
+        }
+    }
 
-            let day:u32 = match caps.name("day") {
-                Some(x) => {
-                    if let Ok(day) = x.as_str().trim().parse::<u32>() {
-                        day
-                    } else {
-                        return None
-                    }
-                },
-                _ => return None,
-            };
+    // Parse the timestamp in the line and convert it to UTC.  Also returns the UTC offset it had, if any.
+    fn parse_time(line: &str, re: &Regex, year: i32) -> Option<(NaiveDateTime, Option<TimeDelta>)> {
+        let caps = re.captures(line)?;
 
-            let clock = match caps.name("clock") {
-                Some(x) => {
-                    let clock = x.as_str();
-                    let time = NaiveTime::parse_from_str(clock, "%H:%M:%S%.3f");
-                    time.unwrap()
+        if let Some(ms) = caps.name("epoch_ms") {
+            let ms = ms.as_str().parse::<i64>().ok()?;
+            return DateTime::from_timestamp_millis(ms).map(|t| (t.naive_utc(), Some(TimeDelta::zero())));
+        }
 
-                },
-                _ => return None,
+        if let Some(secs) = caps.name("epoch") {
+            let secs = secs.as_str().parse::<i64>().ok()?;
+            let nanos = match caps.name("frac") {
+                Some(frac) => Self::parse_nanos(frac.as_str())?,
+                None => 0,
             };
+            return DateTime::from_timestamp(secs, nanos).map(|t| (t.naive_utc(), Some(TimeDelta::zero())));
+        }
+
+        let year = match caps.name("year") {
+            Some(x) => x.as_str().parse::<i32>().ok()?,
+            None => year,
+        };
 
-            NaiveDate::from_ymd_opt(2000, month, day)
-                .map(|date| NaiveDateTime::new(date, clock))
-        } else {
-            None
+        let month:u32 = match caps.name("month") {
+            Some(x) => {
+                let m = x.as_str().trim();
+                if let Ok(imonth) = m.parse::<u32>() {
+                    imonth
+                } else {
+                    // Find month name string and convert to 1..12
+                    MONTHS.find(m)? as u32 / 4 + 1
+                }
+            },
+            _ => return None,
+        };
+
+        let day:u32 = caps.name("day")?.as_str().trim().parse().ok()?;
+
+        let clock = caps.name("clock")?.as_str();
+        let (clock, frac) = match clock.split_once(['.', ',']) {
+            Some((clock, frac)) => (clock, Self::parse_nanos(frac)?),
+            None => (clock, 0),
+        };
+        let mut time = NaiveTime::parse_from_str(clock, "%H:%M:%S").ok()?
+            .with_nanosecond(frac)?;
+
+        if let Some(ampm) = caps.name("ampm") {
+            let hour = time.hour() % 12 + if ampm.as_str() == "PM" { 12 } else { 0 };
+            time = time.with_hour(hour)?;
+        }
+
+        let stamp = NaiveDate::from_ymd_opt(year, month, day)
+            .map(|date| NaiveDateTime::new(date, time))?;
+
+        match caps.name("tz") {
+            Some(tz) => {
+                let offset = Self::parse_offset(tz.as_str())?;
+                Some((stamp - offset, Some(offset)))
+            },
+            None => Some((stamp, None)),
         }
     }
 
+    // Convert a decimal fraction of a second to nanoseconds
+    fn parse_nanos(frac: &str) -> Option<u32> {
+        let digits = &frac[..frac.len().min(9)];
+        let nanos = digits.parse::<u32>().ok()?;
+        Some(nanos * 10u32.pow(9 - digits.len() as u32))
+    }
+
+    // Parse a UTC offset like Z, +02:00 or -0700
+    fn parse_offset(tz: &str) -> Option<TimeDelta> {
+        if tz == "Z" {
+            return Some(TimeDelta::zero());
+        }
+        let sign = if tz.starts_with('-') { -1 } else { 1 };
+        let digits = tz[1..].replace(':', "");
+        let hours = digits.get(0..2)?.parse::<i64>().ok()?;
+        let minutes = digits.get(2..4)?.parse::<i64>().ok()?;
+        Some(TimeDelta::minutes(sign * (hours * 60 + minutes)))
+    }
 
-    #[allow(dead_code)]
     pub fn time(&mut self, line: &str) -> Option<NaiveDateTime> {
-        // TODO: Pack this into a time factory we can hand out per file.
-        // Then, try each configured timestamp matcher in turn until we find one.
+        // Try each configured timestamp matcher in turn until we find one.
         // Count how many successful matches there are for each.
-        // When one of them reaches 1000 (or some threshold) assume that's the format and stop checking the others.
-        // If none matches after (threshold) tests, stop testing all of them and assume None for every line.
-
-        // Apr  4 22:21:15.813
-        // Mmm DD HH:MM:SS.fff
-        // lnav matcher: "^(?<timestamp>[A-Z][a-z]{2} {1,2}\\d{1,2} \\d{2}:\\d{2}:\\d{2}\\.\\d{3}) (?<pid>[0-9A-F]{12}) (?<crumb>[A-Z])      "
-        for (i, m) in self.patterns.iter().enumerate() {
-            if let Some(ts) = TimeStamper::parse_time(line, m) {
-                self.matches[i] += 1;
-                // TODO: Compare to threshold for "winning matcher" and destroy all others when reached
+        // When one of them reaches WINNER_THRESHOLD assume that's the format and stop checking the others.
+        // If none matches after WINNER_THRESHOLD tests, stop testing all of them and assume None for every line.
+        for i in 0..self.formats.len() {
+            if let Some((ts, offset)) = TimeStamper::parse_time(line, &self.formats[i].pattern, self.year) {
+                self.offset = offset;
+                self.formats[i].matches += 1;
+                if self.formats[i].matches == WINNER_THRESHOLD && self.formats.len() > 1 {
+                    log::trace!("Timestamp format {} wins", self.formats[i].name);
+                    let winner = self.formats.swap_remove(i);
+                    self.formats = vec![winner];
+                }
                 return Some(ts)
             }
         }
 
-        // TODO: Compare to threshold for "max losers" and turn off checking if reached
         self.unmatched += 1;
+        if self.unmatched >= WINNER_THRESHOLD && !self.formats.is_empty() && self.formats.iter().all(|f| f.matches == 0) {
+            log::trace!("No timestamp format matched; giving up");
+            self.formats.clear();
+        }
 
         None
     }
@@ -117,7 +315,6 @@ impl TimeStamper {
 
 #[test]
 fn test_timestamp_fields() {
-    use chrono::{Datelike, Timelike};
     let mut stamper = TimeStamper::default();
     let line = "Apr  7 22:21:15.813 some log data here";
 
@@ -127,7 +324,17 @@ fn test_timestamp_fields() {
     assert_eq!(time.hour(), 22);
     assert_eq!(time.minute(), 21);
     assert_eq!(time.second(), 15);
-    assert_eq!(time.timestamp_subsec_millis(), 813);
+    assert_eq!(time.and_utc().timestamp_subsec_millis(), 813);
+}
+
+#[test]
+fn test_timestamp_year() {
+    let mut stamper = TimeStamper::default();
+    let line = "Apr  7 22:21:15.813 some log data here";
+    assert_eq!(stamper.time(line).unwrap().year(), chrono::Local::now().year());
+
+    stamper.set_year(2019);
+    assert_eq!(stamper.time(line).unwrap().year(), 2019);
 }
 
 #[test]
@@ -149,3 +356,106 @@ fn test_timestamp_fail() {
         assert!(stamper.time(line).is_none());
     }
 }
+
+#[test]
+fn test_timestamp_catalog() {
+    let expected = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+
+    let cases = [
+        ("2024-04-07T22:21:15.813Z info: started", "2024-04-07 22:21:15.813"),
+        ("2024-04-07T22:21:15+02:00 info: started", "2024-04-07 20:21:15"),
+        ("2024-04-07 22:21:15,813 INFO [main] started", "2024-04-07 22:21:15.813"),
+        ("<34>1 2003-10-11T22:14:15.003Z mymachine.example.com su - ID47 - failed", "2003-10-11 22:14:15.003"),
+        ("1712528475 started", "2024-04-07 22:21:15"),
+        ("1712528475.5 started", "2024-04-07 22:21:15.5"),
+        ("1712528475813 started", "2024-04-07 22:21:15.813"),
+        (r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326"#, "2000-10-10 20:55:36"),
+        ("2009/11/10 23:00:00 hello", "2009-11-10 23:00:00"),
+        ("2009-11-10 23:00:00.5 +0100 CET m=+0.000000001", "2009-11-10 22:00:00.5"),
+        ("Sun Apr 07 22:21:15 UTC 2024 started", "2024-04-07 22:21:15"),
+        ("Apr 07, 2024 10:21:15 PM com.example.Main main", "2024-04-07 22:21:15"),
+    ];
+
+    for (line, time) in cases {
+        let mut stamper = TimeStamper::default();
+        assert_eq!(stamper.time(line), Some(expected(time)), "{line}");
+    }
+}
+
+#[test]
+fn test_timestamp_winner() {
+    let mut stamper = TimeStamper::default();
+    for _ in 0..WINNER_THRESHOLD {
+        assert!(stamper.time("2024-04-07T22:21:15Z started").is_some());
+    }
+    assert_eq!(stamper.winner(), Some("iso8601"));
+
+    // Other formats are not checked anymore
+    assert!(stamper.time("Apr  7 22:21:15.813 some log data here").is_none());
+}
+
+#[test]
+fn test_timestamp_give_up() {
+    let mut stamper = TimeStamper::default();
+    for _ in 0..WINNER_THRESHOLD {
+        assert!(stamper.time("no timestamp here").is_none());
+    }
+    assert!(stamper.formats.is_empty());
+    assert!(stamper.time("2024-04-07T22:21:15Z started").is_none());
+}
+
+#[test]
+fn test_timestamp_user_formats() {
+    let mut path = std::env::temp_dir();
+    path.push(format!("felon-timestamps-{}", std::process::id()));
+    std::fs::write(&path, "# My formats\n\nmyapp = ^\\[(?P<day>\\d{2})\\.(?P<month>\\d{2})\\.(?P<year>\\d{4}) (?P<clock>\\d{2}:\\d{2}:\\d{2})\\]\n").unwrap();
+    let formats = read_user_formats(&path).unwrap();
+    assert_eq!(formats.len(), 1);
+
+    std::fs::write(&path, "broken = (\n").unwrap();
+    assert!(read_user_formats(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    let mut stamper = TimeStamper::with_formats(&formats);
+    assert_eq!(stamper.formats[0].name, "myapp");
+    let time = stamper.time("[07.04.2024 22:21:15] started").unwrap();
+    assert_eq!(time, NaiveDateTime::parse_from_str("2024-04-07 22:21:15", "%Y-%m-%d %H:%M:%S").unwrap());
+}
+
+#[test]
+fn test_parse_user_time() {
+    let expected = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+    let date = NaiveDate::from_ymd_opt(2024, 5, 1);
+
+    assert_eq!(parse_user_time("2024-05-01 13:45", None, None), Some(expected("2024-05-01 13:45:00")));
+    assert_eq!(parse_user_time(" 2024-05-01T13:45:10.5 ", None, None), Some(expected("2024-05-01 13:45:10.5")));
+    assert_eq!(parse_user_time("2024/05/01 13:45:10", None, None), Some(expected("2024-05-01 13:45:10")));
+    assert_eq!(parse_user_time("2024-05-01", None, None), Some(expected("2024-05-01 00:00:00")));
+    assert_eq!(parse_user_time("13:45", date, None), Some(expected("2024-05-01 13:45:00")));
+    assert_eq!(parse_user_time("13:45", None, None), None);
+    assert_eq!(parse_user_time("2024-05-01T13:45:10+02:00", None, None), Some(expected("2024-05-01 11:45:10")));
+    assert_eq!(parse_user_time("yesterday", date, None), None);
+}
+
+#[test]
+fn test_parse_user_time_offset() {
+    let expected = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+
+    // The log's timestamps are in UTC+2 and converted to UTC
+    let mut stamper = TimeStamper::default();
+    let line = stamper.time("2024-05-01T10:00:00+02:00 started").unwrap();
+    assert_eq!(line, expected("2024-05-01 08:00:00"));
+    let offset = stamper.offset();
+    assert_eq!(offset, Some(TimeDelta::hours(2)));
+
+    // The user's wall-clock times are in the log's zone, so they land on the same line
+    let date = NaiveDate::from_ymd_opt(2024, 5, 1);
+    assert_eq!(parse_user_time("10:00", date, offset), Some(line));
+    assert_eq!(parse_user_time("2024-05-01 10:00", None, offset), Some(line));
+    assert_eq!(parse_user_time("2024-05-01", None, offset), Some(expected("2024-04-30 22:00:00")));
+    assert_eq!(parse_user_time("May  1 10:00:00.000", None, offset).map(|time| time.time()), Some(line.time()));
+
+    // Times with their own offset are not moved
+    assert_eq!(parse_user_time("2024-05-01T10:00:00+02:00", None, offset), Some(line));
+    assert_eq!(parse_user_time("2024-05-01T08:00:00Z", None, offset), Some(line));
+}
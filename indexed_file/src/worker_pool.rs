@@ -0,0 +1,96 @@
+// Worker threads for jobs we can run in parallel without stalling the UI, like scanning the chunks of a big file or
+// decoding the frames of a compressed one
+
+use std::ops::Range;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type Job<T> = Box<dyn FnOnce() -> T + Send>;
+
+/// A pool of workers running jobs and handing back their results as they finish.  For jobs on chunks of a log we
+/// keep track of how far we have given out chunks, front to back, so the caller can hand out the next ones as the
+/// workers finish.
+pub(crate) struct WorkerPool<T> {
+    jobs: mpsc::Sender<Job<T>>,
+    results: mpsc::Receiver<T>,
+
+    /// Jobs given to the workers that we haven't received yet
+    pending: usize,
+
+    /// Number of jobs we keep the workers busy with
+    depth: usize,
+
+    /// Offset where the next chunk we give out begins.  Everything before it has been given out already.
+    pub(crate) next: usize,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// A pool with a worker for each core
+    pub(crate) fn new() -> Self {
+        Self::with_threads(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub(crate) fn with_threads(threads: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job<T>>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for _ in 0..threads {
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            // The workers exit when we drop the pool
+            thread::spawn(move || loop {
+                let job = job_receiver.lock().unwrap().recv();
+                let Ok(job) = job else { break };
+                if result_sender.send(job()).is_err() {
+                    break;
+                }
+            });
+        }
+        Self {
+            jobs,
+            results,
+            pending: 0,
+            depth: threads * 2,
+            next: 0,
+        }
+    }
+
+    /// True if the workers have all the jobs they can use for now
+    pub(crate) fn is_busy(&self) -> bool {
+        self.pending >= self.depth
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Give the workers a job.  Returns false if the workers are gone.
+    pub(crate) fn run(&mut self, job: impl FnOnce() -> T + Send + 'static) -> bool {
+        let sent = self.jobs.send(Box::new(job)).is_ok();
+        if sent {
+            self.pending += 1;
+        }
+        sent
+    }
+
+    /// Give the workers a job on the next chunk
+    pub(crate) fn submit(&mut self, range: Range<usize>, job: impl FnOnce(Range<usize>) -> T + Send + 'static) {
+        self.next = range.end;
+        self.run(move || job(range));
+    }
+
+    /// The result of one of the jobs we submitted, waiting up to the timeout for the workers to finish one
+    pub(crate) fn receive(&mut self, timeout: Duration) -> Option<T> {
+        let result = self.results.recv_timeout(timeout).ok()?;
+        self.pending -= 1;
+        Some(result)
+    }
+
+    /// The result of one of the jobs we submitted, waiting as long as it takes.  None if the workers are gone.
+    pub(crate) fn wait(&mut self) -> Option<T> {
+        let result = self.results.recv().ok()?;
+        self.pending -= 1;
+        Some(result)
+    }
+}
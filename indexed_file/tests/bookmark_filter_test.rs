@@ -0,0 +1,81 @@
+// Tests for showing only bookmarked lines in LogStack
+
+#[cfg(test)]
+mod bookmark_filter_tests {
+    use std::collections::BTreeSet;
+    use indexed_file::files::{CursorLogFile, CursorUtil, LogBase};
+    use indexed_file::{IndexedLog, Log, LogStack};
+
+    // A log of numbered lines, each "line N\n" padded to 10 bytes so line N starts at offset N * 10
+    fn make_log(lines: usize) -> LogStack {
+        let text = (0..lines).map(|i| format!("line {:04}", i)).collect::<Vec<_>>();
+        let text = text.iter().map(|s| s.as_str()).collect();
+        LogStack::new(Log::from(CursorLogFile::from_vec(text).unwrap().to_src()))
+    }
+
+    fn marks(lines: &[usize]) -> Option<BTreeSet<usize>> {
+        Some(lines.iter().map(|line| line * 10).collect())
+    }
+
+    fn shown(log: &mut LogStack) -> Vec<String> {
+        log.iter_lines().map(|line| line.line.trim_end().to_string()).collect()
+    }
+
+    // Search forward until we find something or give up
+    fn find(log: &mut LogStack, offset: usize) -> Option<usize> {
+        let mut found = log.search_next(1, offset);
+        while found.is_none() && log.describe_pending().starts_with("Search") {
+            found = log.run_pending(100);
+        }
+        found
+    }
+
+    #[test]
+    fn test_bookmark_filter() {
+        let mut log = make_log(1000);
+        log.filter_bookmarks(marks(&[3, 500, 999]));
+        assert_eq!(shown(&mut log), vec!["line 0003", "line 0500", "line 0999"]);
+
+        let rev = log.iter_lines().rev().map(|line| line.line.trim_end().to_string()).collect::<Vec<_>>();
+        assert_eq!(rev, vec!["line 0999", "line 0500", "line 0003"]);
+
+        // Lines from the middle of the log
+        let lines = log.iter_lines_range(&(40..6000)).map(|line| line.offset).collect::<Vec<_>>();
+        assert_eq!(lines, vec![5000]);
+
+        log.filter_bookmarks(None);
+        assert_eq!(shown(&mut log).len(), 1000);
+    }
+
+    #[test]
+    fn test_bookmark_filter_with_filters() {
+        let mut log = make_log(1000);
+        log.filter_bookmarks(marks(&[3, 10, 500, 510, 999]));
+
+        // Only the bookmarks the filter shows
+        log.filter_regex("0$").unwrap();
+        assert_eq!(shown(&mut log), vec!["line 0010", "line 0500", "line 0510"]);
+        let rev = log.iter_lines().rev().map(|line| line.line.trim_end().to_string()).collect::<Vec<_>>();
+        assert_eq!(rev, vec!["line 0510", "line 0500", "line 0010"]);
+
+        // Searches only find the lines we show
+        log.search_regex("line 05").unwrap();
+        assert_eq!(find(&mut log, 0), Some(5000));
+        assert_eq!(find(&mut log, 5010), Some(5100));
+        assert_eq!(find(&mut log, 5110), None);
+    }
+
+    #[test]
+    fn test_bookmark_filter_invalid_utf8() {
+        // Each line starts with bytes that are not valid utf-8, which are longer than a line once they are replaced
+        let data = (0..100).flat_map(|i| [vec![0xff; 8], format!("{i:04}\n").into_bytes()].concat()).collect::<Vec<_>>();
+        let mut log = LogStack::new(Log::from(CursorLogFile::new(data)));
+        let marks = [3, 4, 5, 50];
+        log.filter_bookmarks(Some(marks.iter().map(|line| line * 13).collect()));
+
+        let expected = marks.iter().map(|line| line * 13).collect::<Vec<_>>();
+        assert_eq!(log.iter_lines().map(|line| line.offset).collect::<Vec<_>>(), expected);
+        let rev = log.iter_lines().rev().map(|line| line.offset).collect::<Vec<_>>();
+        assert_eq!(rev.into_iter().rev().collect::<Vec<_>>(), expected);
+    }
+}
@@ -35,7 +35,7 @@ mod filtered_log_iterator_helper {
         }
     }
 
-    pub(crate) fn new<LOG: IndexedLog>(log: &mut LOG) -> LineIndexerDataIterator<LOG> {
+    pub(crate) fn new<LOG: IndexedLog>(log: &mut LOG) -> LineIndexerDataIterator<'_, LOG> {
         LineIndexerDataIterator::new(log)
     }
 
@@ -137,6 +137,17 @@ mod filtered_log_iterator_tests {
     }
 
 
+    #[test]
+    fn test_iterator_rev_from_offset_no_match_at_start() {
+        let (harness, mut file) = Harness::default();
+        // Match lines from 5,000 to 5,999. The first line in the file does not match.
+        file.filter_regex("5...$").unwrap();
+
+        let range = ..5500 * harness.patt_len;
+        let it = LineIndexerDataIterator::range(&mut file, &range);
+        assert_eq!(it.rev().count(), 500);
+    }
+
     #[test]
     fn test_iterator_gaps() {
         let (harness, mut file) = Harness::default();
@@ -457,7 +468,7 @@ mod filtered_log_iterator_tests {
         file.filter_regex("0$").unwrap();
 
         let size = harness.lines * harness.patt_len;
-        let offsets = (0..harness.lines/100).map(|_| rand::random::<usize>() % size).collect::<Vec<_>>();
+        let offsets = (0..harness.lines/100).map(|_| (rand::random::<u64>() as usize) % size).collect::<Vec<_>>();
 
         // In case of failure, copy the random vector from the log here and uncomment this line to continue debugging:
         // let offsets = vec![30431, 47575, 3525, 185, 44166, 41886, 34670, 21278, 33469, 28364, 42469, 14464, 11461, 52506, 21765, 17043, 22367, 18331, 42082, 46408, 5961, 9943, 50902, 6684, 37820, 17028, 35756, 47341, 46853, 50429, 7729, 21521, 46755, 49777, 16002, 1483, 7347, 4243, 4860, 20703, 48702, 12057, 42099, 2624, 15159, 47419, 1596, 4940, 51691, 12911, 27690, 25517, 39068, 53378, 13010, 4652, 2462, 44391, 2575, 21026];
@@ -518,4 +529,133 @@ mod filtered_log_iterator_tests {
         assert_eq!(file.info().nth(1).unwrap().lines_indexed, harness.lines / 10);
      }
 
+
+    #[test]
+    fn test_multiple_includes() {
+        let (harness, mut file) = Harness::default();
+        file.filter_regex("000$").unwrap();
+        file.filter_regex("500$").unwrap();
+
+        let offsets = file.iter_lines().map(|line| line.offset).collect::<Vec<_>>();
+        assert_eq!(offsets.len(), harness.lines / 500);
+        for (i, offset) in offsets.iter().enumerate() {
+            assert_eq!(*offset, i * 500 * harness.patt_len);
+        }
+
+        let rev = file.iter_lines().rev().map(|line| line.offset).collect::<Vec<_>>();
+        assert_eq!(rev.into_iter().rev().collect::<Vec<_>>(), offsets);
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let (harness, mut file) = Harness::default();
+        file.filter_regex("00$").unwrap();
+        file.filter_regex("!000$").unwrap();
+
+        let lines = file.iter_lines().map(|line| line.line.trim().to_string()).collect::<Vec<_>>();
+        assert_eq!(lines.len(), harness.lines / 100 - harness.lines / 1000);
+        assert!(lines.iter().all(|line| line.ends_with("00") && !line.ends_with("000")));
+
+        let rev = file.iter_lines().rev().count();
+        assert_eq!(rev, lines.len());
+    }
+
+    #[test]
+    fn test_multiple_excludes() {
+        let (harness, mut file) = Harness::default();
+        file.filter_regex("!0$").unwrap();
+        file.filter_regex("!1$").unwrap();
+
+        let lines = file.iter_lines().map(|line| line.line.trim().to_string()).collect::<Vec<_>>();
+        assert_eq!(lines.len(), harness.lines - harness.lines / 5);
+        assert!(lines.iter().all(|line| !line.ends_with('0') && !line.ends_with('1')));
+
+        // Start in the middle of the file, going backwards
+        let range = ..harness.lines / 2 * harness.patt_len;
+        let it = LineIndexerDataIterator::range(&mut file, &range);
+        assert_eq!(it.rev().count(), lines.len() / 2);
+    }
+
+    #[test]
+    fn test_enable_disable_filters() {
+        let (harness, mut file) = Harness::default();
+        file.filter_regex("00$").unwrap();
+        file.filter_regex("!000$").unwrap();
+        assert_eq!(file.iter_lines().count(), harness.lines / 100 - harness.lines / 1000);
+
+        // Disabling the exclude filter shows its lines again
+        file.enable_filter(1, false);
+        assert_eq!(file.filters().collect::<Vec<_>>(), vec![("00$", true), ("!000$", false)]);
+        assert_eq!(file.iter_lines().count(), harness.lines / 100);
+
+        // The include filter kept its index while it was combined with the other one
+        assert_eq!(file.info().nth(1).unwrap().lines_indexed, harness.lines / 100);
+
+        // Adding a filter we already have enables it again rather than adding another one
+        file.filter_regex("!000$").unwrap();
+        assert_eq!(file.filters().count(), 2);
+        assert_eq!(file.iter_lines().count(), harness.lines / 100 - harness.lines / 1000);
+
+        file.remove_filter(0);
+        assert_eq!(file.iter_lines().count(), harness.lines - harness.lines / 1000);
+
+        // Empty filter clears them all
+        file.filter_regex("").unwrap();
+        assert_eq!(file.filters().count(), 0);
+        assert_eq!(file.iter_lines().count(), harness.lines);
+    }
+
+    #[test]
+    fn test_edit_filters() {
+        let (harness, mut file) = Harness::default();
+        file.filter_regex("00$").unwrap();
+        file.filter_regex("5$").unwrap();
+        file.enable_filter(1, false);
+
+        // Editing keeps the place and the enabled state; a leading '!' makes it an exclude filter
+        file.edit_filter(0, "!000$").unwrap();
+        assert_eq!(file.filters().collect::<Vec<_>>(), vec![("!000$", true), ("5$", false)]);
+        assert_eq!(file.iter_lines().count(), harness.lines - harness.lines / 1000);
+        assert!(file.edit_filter(0, "(").is_err());
+        assert_eq!(file.filters().next(), Some(("!000$", true)));
+
+        file.move_filter(1, 0);
+        assert_eq!(file.filters().collect::<Vec<_>>(), vec![("5$", false), ("!000$", true)]);
+        assert_eq!(file.filter_stats(1).unwrap().lines_indexed, harness.lines - harness.lines / 1000);
+        assert!(file.filter_stats(2).is_none());
+    }
+
+    #[test]
+    fn test_multiple_filters_gap_filler() {
+        let (harness, mut file) = Harness::default();
+        file.filter_regex("00$").unwrap();
+        file.filter_regex("!000$").unwrap();
+
+        let pos = file.seek(0);
+        file.resolve_gaps(&pos);
+        assert!(!file.has_gaps());
+
+        // Each filter indexes the whole source on its own
+        let stats = file.info().map(|stats| stats.lines_indexed).collect::<Vec<_>>();
+        assert_eq!(stats, vec![harness.lines, harness.lines / 100, harness.lines - harness.lines / 1000]);
+    }
+
+    #[test]
+    fn test_multiple_filters_invalid_utf8() {
+        use indexed_file::{Log, LogStack};
+        use indexed_file::files::CursorLogFile;
+
+        // Each line starts with a byte that is not valid utf-8, which is longer once it is replaced
+        let data = (0..1000).flat_map(|i| [b"\xff".to_vec(), format!("{i:04}\n").into_bytes()].concat()).collect::<Vec<_>>();
+        let mut file = LogStack::new(Log::from(CursorLogFile::new(data)));
+        file.filter_regex("0$").unwrap();
+        file.filter_regex("!00$").unwrap();
+
+        let expected = (0..1000).filter(|i| i % 10 == 0 && i % 100 != 0).map(|i| i * 6).collect::<Vec<_>>();
+        let offsets = file.iter_lines().map(|line| line.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, expected);
+
+        let rev = file.iter_lines().rev().map(|line| line.offset).collect::<Vec<_>>();
+        assert_eq!(rev.into_iter().rev().collect::<Vec<_>>(), expected);
+    }
 }
@@ -0,0 +1,42 @@
+// Tests for finding lines by number in LogStack while the log is still being indexed
+
+#[cfg(test)]
+mod find_line_tests {
+    use indexed_file::files::{CursorLogFile, CursorUtil, Stream};
+    use indexed_file::{Log, LogStack};
+
+    const PATT_LEN: usize = 9;
+    const LINES: usize = 200000;
+
+    // Lines "10000000" to "10199999"
+    fn make_log() -> LogStack {
+        let buff = CursorLogFile::from_vec((10000000..10000000 + LINES).collect()).unwrap();
+        LogStack::new(Log::from(buff))
+    }
+
+    // Find the line, running the pending op until it finishes if it takes a while
+    fn find(log: &mut LogStack, number: usize) -> Option<usize> {
+        let mut found = log.find_line(number);
+        while found.is_none() && log.describe_pending() == "Find Line" {
+            found = log.run_pending(5);
+        }
+        found
+    }
+
+    #[test]
+    fn test_find_line() {
+        let mut log = make_log();
+        assert_eq!(find(&mut log, 1), Some(0));
+        assert_eq!(find(&mut log, 2), Some(PATT_LEN));
+        assert_eq!(find(&mut log, 150000), Some(149999 * PATT_LEN));
+        assert_eq!(find(&mut log, LINES), Some((LINES - 1) * PATT_LEN));
+        assert_eq!(find(&mut log, 1000), Some(999 * PATT_LEN));
+    }
+
+    #[test]
+    fn test_find_line_past_end() {
+        let mut log = make_log();
+        assert_eq!(find(&mut log, LINES + 1), Some(log.len()));
+        assert_eq!(find(&mut log, usize::MAX), Some(log.len()));
+    }
+}
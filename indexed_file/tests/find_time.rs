@@ -0,0 +1,125 @@
+// Tests for locating lines by timestamp without indexing the whole file
+
+#[cfg(test)]
+mod find_time_tests {
+    use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+    use indexed_file::files::{CursorLogFile, CursorUtil, LogBase, Stream};
+    use indexed_file::{IndexedLog, Log};
+
+    fn start() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(13, 0, 0).unwrap()
+    }
+
+    // A log with one line every two seconds. Every 10th line is followed by an untimestamped continuation line.
+    fn make_log(lines: usize) -> (Log, Vec<usize>) {
+        let mut text = Vec::new();
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        for i in 0..lines {
+            let time = start() + TimeDelta::seconds(2 * i as i64);
+            let line = format!("{} event {i}", time.format("%Y-%m-%dT%H:%M:%S%.3f"));
+            offsets.push(offset);
+            offset += line.len() + 1;
+            text.push(line);
+            if i % 10 == 0 {
+                let line = format!("    continued {i}");
+                offset += line.len() + 1;
+                text.push(line);
+            }
+        }
+        let text = text.iter().map(|s| s.as_str()).collect();
+        (Log::from(CursorLogFile::from_vec(text).unwrap().to_src()), offsets)
+    }
+
+    #[test]
+    fn test_find_time_exact() {
+        let (mut log, offsets) = make_log(5000);
+        for i in [0, 1, 10, 11, 2500, 4999] {
+            let time = start() + TimeDelta::seconds(2 * i as i64);
+            assert_eq!(log.find_time(time), Some(offsets[i]), "line {i}");
+        }
+    }
+
+    #[test]
+    fn test_find_time_between() {
+        let (mut log, offsets) = make_log(5000);
+
+        // Between two lines finds the later one, even when the earlier one has continuation lines
+        for i in [0, 10, 1234] {
+            let time = start() + TimeDelta::seconds(2 * i as i64 + 1);
+            assert_eq!(log.find_time(time), Some(offsets[i + 1]), "after line {i}");
+        }
+
+        // Before the first line
+        assert_eq!(log.find_time(start() - TimeDelta::hours(1)), Some(0));
+
+        // After the last line
+        assert_eq!(log.find_time(start() + TimeDelta::days(1)), None);
+    }
+
+    #[test]
+    fn test_find_time_unindexed() {
+        let (mut log, offsets) = make_log(5000);
+        let time = start() + TimeDelta::seconds(2 * 3000);
+        assert_eq!(log.find_time(time), Some(offsets[3000]));
+        assert_eq!(log.first_time(), Some(start()));
+
+        // We did not need to index the file to find it
+        assert!(log.len() > 0);
+        assert!(log.info().all(|stats| stats.bytes_indexed == 0));
+    }
+
+    #[test]
+    fn test_find_time_long_untimestamped_block() {
+        // A dump much longer than we read past looking for a timestamp, between two timestamped lines
+        let mut text = Vec::new();
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        for i in 0..200 {
+            let time = start() + TimeDelta::seconds(2 * i as i64);
+            let mut lines = vec![format!("{} event {i}", time.format("%Y-%m-%dT%H:%M:%S%.3f"))];
+            if i == 100 {
+                lines.extend((0..5000).map(|j| format!("    at frame {j}")));
+            }
+            offsets.push(offset);
+            for line in lines {
+                offset += line.len() + 1;
+                text.push(line);
+            }
+        }
+        let text = text.iter().map(|s| s.as_str()).collect();
+        let mut log = Log::from(CursorLogFile::from_vec(text).unwrap().to_src());
+
+        for i in [99, 100, 101, 150, 199] {
+            let time = start() + TimeDelta::seconds(2 * i as i64);
+            assert_eq!(log.find_time(time), Some(offsets[i]), "line {i}");
+        }
+        let time = start() + TimeDelta::seconds(2 * 100 + 1);
+        assert_eq!(log.find_time(time), Some(offsets[101]));
+    }
+
+    #[test]
+    fn test_find_time_no_timestamps() {
+        let mut log = Log::from(CursorLogFile::from_vec(vec!["no", "timestamps", "here"]).unwrap().to_src());
+        assert_eq!(log.first_time(), None);
+        assert_eq!(log.find_time(start()), None);
+    }
+
+    #[test]
+    fn test_find_time_invalid_utf8() {
+        // Every line has a byte that is not valid utf-8, which is longer once it is replaced
+        let mut data = Vec::new();
+        let mut offsets = Vec::new();
+        for i in 0..1000 {
+            let time = start() + TimeDelta::seconds(2 * i as i64);
+            offsets.push(data.len());
+            data.extend_from_slice(format!("{} event {i} ", time.format("%Y-%m-%dT%H:%M:%S")).as_bytes());
+            data.extend_from_slice(b"\xff\n");
+        }
+        let mut log = Log::from(CursorLogFile::new(data).to_src());
+        for i in [0, 1, 500, 999] {
+            let time = start() + TimeDelta::seconds(2 * i as i64);
+            assert_eq!(log.find_time(time), Some(offsets[i]), "line {i}");
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Tests for navigating between the matches of several searches in LogStack
+
+#[cfg(test)]
+mod search_tests {
+    use indexed_file::files::{CursorLogFile, CursorUtil};
+    use indexed_file::{Log, LogStack};
+
+    const PATT_LEN: usize = 9;
+
+    // Lines "10000000" to "10005999"
+    fn make_log() -> LogStack {
+        let buff = CursorLogFile::from_vec((10000000..10006000).collect()).unwrap();
+        LogStack::new(Log::from(buff))
+    }
+
+    fn offset(line: usize) -> usize {
+        line * PATT_LEN
+    }
+
+    // Search until we find something or give up; the search may time out before it finishes
+    fn find(log: &mut LogStack, forward: bool, count: usize, line: usize) -> Option<usize> {
+        let mut found = if forward {
+            log.search_next(count, offset(line))
+        } else {
+            log.search_next_back(count, offset(line))
+        };
+        while found.is_none() && log.describe_pending().starts_with("Search") {
+            found = log.run_pending(100);
+        }
+        found
+    }
+
+    #[test]
+    fn test_search_one() {
+        let mut log = make_log();
+        log.search_regex("000$").unwrap();
+        assert_eq!(find(&mut log, true, 1, 0), Some(offset(0)));
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(1000)));
+        assert_eq!(find(&mut log, true, 2, 1), Some(offset(2000)));
+        assert_eq!(find(&mut log, false, 1, 2000), Some(offset(1000)));
+        assert_eq!(find(&mut log, false, 1, 1000), Some(offset(0)));
+        assert_eq!(find(&mut log, false, 1, 0), None);
+        assert_eq!(find(&mut log, true, 1, 5001), None);
+    }
+
+    #[test]
+    fn test_search_all() {
+        let mut log = make_log();
+        log.add_search("000$").unwrap();
+        log.add_search("250$").unwrap();
+        log.add_search("000$").unwrap();
+        assert_eq!(log.searches().collect::<Vec<_>>(), vec!["000$", "250$"]);
+
+        // Matches of either search, in order
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(250)));
+        assert_eq!(find(&mut log, true, 2, 1), Some(offset(1000)));
+        assert_eq!(find(&mut log, true, 3, 1), Some(offset(1250)));
+        assert_eq!(find(&mut log, false, 1, 1000), Some(offset(250)));
+        assert_eq!(find(&mut log, false, 2, 1000), Some(offset(0)));
+        assert_eq!(find(&mut log, false, 1, 250), Some(offset(0)));
+    }
+
+    #[test]
+    fn test_search_target() {
+        let mut log = make_log();
+        log.add_search("000$").unwrap();
+        log.add_search("250$").unwrap();
+
+        log.set_search_target(Some("000$"));
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(1000)));
+        assert_eq!(find(&mut log, false, 1, 1000), Some(offset(0)));
+
+        log.set_search_target(Some("250$"));
+        assert_eq!(find(&mut log, true, 1, 251), Some(offset(1250)));
+        assert_eq!(find(&mut log, false, 1, 250), None);
+
+        // Removing the target goes back to finding all of them
+        log.remove_search("250$");
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(1000)));
+
+        log.set_search_target(None);
+        log.add_search("250$").unwrap();
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(250)));
+
+        // A new search expression replaces them all
+        log.search_regex("500$").unwrap();
+        assert_eq!(log.searches().count(), 1);
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(500)));
+    }
+
+    #[test]
+    fn test_search_filtered() {
+        let mut log = make_log();
+        log.add_search("000$").unwrap();
+        log.add_search("250$").unwrap();
+        log.filter_regex("!000$").unwrap();
+
+        // Lines hidden by the filter are not found
+        assert_eq!(find(&mut log, true, 1, 1), Some(offset(250)));
+        assert_eq!(find(&mut log, true, 2, 1), Some(offset(1250)));
+
+        log.set_search_target(Some("000$"));
+        assert_eq!(find(&mut log, true, 1, 0), None);
+    }
+
+    #[test]
+    fn test_search_all_invalid_utf8() {
+        // Each line starts with bytes that are not valid utf-8, which are longer than a line once they are replaced
+        let data = (0..1000).flat_map(|i| [vec![0xff; 8], format!("{i:04}\n").into_bytes()].concat()).collect::<Vec<_>>();
+        let mut log = LogStack::new(Log::from(CursorLogFile::new(data)));
+        log.add_search("0$").unwrap();
+        log.add_search("1$").unwrap();
+
+        // Matches of both searches are on neighboring lines
+        let line = |i: usize| i * 13;
+        let expected = [10, 11, 20, 21, 30];
+        for (count, i) in expected.into_iter().enumerate() {
+            assert_eq!(log.search_next(count + 1, line(2)), Some(line(i)), "forward {}", count + 1);
+        }
+        assert_eq!(log.search_next_back(1, line(21)), Some(line(20)));
+    }
+
+    #[test]
+    fn test_fill_filters_and_searches_together() {
+        use indexed_file::{IndexedLog, LineIndexerDataIterator};
+
+        let mut log = make_log();
+        log.filter_regex("[05]$").unwrap();
+        log.filter_regex("!00$").unwrap();
+        log.add_search("5$").unwrap();
+        while log.has_pending() {
+            log.run_pending(100);
+        }
+        assert!(!log.has_gaps());
+        assert_eq!(log.filter_stats(0).unwrap().lines_indexed, 1200);
+        assert_eq!(log.filter_stats(1).unwrap().lines_indexed, 5940);
+        assert_eq!(log.search_stats("5$").unwrap().lines_indexed, 600);
+
+        let shown = LineIndexerDataIterator::new(&mut log).map(|line| line.offset).collect::<Vec<_>>();
+        let expected = (0..6000).filter(|line| line % 5 == 0 && line % 100 != 0).map(offset).collect::<Vec<_>>();
+        assert_eq!(shown, expected);
+        assert_eq!(find(&mut log, true, 1, 10), Some(offset(15)));
+    }
+
+    #[test]
+    fn test_fill_filters_and_searches_together_invalid_utf8() {
+        use indexed_file::{IndexedLog, LineIndexerDataIterator};
+
+        // Each line starts with bytes that are not valid utf-8, which are longer than a line once they are replaced
+        let data = (0..1000).flat_map(|i| [vec![0xff; 8], format!("{i:04}\n").into_bytes()].concat()).collect::<Vec<_>>();
+        let mut log = LogStack::new(Log::from(CursorLogFile::new(data)));
+        log.filter_regex("[01]$").unwrap();
+        log.filter_regex("!00$").unwrap();
+        log.add_search("1$").unwrap();
+        while log.has_pending() {
+            log.run_pending(100);
+        }
+        assert!(!log.has_gaps());
+
+        let line = |i: usize| i * 13;
+        let shown = LineIndexerDataIterator::new(&mut log).map(|line| line.offset).collect::<Vec<_>>();
+        let expected = (0..1000).filter(|i| i % 10 < 2 && i % 100 != 0).map(line).collect::<Vec<_>>();
+        assert_eq!(shown, expected);
+        assert_eq!(log.search_next(2, line(2)), Some(line(21)));
+    }
+
+    #[test]
+    fn test_filter_big_file_in_background() {
+        use indexed_file::files::TextLogFile;
+        use indexed_file::IndexedLog;
+        use std::io::Write;
+
+        // Lines "00000000" to "01399999", about 12MB
+        let path = std::env::temp_dir().join(format!("grok-{}-filter-big", std::process::id()));
+        let lines = 1_400_000;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        for line in 0..lines {
+            writeln!(writer, "{:08}", line).unwrap();
+        }
+        drop(writer);
+
+        let mut log = LogStack::new(Log::from(TextLogFile::from_path(&path).unwrap()));
+        log.filter_regex("[05]$").unwrap();
+        log.filter_regex("!00$").unwrap();
+        log.add_search("5$").unwrap();
+        while log.has_pending() {
+            log.run_pending(100);
+        }
+        assert!(!log.has_gaps());
+        assert_eq!(log.filter_stats(0).unwrap().lines_indexed, lines / 5);
+        assert_eq!(log.filter_stats(1).unwrap().lines_indexed, lines - lines / 100);
+        assert_eq!(log.search_stats("5$").unwrap().lines_indexed, lines / 10);
+
+        assert_eq!(find(&mut log, true, 1, 1_000_000), Some(offset(1_000_005)));
+        assert_eq!(find(&mut log, false, 2, 1_000_000), Some(offset(999_985)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+// Tests for hiding lines outside a time window in LogStack
+
+#[cfg(test)]
+mod time_filter_tests {
+    use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+    use indexed_file::files::{CursorLogFile, CursorUtil, LogBase};
+    use indexed_file::{IndexedLog, Log, LogStack};
+
+    fn at(sec: usize) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(13, 0, 0).unwrap() + TimeDelta::seconds(sec as i64)
+    }
+
+    // A log with one line per second. Every 10th line is followed by an untimestamped continuation line.
+    fn make_log(lines: usize) -> LogStack {
+        let mut text = Vec::new();
+        for i in 0..lines {
+            text.push(format!("{} event {i}", at(i).format("%Y-%m-%d %H:%M:%S")));
+            if i % 10 == 0 {
+                text.push(format!("    continued {i}"));
+            }
+        }
+        let text = text.iter().map(|s| s.as_str()).collect();
+        LogStack::new(Log::from(CursorLogFile::from_vec(text).unwrap().to_src()))
+    }
+
+    fn events(log: &mut LogStack) -> Vec<String> {
+        log.iter_lines().map(|line| line.line.trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn test_time_window() {
+        let mut log = make_log(5000);
+        log.filter_time(Some(at(1000)), Some(at(1600)));
+
+        let lines = events(&mut log);
+        assert_eq!(lines.len(), 600 + 60);
+        assert!(lines[0].ends_with("event 1000"));
+        assert_eq!(lines[1], "    continued 1000");
+        assert!(lines.last().unwrap().ends_with("event 1599"));
+
+        let rev = log.iter_lines().rev().map(|line| line.line.trim_end().to_string()).collect::<Vec<_>>();
+        assert_eq!(rev.into_iter().rev().collect::<Vec<_>>(), lines);
+    }
+
+    #[test]
+    fn test_time_window_open_ended() {
+        let mut log = make_log(5000);
+        log.filter_time(Some(at(4990)), None);
+        let lines = events(&mut log);
+        assert_eq!(lines.len(), 10 + 1);
+        assert!(lines.last().unwrap().ends_with("event 4999"));
+
+        log.filter_time(None, Some(at(5)));
+        let lines = events(&mut log);
+        assert_eq!(lines.len(), 5 + 1);
+        assert!(lines[0].ends_with("event 0"));
+
+        // Clear the window
+        log.filter_time(None, None);
+        assert_eq!(events(&mut log).len(), 5000 + 500);
+    }
+
+    #[test]
+    fn test_time_window_empty() {
+        let mut log = make_log(1000);
+        log.filter_time(Some(at(2000)), None);
+        assert!(events(&mut log).is_empty());
+
+        log.filter_time(Some(at(500)), Some(at(500)));
+        assert!(events(&mut log).is_empty());
+    }
+
+    #[test]
+    fn test_time_window_with_regex() {
+        let mut log = make_log(5000);
+        log.filter_time(Some(at(1000)), Some(at(2000)));
+        log.filter_regex("event 1.05$").unwrap();
+
+        let lines = events(&mut log);
+        assert_eq!(lines.len(), 10);
+        assert!(lines[0].ends_with("event 1005"));
+        assert!(lines[9].ends_with("event 1905"));
+
+        let rev = log.iter_lines().rev().count();
+        assert_eq!(rev, 10);
+    }
+}
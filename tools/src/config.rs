@@ -49,11 +49,10 @@ impl Config {
 
         // Parse remaining args as input filenames
         for ostr in pargs.finish() {
-            if let Some(s) = ostr.to_str() {
-                if s.as_bytes().first() == Some(&b'-') {
-                    eprintln!("Error: Unknown argument: {:?}", ostr);
-                    std::process::exit(1);
-                }
+            if let Some(s) = ostr.to_str()
+                && s.as_bytes().first() == Some(&b'-') {
+                eprintln!("Error: Unknown argument: {:?}", ostr);
+                std::process::exit(1);
             }
             self.filename.push(PathBuf::from(ostr));
         }